@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::SystemTime,
+};
+
+use resvg::{tiny_skia::Pixmap, usvg};
+
+/// The default number of entries kept per cache store.
+pub const DEFAULT_CACHE_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Identifies a cached asset by its absolute path and last modification time.
+pub struct AssetKey {
+    /// The absolute path of the asset.
+    pub path: PathBuf,
+    /// The last modification time of the asset when it was cached.
+    pub modified: SystemTime,
+}
+
+impl AssetKey {
+    /// Creates a key for the asset at the specified path using its current modification time.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - The absolute path of the asset.
+    pub fn for_path(path: &Path) -> Result<Self, std::io::Error> {
+        let modified = std::fs::metadata(path)?.modified()?;
+
+        Ok(AssetKey {
+            path: path.to_path_buf(),
+            modified,
+        })
+    }
+}
+
+/// Identifies a cached asset resampled to a specific width and height.
+type ScaledAssetKey = (AssetKey, (u32, u32));
+
+/// Identifies a cached tree by its fragment file and a hash of the SVG source it was parsed from.
+type TreeKey = (AssetKey, u64);
+
+#[derive(Debug)]
+/// A bounded map that evicts the least recently used entry once full.
+struct LruMap<K, V> {
+    entries: HashMap<K, (Arc<V>, u64)>,
+    capacity: usize,
+    tick: u64,
+}
+
+impl<K: Eq + Hash + Clone, V> LruMap<K, V> {
+    fn new(capacity: usize) -> Self {
+        LruMap {
+            entries: HashMap::new(),
+            capacity,
+            tick: 0,
+        }
+    }
+
+    fn get(&mut self, key: &K) -> Option<Arc<V>> {
+        self.tick += 1;
+        let tick = self.tick;
+
+        self.entries.get_mut(key).map(|(value, last_used)| {
+            *last_used = tick;
+            value.clone()
+        })
+    }
+
+    fn insert(&mut self, key: K, value: Arc<V>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if !self.entries.contains_key(&key) && self.entries.len() >= self.capacity {
+            let oldest = self
+                .entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+
+            if let Some(oldest) = oldest {
+                self.entries.remove(&oldest);
+            }
+        }
+
+        self.tick += 1;
+        self.entries.insert(key, (value, self.tick));
+    }
+
+    fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Looks up an entry in a cache store, loading and inserting it on a miss.
+///
+/// The store is not locked while loading so that concurrent renders are never blocked on I/O.
+fn get_or_try_insert<K: Eq + Hash + Clone, V, E>(
+    store: &Mutex<LruMap<K, V>>,
+    key: K,
+    load: impl FnOnce() -> Result<V, E>,
+) -> Result<Arc<V>, E> {
+    if let Some(value) = store.lock().unwrap_or_else(|e| e.into_inner()).get(&key) {
+        return Ok(value);
+    }
+
+    let value = Arc::new(load()?);
+    store
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(key, value.clone());
+
+    Ok(value)
+}
+
+/// Returns the number of entries in a cache store.
+fn store_len<K: Eq + Hash + Clone, V>(store: &Mutex<LruMap<K, V>>) -> usize {
+    store.lock().unwrap_or_else(|e| e.into_inner()).len()
+}
+
+#[derive(Debug)]
+/// A shared, bounded cache for template assets that can be reused across renders.
+///
+/// Entries are keyed by absolute asset path and modification time, so edited assets are picked up
+/// automatically. Parsed trees depend on the usvg options and resource policy they were parsed with,
+/// so a cache should only be shared between renderers using the same options and policy.
+pub struct RenderCache {
+    /// Fragment SVG source text.
+    sources: Mutex<LruMap<AssetKey, String>>,
+    /// Decoded static base images (default, translucent and mask).
+    images: Mutex<LruMap<AssetKey, Pixmap>>,
+    /// Static base images resampled to a specific size.
+    scaled_images: Mutex<LruMap<ScaledAssetKey, Pixmap>>,
+    /// Parsed trees for fragments that have no placeholders.
+    trees: Mutex<LruMap<TreeKey, usvg::Tree>>,
+}
+
+impl RenderCache {
+    /// Creates a new cache.
+    ///
+    /// # Arguments
+    ///
+    /// - `capacity` - The maximum number of entries kept per asset kind.
+    pub fn new(capacity: usize) -> Self {
+        RenderCache {
+            sources: Mutex::new(LruMap::new(capacity)),
+            images: Mutex::new(LruMap::new(capacity)),
+            scaled_images: Mutex::new(LruMap::new(capacity)),
+            trees: Mutex::new(LruMap::new(capacity)),
+        }
+    }
+
+    /// Returns the cached source text of a fragment, loading it on a miss.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - The absolute path of the fragment file.
+    /// - `load` - Loads the source text if it is not cached.
+    pub fn fragment_source<E: From<std::io::Error>>(
+        &self,
+        path: &Path,
+        load: impl FnOnce() -> Result<String, E>,
+    ) -> Result<Arc<String>, E> {
+        get_or_try_insert(&self.sources, AssetKey::for_path(path)?, load)
+    }
+
+    /// Returns a cached decoded image as a premultiplied pixmap, loading it on a miss.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - The absolute path of the image file.
+    /// - `load` - Decodes the image if it is not cached.
+    pub fn image<E: From<std::io::Error>>(
+        &self,
+        path: &Path,
+        load: impl FnOnce() -> Result<Pixmap, E>,
+    ) -> Result<Arc<Pixmap>, E> {
+        get_or_try_insert(&self.images, AssetKey::for_path(path)?, load)
+    }
+
+    /// Returns a cached image resampled to the specified size, resampling it on a miss.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - The absolute path of the image file.
+    /// - `size` - The size the image was resampled to.
+    /// - `load` - Decodes and resamples the image if it is not cached.
+    pub fn scaled_image<E: From<std::io::Error>>(
+        &self,
+        path: &Path,
+        size: (u32, u32),
+        load: impl FnOnce() -> Result<Pixmap, E>,
+    ) -> Result<Arc<Pixmap>, E> {
+        get_or_try_insert(&self.scaled_images, (AssetKey::for_path(path)?, size), load)
+    }
+
+    /// Returns a cached resampled image without loading it on a miss.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - The absolute path of the image file.
+    /// - `size` - The size the image was resampled to.
+    pub fn cached_scaled_image(&self, path: &Path, size: (u32, u32)) -> Result<Option<Arc<Pixmap>>, std::io::Error> {
+        let key = (AssetKey::for_path(path)?, size);
+        Ok(self.scaled_images.lock().unwrap_or_else(|e| e.into_inner()).get(&key))
+    }
+
+    /// Returns a cached parsed tree, parsing it on a miss. Trees are keyed by the SVG source
+    /// they are parsed from as well as the fragment file, so sources with substituted variables
+    /// are not served a tree parsed with other values.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - The absolute path of the fragment file.
+    /// - `svg_code` - The SVG source the tree is parsed from.
+    /// - `load` - Parses the tree if it is not cached.
+    pub fn tree<E: From<std::io::Error>>(
+        &self,
+        path: &Path,
+        svg_code: &str,
+        load: impl FnOnce() -> Result<usvg::Tree, E>,
+    ) -> Result<Arc<usvg::Tree>, E> {
+        let mut hasher = DefaultHasher::new();
+        svg_code.hash(&mut hasher);
+
+        get_or_try_insert(&self.trees, (AssetKey::for_path(path)?, hasher.finish()), load)
+    }
+
+    /// Returns the total number of cached entries across all asset kinds.
+    pub fn len(&self) -> usize {
+        store_len(&self.sources)
+            + store_len(&self.images)
+            + store_len(&self.scaled_images)
+            + store_len(&self.trees)
+    }
+
+    /// Returns whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Removes all cached entries.
+    pub fn clear(&self) {
+        self.sources.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.images.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.scaled_images.lock().unwrap_or_else(|e| e.into_inner()).clear();
+        self.trees.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+}
+
+impl Default for RenderCache {
+    fn default() -> Self {
+        RenderCache::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, time::Duration};
+
+    use super::*;
+    use crate::test_support::TempDir;
+
+    #[test]
+    fn evicts_least_recently_used_entry() {
+        let mut map = LruMap::new(2);
+        map.insert("a", Arc::new(1));
+        map.insert("b", Arc::new(2));
+
+        // Using "a" makes "b" the least recently used entry.
+        assert_eq!(map.get(&"a").as_deref(), Some(&1));
+        map.insert("c", Arc::new(3));
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&"b"), None);
+        assert_eq!(map.get(&"a").as_deref(), Some(&1));
+        assert_eq!(map.get(&"c").as_deref(), Some(&3));
+
+        // Replacing an entry does not evict another.
+        map.insert("c", Arc::new(4));
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&"c").as_deref(), Some(&4));
+    }
+
+    #[test]
+    fn does_not_store_entries_without_capacity() {
+        let mut map = LruMap::new(0);
+        map.insert("a", Arc::new(1));
+
+        assert_eq!(map.len(), 0);
+    }
+
+    #[test]
+    fn invalidates_entries_of_modified_assets() {
+        let dir = TempDir::new("cache-modified");
+        let path = dir.write("a.svg", "<svg/>");
+
+        let cache = RenderCache::default();
+        let source = |value: &str| {
+            cache
+                .fragment_source(&path, || Ok::<_, std::io::Error>(value.to_string()))
+                .unwrap()
+        };
+
+        assert_eq!(*source("first"), "first");
+        assert_eq!(*source("second"), "first");
+
+        let modified = fs::metadata(&path).unwrap().modified().unwrap() + Duration::from_secs(60);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(modified).unwrap();
+
+        assert_eq!(*source("third"), "third");
+        assert_eq!(*source("fourth"), "third");
+    }
+
+    #[test]
+    fn looks_up_scaled_images_without_loading() {
+        let dir = TempDir::new("cache-scaled");
+        let path = dir.write("a.png", "");
+
+        let cache = RenderCache::default();
+        assert!(cache.cached_scaled_image(&path, (2, 2)).unwrap().is_none());
+
+        cache
+            .scaled_image(&path, (2, 2), || Ok::<_, std::io::Error>(Pixmap::new(2, 2).unwrap()))
+            .unwrap();
+        assert!(cache.cached_scaled_image(&path, (2, 2)).unwrap().is_some());
+        assert!(cache.cached_scaled_image(&path, (4, 4)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,543 @@
+use std::fmt::Display;
+
+/// The deepest that parentheses, negations and function calls can be nested, which keeps the
+/// recursive parser from overflowing the stack.
+const MAX_NESTING_DEPTH: usize = 64;
+
+/// The most operators an expression can contain. Chains of operators build trees as deep as the
+/// chain is long, which are evaluated recursively, so they are limited like nesting.
+const MAX_OPERATORS: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Errors caused by malformed expressions.
+pub enum ExpressionError {
+    /// A character that cannot start a token.
+    UnexpectedCharacter { position: usize, character: char },
+    /// The expression ends where a value or closing parenthesis is expected.
+    UnexpectedEnd,
+    /// A placeholder reference is not closed with `}`.
+    UnterminatedPlaceholder { position: usize },
+    /// A number that cannot be parsed.
+    InvalidNumber { position: usize, number: String },
+    /// A function that does not exist.
+    UnknownFunction { position: usize, name: String },
+    /// A function is called with the wrong number of arguments.
+    WrongArgumentCount { position: usize, name: String, expected: String, found: usize },
+    /// Parentheses, negations and function calls are nested too deeply.
+    TooDeeplyNested { position: usize },
+    /// The expression contains too many operators.
+    TooManyOperators { position: usize },
+}
+
+impl Display for ExpressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpressionError::UnexpectedCharacter { position, character } => {
+                write!(f, "Unexpected '{character}' at character {position}.")
+            }
+            ExpressionError::UnexpectedEnd => write!(f, "The expression ends unexpectedly."),
+            ExpressionError::UnterminatedPlaceholder { position } => {
+                write!(f, "The placeholder at character {position} is not closed with '}}'.")
+            }
+            ExpressionError::InvalidNumber { position, number } => {
+                write!(f, "Invalid number '{number}' at character {position}.")
+            }
+            ExpressionError::UnknownFunction { position, name } => {
+                write!(f, "Unknown function '{name}' at character {position}.")
+            }
+            ExpressionError::WrongArgumentCount { position, name, expected, found } => write!(
+                f,
+                "The function '{name}' at character {position} takes {expected} arguments, but {found} were given."
+            ),
+            ExpressionError::TooDeeplyNested { position } => write!(
+                f,
+                "The expression is nested more than {MAX_NESTING_DEPTH} levels deep at character {position}."
+            ),
+            ExpressionError::TooManyOperators { position } => write!(
+                f,
+                "The expression contains more than {MAX_OPERATORS} operators at character {position}."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExpressionError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// An arithmetic operator.
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    /// Division. Dividing by zero divides by one instead.
+    Divide,
+    /// The remainder of a division. Like division, a divisor of zero is treated as one.
+    Remainder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A function that can be called in expressions.
+pub enum Function {
+    /// Rounds to the nearest integer, or to a number of decimal places.
+    Round,
+    /// Rounds down.
+    Floor,
+    /// Rounds up.
+    Ceil,
+    /// The absolute value.
+    Abs,
+    /// The smallest argument.
+    Min,
+    /// The largest argument.
+    Max,
+    /// Limits a value to a range.
+    Clamp,
+}
+
+impl Function {
+    /// Returns the function with a name.
+    ///
+    /// # Arguments
+    ///
+    /// - `name` - The name of the function.
+    fn from_name(name: &str) -> Option<Function> {
+        match name {
+            "round" => Some(Function::Round),
+            "floor" => Some(Function::Floor),
+            "ceil" => Some(Function::Ceil),
+            "abs" => Some(Function::Abs),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "clamp" => Some(Function::Clamp),
+            _ => None,
+        }
+    }
+
+    /// Returns the smallest and largest number of arguments the function takes.
+    fn arity(&self) -> (usize, usize) {
+        match self {
+            Function::Round => (1, 2),
+            Function::Floor | Function::Ceil | Function::Abs => (1, 1),
+            Function::Min | Function::Max => (1, usize::MAX),
+            Function::Clamp => (3, 3),
+        }
+    }
+
+    /// Applies the function to its arguments.
+    ///
+    /// # Arguments
+    ///
+    /// - `args` - The arguments, which match the arity of the function.
+    fn apply(&self, args: &[f64]) -> f64 {
+        match self {
+            Function::Round => {
+                let factor = 10f64.powi(args.get(1).copied().unwrap_or(0.0) as i32);
+                (args[0] * factor).round() / factor
+            }
+            Function::Floor => args[0].floor(),
+            Function::Ceil => args[0].ceil(),
+            Function::Abs => args[0].abs(),
+            Function::Min => args.iter().copied().fold(f64::INFINITY, f64::min),
+            Function::Max => args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            // Unlike `f64::clamp`, an inverted range does not panic but returns the lower bound.
+            Function::Clamp => args[0].min(args[2]).max(args[1]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// An arithmetic expression over placeholder values, such as `{kills#text} / {deaths#text}`.
+pub enum Expression {
+    /// A number literal.
+    Number(f64),
+    /// The numeric value of a placeholder.
+    Placeholder(String),
+    /// The negation of an expression.
+    Negate(Box<Expression>),
+    /// An operator applied to two expressions.
+    Binary(Operator, Box<Expression>, Box<Expression>),
+    /// A function call.
+    Call(Function, Vec<Expression>),
+}
+
+impl Expression {
+    /// Parses an expression. Expressions consist of numbers, placeholders in braces, the
+    /// operators `+`, `-`, `*`, `/` and `%`, parentheses and the functions `round`, `floor`,
+    /// `ceil`, `abs`, `min`, `max` and `clamp`.
+    ///
+    /// # Arguments
+    ///
+    /// - `source` - The expression to parse.
+    pub fn parse(source: &str) -> Result<Expression, ExpressionError> {
+        let mut parser = Parser {
+            chars: source.chars().collect(),
+            position: 0,
+            depth: 0,
+            operators: 0,
+        };
+
+        let expression = parser.sum()?;
+
+        match parser.peek() {
+            Some(character) => Err(ExpressionError::UnexpectedCharacter {
+                position: parser.position,
+                character,
+            }),
+            None => Ok(expression),
+        }
+    }
+
+    /// Returns the names of the placeholders the expression references.
+    pub fn placeholders(&self) -> Vec<&str> {
+        match self {
+            Expression::Number(_) => vec![],
+            Expression::Placeholder(name) => vec![name],
+            Expression::Negate(expression) => expression.placeholders(),
+            Expression::Binary(_, left, right) => {
+                let mut placeholders = left.placeholders();
+                placeholders.extend(right.placeholders());
+                placeholders
+            }
+            Expression::Call(_, args) => args.iter().flat_map(Expression::placeholders).collect(),
+        }
+    }
+
+    /// Evaluates the expression. Division and remainders by zero divide by one instead, so
+    /// ratios such as kills to deaths are defined for zero deaths.
+    ///
+    /// # Arguments
+    ///
+    /// - `value` - Returns the numeric value of a placeholder.
+    ///
+    /// Returns `None` if a placeholder has no numeric value or the result is not finite.
+    pub fn evaluate(&self, value: &impl Fn(&str) -> Option<f64>) -> Option<f64> {
+        let result = match self {
+            Expression::Number(n) => *n,
+            Expression::Placeholder(name) => value(name)?,
+            Expression::Negate(expression) => -expression.evaluate(value)?,
+            Expression::Binary(operator, left, right) => {
+                let left = left.evaluate(value)?;
+                let right = right.evaluate(value)?;
+                let divisor = if right == 0.0 { 1.0 } else { right };
+
+                match operator {
+                    Operator::Add => left + right,
+                    Operator::Subtract => left - right,
+                    Operator::Multiply => left * right,
+                    Operator::Divide => left / divisor,
+                    Operator::Remainder => left % divisor,
+                }
+            }
+            Expression::Call(function, args) => {
+                let args: Vec<f64> = args.iter().map(|arg| arg.evaluate(value)).collect::<Option<_>>()?;
+                function.apply(&args)
+            }
+        };
+
+        result.is_finite().then_some(result)
+    }
+}
+
+/// A recursive descent parser for expressions.
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    /// How deeply the current value is nested in parentheses, negations and function calls.
+    depth: usize,
+    /// The number of binary operators parsed so far.
+    operators: usize,
+}
+
+impl Parser {
+    /// Skips whitespace and returns the next character without consuming it.
+    fn peek(&mut self) -> Option<char> {
+        while self.chars.get(self.position).is_some_and(|c| c.is_whitespace()) {
+            self.position += 1;
+        }
+
+        self.chars.get(self.position).copied()
+    }
+
+    /// Consumes the next character if it is the expected one.
+    ///
+    /// # Arguments
+    ///
+    /// - `expected` - The expected character.
+    fn consume(&mut self, expected: char) -> bool {
+        let matches = self.peek() == Some(expected);
+        if matches {
+            self.position += 1;
+        }
+        matches
+    }
+
+    /// Consumes the expected character or fails.
+    ///
+    /// # Arguments
+    ///
+    /// - `expected` - The expected character.
+    fn expect(&mut self, expected: char) -> Result<(), ExpressionError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.position += 1;
+                Ok(())
+            }
+            Some(character) => Err(ExpressionError::UnexpectedCharacter {
+                position: self.position,
+                character,
+            }),
+            None => Err(ExpressionError::UnexpectedEnd),
+        }
+    }
+
+    /// Consumes a binary operator and counts it towards the operator limit.
+    fn count_operator(&mut self) -> Result<(), ExpressionError> {
+        if self.operators >= MAX_OPERATORS {
+            return Err(ExpressionError::TooManyOperators { position: self.position });
+        }
+
+        self.operators += 1;
+        self.position += 1;
+        Ok(())
+    }
+
+    /// Parses additions and subtractions.
+    fn sum(&mut self) -> Result<Expression, ExpressionError> {
+        let mut expression = self.product()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some('+') => Operator::Add,
+                Some('-') => Operator::Subtract,
+                _ => return Ok(expression),
+            };
+            self.count_operator()?;
+
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(self.product()?));
+        }
+    }
+
+    /// Parses multiplications, divisions and remainders.
+    fn product(&mut self) -> Result<Expression, ExpressionError> {
+        let mut expression = self.unary()?;
+
+        loop {
+            let operator = match self.peek() {
+                Some('*') => Operator::Multiply,
+                Some('/') => Operator::Divide,
+                Some('%') => Operator::Remainder,
+                _ => return Ok(expression),
+            };
+            self.count_operator()?;
+
+            expression = Expression::Binary(operator, Box::new(expression), Box::new(self.unary()?));
+        }
+    }
+
+    /// Parses negations. Every nested value is parsed through here, so this limits the nesting depth.
+    fn unary(&mut self) -> Result<Expression, ExpressionError> {
+        if self.depth >= MAX_NESTING_DEPTH {
+            return Err(ExpressionError::TooDeeplyNested { position: self.position });
+        }
+
+        self.depth += 1;
+        let expression = match self.consume('-') {
+            true => self.unary().map(|expression| Expression::Negate(Box::new(expression))),
+            false => self.primary(),
+        };
+        self.depth -= 1;
+
+        expression
+    }
+
+    /// Parses numbers, placeholders, function calls and parenthesized expressions.
+    fn primary(&mut self) -> Result<Expression, ExpressionError> {
+        let next = self.peek();
+        let start = self.position;
+
+        match next {
+            None => Err(ExpressionError::UnexpectedEnd),
+            Some('(') => {
+                self.position += 1;
+                let expression = self.sum()?;
+                self.expect(')')?;
+                Ok(expression)
+            }
+            Some('{') => {
+                self.position += 1;
+
+                let Some(length) = self.chars[self.position..].iter().position(|c| *c == '}') else {
+                    return Err(ExpressionError::UnterminatedPlaceholder { position: start });
+                };
+
+                let name: String = self.chars[self.position..self.position + length].iter().collect();
+                self.position += length + 1;
+                Ok(Expression::Placeholder(name.trim().to_string()))
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let number = self.take_while(|c| c.is_ascii_digit() || c == '.');
+
+                number
+                    .parse()
+                    .map(Expression::Number)
+                    .map_err(|_| ExpressionError::InvalidNumber { position: start, number })
+            }
+            Some(c) if c.is_ascii_alphabetic() => {
+                let name = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_');
+
+                let Some(function) = Function::from_name(&name) else {
+                    return Err(ExpressionError::UnknownFunction { position: start, name });
+                };
+
+                self.expect('(')?;
+                let mut args = vec![];
+                if !self.consume(')') {
+                    loop {
+                        args.push(self.sum()?);
+                        if self.consume(')') {
+                            break;
+                        }
+                        self.expect(',')?;
+                    }
+                }
+
+                let (min, max) = function.arity();
+                if args.len() < min || args.len() > max {
+                    let expected = match (min, max) {
+                        (min, max) if min == max => min.to_string(),
+                        (min, usize::MAX) => format!("at least {min}"),
+                        (min, max) => format!("{min} to {max}"),
+                    };
+
+                    return Err(ExpressionError::WrongArgumentCount {
+                        position: start,
+                        name,
+                        expected,
+                        found: args.len(),
+                    });
+                }
+
+                Ok(Expression::Call(function, args))
+            }
+            Some(character) => Err(ExpressionError::UnexpectedCharacter { position: start, character }),
+        }
+    }
+
+    /// Consumes characters while they match a predicate and returns them.
+    ///
+    /// # Arguments
+    ///
+    /// - `predicate` - Whether a character is consumed.
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.position;
+        while self.chars.get(self.position).is_some_and(|c| predicate(*c)) {
+            self.position += 1;
+        }
+
+        self.chars[start..self.position].iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn evaluate(source: &str) -> Option<f64> {
+        let values = |name: &str| match name {
+            "kills#text" => Some(12.0),
+            "deaths#text" => Some(0.0),
+            _ => None,
+        };
+
+        Expression::parse(source).expect("The expression is valid.").evaluate(&values)
+    }
+
+    #[test]
+    fn evaluates_operators_and_functions() {
+        assert_eq!(evaluate("1 + 2 * 3 - -4"), Some(11.0));
+        assert_eq!(evaluate("(1 + 2) * 3 % 4"), Some(1.0));
+        assert_eq!(evaluate("round({kills#text} / 5, 1)"), Some(2.4));
+        assert_eq!(evaluate("clamp(max(1, 2, 3), 0, min(2.5, 4))"), Some(2.5));
+        assert_eq!(evaluate("{missing#text} + 1"), None);
+    }
+
+    #[test]
+    fn divides_by_one_instead_of_zero() {
+        assert_eq!(evaluate("{kills#text} / {deaths#text}"), Some(12.0));
+        assert_eq!(evaluate("7 % 0"), Some(0.0));
+        assert_eq!(evaluate("1 / (2 - 2)"), Some(1.0));
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        let error = |source: &str| Expression::parse(source).expect_err("The expression is invalid.");
+
+        assert_eq!(error("1 +"), ExpressionError::UnexpectedEnd);
+        assert_eq!(error("(1 + 2"), ExpressionError::UnexpectedEnd);
+        assert_eq!(error("1 + 2)"), ExpressionError::UnexpectedCharacter { position: 5, character: ')' });
+        assert_eq!(error("2 ^ 3"), ExpressionError::UnexpectedCharacter { position: 2, character: '^' });
+        assert_eq!(error("1 + {kills#text"), ExpressionError::UnterminatedPlaceholder { position: 4 });
+        assert_eq!(
+            error("1.2.3"),
+            ExpressionError::InvalidNumber { position: 0, number: "1.2.3".to_string() }
+        );
+        assert_eq!(
+            error("sqrt(4)"),
+            ExpressionError::UnknownFunction { position: 0, name: "sqrt".to_string() }
+        );
+        assert_eq!(
+            error("abs(1, 2)"),
+            ExpressionError::WrongArgumentCount {
+                position: 0,
+                name: "abs".to_string(),
+                expected: "1".to_string(),
+                found: 2,
+            }
+        );
+        assert_eq!(
+            error("min()"),
+            ExpressionError::WrongArgumentCount {
+                position: 0,
+                name: "min".to_string(),
+                expected: "at least 1".to_string(),
+                found: 0,
+            }
+        );
+    }
+
+    #[test]
+    fn limits_nesting_depth() {
+        let nested =
+            |depth: usize, open: &str, close: &str| format!("{}1{}", open.repeat(depth), close.repeat(depth));
+
+        assert_eq!(evaluate(&nested(MAX_NESTING_DEPTH - 1, "(", ")")), Some(1.0));
+        assert_eq!(evaluate(&nested(MAX_NESTING_DEPTH - 1, "-", "")), Some(-1.0));
+        assert_eq!(evaluate(&nested(MAX_NESTING_DEPTH - 1, "abs(", ")")), Some(1.0));
+
+        for (open, close) in [("(", ")"), ("-", ""), ("abs(", ")")] {
+            let source = nested(100_000, open, close);
+
+            assert!(
+                matches!(Expression::parse(&source), Err(ExpressionError::TooDeeplyNested { .. })),
+                "{open}1{close} is nested too deeply"
+            );
+        }
+    }
+
+    #[test]
+    fn limits_operator_count() {
+        let chain = |operators: usize, operator: &str| vec!["1"; operators + 1].join(operator);
+
+        assert_eq!(evaluate(&chain(MAX_OPERATORS, " + ")), Some((MAX_OPERATORS + 1) as f64));
+        assert_eq!(evaluate(&chain(MAX_OPERATORS, " * ")), Some(1.0));
+
+        for operator in ["+", "*", "-", "/"] {
+            let source = chain(1_000_000, operator);
+
+            assert!(
+                matches!(Expression::parse(&source), Err(ExpressionError::TooManyOperators { .. })),
+                "A chain of {operator} is too long"
+            );
+        }
+    }
+}
@@ -0,0 +1,502 @@
+use std::{collections::HashMap, fmt::Display};
+
+use resvg::usvg::fontdb;
+use serde::Deserialize;
+
+use crate::placeholders::{FontSize, PlaceholderError, TextSpan, is_valid_color};
+
+/// The default colors of the color codes `0` to `f`.
+const DEFAULT_PALETTE: [(char, &str); 16] = [
+    ('0', "#000000"),
+    ('1', "#0000AA"),
+    ('2', "#00AA00"),
+    ('3', "#00AAAA"),
+    ('4', "#AA0000"),
+    ('5', "#AA00AA"),
+    ('6', "#FFAA00"),
+    ('7', "#AAAAAA"),
+    ('8', "#555555"),
+    ('9', "#5555FF"),
+    ('a', "#55FF55"),
+    ('b', "#55FFFF"),
+    ('c', "#FF5555"),
+    ('d', "#FF55FF"),
+    ('e', "#FFFF55"),
+    ('f', "#FFFFFF"),
+];
+
+#[derive(Deserialize, Debug, Clone)]
+/// Text with legacy Minecraft formatting codes, such as `§6[MVP§c++§6] Name`. Codes start with
+/// `§` or `&` and are followed by a color (`0`-`f`) or style code: `l` (bold), `o` (italic),
+/// `n` (underline), `m` (strikethrough), `k` (obfuscated, ignored) and `r` (reset). Colors reset
+/// the styles, as they do in the game.
+pub struct MinecraftText {
+    /// The text with formatting codes.
+    pub minecraft: String,
+    /// Optional: Colors that replace the default colors of color codes, keyed by the code.
+    #[serde(default)]
+    pub palette: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Default)]
+/// The style of the text that follows formatting codes.
+struct Style {
+    color: Option<char>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strikethrough: bool,
+}
+
+impl MinecraftText {
+    /// Validates the palette. Keys must be color codes and values CSS colors.
+    ///
+    /// # Arguments
+    ///
+    /// - `placeholder` - The ID of the placeholder the text belongs to, used in errors.
+    pub fn validate(&self, placeholder: &str) -> Result<(), PlaceholderError> {
+        let mut codes: Vec<&String> = self.palette.keys().collect();
+        codes.sort();
+
+        for code in codes {
+            if code.len() != 1 || !code.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(PlaceholderError::InvalidColorCode {
+                    placeholder: placeholder.to_string(),
+                    code: code.clone(),
+                });
+            }
+
+            if !is_valid_color(&self.palette[code]) {
+                return Err(PlaceholderError::InvalidFill {
+                    placeholder: placeholder.to_string(),
+                    fill: self.palette[code].clone(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the color of a color code, preferring the palette of the text over the defaults.
+    ///
+    /// # Arguments
+    ///
+    /// - `code` - The lowercase color code.
+    fn color(&self, code: char) -> Option<String> {
+        let custom = self.palette.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&code.to_string()))
+            .map(|(_, color)| color.clone());
+
+        custom.or_else(|| {
+            DEFAULT_PALETTE.iter()
+                .find(|(key, _)| *key == code)
+                .map(|(_, color)| color.to_string())
+        })
+    }
+
+    /// Parses the formatting codes into text spans. Characters after `§` or `&` that are not
+    /// formatting codes are kept as text.
+    pub fn spans(&self) -> Vec<TextSpan> {
+        let mut spans: Vec<TextSpan> = vec![];
+        let mut style = Style::default();
+        let mut value = String::new();
+        let mut chars = self.minecraft.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let code = match (c, chars.peek()) {
+                ('§' | '&', Some(code)) if "0123456789abcdefklmnor".contains(code.to_ascii_lowercase()) => {
+                    code.to_ascii_lowercase()
+                }
+                _ => {
+                    value.push(c);
+                    continue;
+                }
+            };
+            chars.next();
+
+            if !value.is_empty() {
+                spans.push(self.span(&std::mem::take(&mut value), &style));
+            }
+
+            match code {
+                'l' => style.bold = true,
+                'o' => style.italic = true,
+                'n' => style.underline = true,
+                'm' => style.strikethrough = true,
+                'k' => {}
+                'r' => style = Style::default(),
+                color => {
+                    style = Style {
+                        color: Some(color),
+                        ..Style::default()
+                    }
+                }
+            }
+        }
+
+        if !value.is_empty() {
+            spans.push(self.span(&value, &style));
+        }
+
+        spans
+    }
+
+    /// Creates a text span with a style.
+    ///
+    /// # Arguments
+    ///
+    /// - `value` - The text value.
+    /// - `style` - The style of the text.
+    fn span(&self, value: &str, style: &Style) -> TextSpan {
+        let decorations: Vec<&str> = [(style.underline, "underline"), (style.strikethrough, "line-through")]
+            .into_iter()
+            .filter_map(|(enabled, decoration)| enabled.then_some(decoration))
+            .collect();
+
+        TextSpan {
+            fill: style.color.and_then(|code| self.color(code)),
+            font_weight: style.bold.then_some(700),
+            font_style: style.italic.then(|| "italic".to_string()),
+            text_decoration: (!decorations.is_empty()).then(|| decorations.join(" ")),
+            ..TextSpan::plain(value)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Errors caused by malformed markup.
+pub enum MarkupError {
+    /// A tag is not closed with `>`.
+    UnterminatedTag { position: usize },
+    /// A tag is not one of the supported tags.
+    UnknownTag { position: usize, tag: String },
+    /// A tag that requires an argument, such as `<c:#ff0>`, has none.
+    MissingArgument { position: usize, tag: String },
+    /// A tag that takes no argument, such as `<b>`, has one.
+    UnexpectedArgument { position: usize, tag: String },
+    /// A closing tag does not match the most recently opened tag.
+    MismatchedClosingTag { position: usize, expected: Option<String>, found: String },
+    /// A tag is not closed before the end of the markup.
+    UnclosedTag { tag: String },
+    /// The markup ends with an escape character.
+    DanglingEscape { position: usize },
+}
+
+impl Display for MarkupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarkupError::UnterminatedTag { position } => write!(f, "The tag at character {position} is not closed with '>'."),
+            MarkupError::UnknownTag { position, tag } => write!(f, "Unknown tag '{tag}' at character {position}."),
+            MarkupError::MissingArgument { position, tag } => {
+                write!(f, "The tag '{tag}' at character {position} requires an argument, such as <{tag}:value>.")
+            }
+            MarkupError::UnexpectedArgument { position, tag } => {
+                write!(f, "The tag '{tag}' at character {position} does not take an argument.")
+            }
+            MarkupError::MismatchedClosingTag { position, expected: Some(expected), found } => {
+                write!(f, "Expected </{expected}> at character {position}, but found </{found}>.")
+            }
+            MarkupError::MismatchedClosingTag { position, expected: None, found } => {
+                write!(f, "The closing tag </{found}> at character {position} has no opening tag.")
+            }
+            MarkupError::UnclosedTag { tag } => write!(f, "The tag '{tag}' is not closed."),
+            MarkupError::DanglingEscape { position } => {
+                write!(f, "The escape character at character {position} is not followed by a character.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MarkupError {}
+
+#[derive(Deserialize, Debug, Clone)]
+/// Text with markup tags that style parts of it, such as `<c:#ff0>Gold</c> <b>bold</b>`. The
+/// tags are `<c:color>`, `<size:font size>`, `<font:font family>`, `<b>` (bold), `<i>` (italic),
+/// `<u>` (underline) and `<s>` (strikethrough). Tags can be nested and must be closed in reverse
+/// order. A backslash escapes the next character, so `\<` is a literal `<`.
+pub struct MarkupText {
+    /// The text with markup tags.
+    pub markup: String,
+}
+
+impl MarkupText {
+    /// Validates the markup and the style attributes of the text spans it describes.
+    ///
+    /// # Arguments
+    ///
+    /// - `placeholder` - The ID of the placeholder the text belongs to, used in errors.
+    /// - `fontdb` - The font database that font families must be loaded in.
+    pub fn validate(&self, placeholder: &str, fontdb: &fontdb::Database) -> Result<(), PlaceholderError> {
+        let spans = self.parse().map_err(|error| PlaceholderError::InvalidMarkup {
+            placeholder: placeholder.to_string(),
+            error,
+        })?;
+
+        for span in spans {
+            span.validate(placeholder, fontdb)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the text spans described by the markup. Malformed markup is returned as plain
+    /// text, as it is rejected when the placeholder values are validated.
+    pub fn spans(&self) -> Vec<TextSpan> {
+        self.parse().unwrap_or_else(|_| vec![TextSpan::plain(&self.markup)])
+    }
+
+    /// Parses the markup into text spans.
+    pub fn parse(&self) -> Result<Vec<TextSpan>, MarkupError> {
+        let mut spans: Vec<TextSpan> = vec![];
+        // The open tags and the style of the text inside them.
+        let mut stack: Vec<(String, TextSpan)> = vec![];
+        let mut value = String::new();
+        let mut chars = self.markup.chars().enumerate();
+
+        let style = |stack: &[(String, TextSpan)]| {
+            stack.last().map_or_else(|| TextSpan::plain(""), |(_, span)| span.clone())
+        };
+
+        while let Some((position, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => value.push(escaped),
+                    None => return Err(MarkupError::DanglingEscape { position }),
+                },
+                '<' => {
+                    let mut tag = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '>')) => break,
+                            Some((_, c)) => tag.push(c),
+                            None => return Err(MarkupError::UnterminatedTag { position }),
+                        }
+                    }
+
+                    if !value.is_empty() {
+                        spans.push(style(&stack).with_value(&std::mem::take(&mut value)));
+                    }
+
+                    if let Some(name) = tag.strip_prefix('/') {
+                        match stack.pop() {
+                            Some((open, _)) if open == name => {}
+                            open => {
+                                return Err(MarkupError::MismatchedClosingTag {
+                                    position,
+                                    expected: open.map(|(open, _)| open),
+                                    found: name.to_string(),
+                                });
+                            }
+                        }
+                        continue;
+                    }
+
+                    let (name, argument) = match tag.split_once(':') {
+                        Some((name, argument)) => (name.to_string(), Some(argument.to_string())),
+                        None => (tag, None),
+                    };
+                    let span = Self::apply_tag(style(&stack), position, &name, argument)?;
+                    stack.push((name, span));
+                }
+                c => value.push(c),
+            }
+        }
+
+        if let Some((tag, _)) = stack.pop() {
+            return Err(MarkupError::UnclosedTag { tag });
+        }
+
+        if !value.is_empty() {
+            spans.push(TextSpan::plain(&value));
+        }
+
+        Ok(spans)
+    }
+
+    /// Returns the style of text inside a tag.
+    ///
+    /// # Arguments
+    ///
+    /// - `span` - The style of the text outside the tag.
+    /// - `position` - The position of the tag in the markup, used in errors.
+    /// - `name` - The name of the tag.
+    /// - `argument` - Optional: The argument of the tag.
+    fn apply_tag(span: TextSpan, position: usize, name: &str, argument: Option<String>) -> Result<TextSpan, MarkupError> {
+        let decorate = |span: TextSpan, decoration: &str| {
+            let decorations = match span.text_decoration {
+                Some(decorations) if decorations.split_whitespace().any(|d| d == decoration) => decorations,
+                Some(decorations) => format!("{decorations} {decoration}"),
+                None => decoration.to_string(),
+            };
+
+            TextSpan { text_decoration: Some(decorations), ..span }
+        };
+
+        match (name, argument) {
+            ("c" | "size" | "font", None) => Err(MarkupError::MissingArgument {
+                position,
+                tag: name.to_string(),
+            }),
+            ("c", Some(fill)) => Ok(TextSpan { fill: Some(fill), ..span }),
+            ("size", Some(font_size)) => Ok(TextSpan { font_size: Some(FontSize::String(font_size)), ..span }),
+            ("font", Some(font_family)) => Ok(TextSpan { font_family: Some(font_family), ..span }),
+            ("b" | "i" | "u" | "s", Some(_)) => Err(MarkupError::UnexpectedArgument {
+                position,
+                tag: name.to_string(),
+            }),
+            ("b", None) => Ok(TextSpan { font_weight: Some(700), ..span }),
+            ("i", None) => Ok(TextSpan { font_style: Some("italic".to_string()), ..span }),
+            ("u", None) => Ok(decorate(span, "underline")),
+            ("s", None) => Ok(decorate(span, "line-through")),
+            _ => Err(MarkupError::UnknownTag {
+                position,
+                tag: name.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(markup: &str) -> Result<Vec<TextSpan>, MarkupError> {
+        MarkupText { markup: markup.to_string() }.parse()
+    }
+
+    fn error(markup: &str) -> MarkupError {
+        parse(markup).expect_err("The markup is invalid.")
+    }
+
+    #[test]
+    fn parses_nested_tags() {
+        let spans = parse(r"<c:#ff0>Gold <b>bold <u><s>struck</s></u></b></c> \<plain\>").expect("The markup is valid.");
+        let values: Vec<&str> = spans.iter().map(|span| span.value.as_str()).collect();
+        assert_eq!(values, ["Gold ", "bold ", "struck", " <plain>"]);
+
+        assert_eq!(spans[1].fill.as_deref(), Some("#ff0"));
+        assert_eq!(spans[1].font_weight, Some(700));
+        assert_eq!(spans[2].text_decoration.as_deref(), Some("underline line-through"));
+        assert_eq!(spans[3].fill, None);
+    }
+
+    #[test]
+    fn rejects_mismatched_tags() {
+        assert_eq!(
+            error("<b><i>text</b></i>"),
+            MarkupError::MismatchedClosingTag {
+                position: 10,
+                expected: Some("i".to_string()),
+                found: "b".to_string(),
+            }
+        );
+        assert_eq!(
+            error("text</b>"),
+            MarkupError::MismatchedClosingTag { position: 4, expected: None, found: "b".to_string() }
+        );
+        assert_eq!(error("<b>text"), MarkupError::UnclosedTag { tag: "b".to_string() });
+    }
+
+    #[test]
+    fn rejects_malformed_tags() {
+        assert_eq!(error("a <b"), MarkupError::UnterminatedTag { position: 2 });
+        assert_eq!(error("<x>a</x>"), MarkupError::UnknownTag { position: 0, tag: "x".to_string() });
+        assert_eq!(error("<c>a</c>"), MarkupError::MissingArgument { position: 0, tag: "c".to_string() });
+        assert_eq!(
+            error("<b:700>a</b>"),
+            MarkupError::UnexpectedArgument { position: 0, tag: "b".to_string() }
+        );
+        assert_eq!(error(r"a\"), MarkupError::DanglingEscape { position: 1 });
+    }
+
+    fn minecraft(text: &str) -> MinecraftText {
+        MinecraftText { minecraft: text.to_string(), palette: HashMap::new() }
+    }
+
+    fn values(spans: &[TextSpan]) -> Vec<&str> {
+        spans.iter().map(|span| span.value.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_minecraft_colors() {
+        let spans = minecraft("§6[MVP§c++§6] Name").spans();
+        assert_eq!(values(&spans), ["[MVP", "++", "] Name"]);
+
+        let fills: Vec<Option<&str>> = spans.iter().map(|span| span.fill.as_deref()).collect();
+        assert_eq!(fills, [Some("#FFAA00"), Some("#FF5555"), Some("#FFAA00")]);
+    }
+
+    #[test]
+    fn parses_ampersand_codes() {
+        let spans = minecraft("&aGreen &LBold").spans();
+        assert_eq!(values(&spans), ["Green ", "Bold"]);
+        assert_eq!(spans[0].fill.as_deref(), Some("#55FF55"));
+        assert_eq!(spans[1].fill.as_deref(), Some("#55FF55"));
+        assert_eq!(spans[1].font_weight, Some(700));
+    }
+
+    #[test]
+    fn resets_style() {
+        let spans = minecraft("§c§l§nLoud§rQuiet").spans();
+        assert_eq!(values(&spans), ["Loud", "Quiet"]);
+
+        assert_eq!(spans[0].font_weight, Some(700));
+        assert_eq!(spans[0].text_decoration.as_deref(), Some("underline"));
+        assert_eq!(spans[1].fill, None);
+        assert_eq!(spans[1].font_weight, None);
+        assert_eq!(spans[1].text_decoration, None);
+    }
+
+    #[test]
+    fn colors_clear_styles() {
+        let spans = minecraft("§l§oStyled§9Blue").spans();
+        assert_eq!(values(&spans), ["Styled", "Blue"]);
+
+        assert_eq!(spans[0].font_weight, Some(700));
+        assert_eq!(spans[0].font_style.as_deref(), Some("italic"));
+        assert_eq!(spans[1].fill.as_deref(), Some("#5555FF"));
+        assert_eq!(spans[1].font_weight, None);
+        assert_eq!(spans[1].font_style, None);
+    }
+
+    #[test]
+    fn keeps_characters_that_are_not_codes() {
+        assert_eq!(values(&minecraft("Name§").spans()), ["Name§"]);
+        assert_eq!(values(&minecraft("Tom & Jerry §z").spans()), ["Tom & Jerry §z"]);
+    }
+
+    #[test]
+    fn overrides_palette_colors() {
+        let text = MinecraftText {
+            minecraft: "§6Gold§cRed".to_string(),
+            palette: HashMap::from([("6".to_string(), "#FFC000".to_string())]),
+        };
+        let spans = text.spans();
+
+        assert!(text.validate("rank").is_ok());
+        assert_eq!(spans[0].fill.as_deref(), Some("#FFC000"));
+        assert_eq!(spans[1].fill.as_deref(), Some("#FF5555"));
+    }
+
+    #[test]
+    fn rejects_invalid_palettes() {
+        let palette = |code: &str, color: &str| MinecraftText {
+            minecraft: String::new(),
+            palette: HashMap::from([(code.to_string(), color.to_string())]),
+        };
+
+        for code in ["g", "10", ""] {
+            assert!(
+                matches!(palette(code, "#FFC000").validate("rank"), Err(PlaceholderError::InvalidColorCode { .. })),
+                "{code} is not a color code"
+            );
+        }
+
+        for color in ["#FFC00", "#GGGGGG", "red\" onload=\"x"] {
+            assert!(
+                matches!(palette("6", color).validate("rank"), Err(PlaceholderError::InvalidFill { .. })),
+                "{color} is not a color"
+            );
+        }
+    }
+}
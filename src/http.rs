@@ -1,42 +1,33 @@
-use std::{io::Cursor, path::Path, sync::Mutex, time};
+use std::{io::Cursor, num::NonZeroUsize, path::Path, sync::{Arc, RwLock}, thread, time::{self, Duration}};
 
 use axum::{
     Router,
     body::Body,
-    extract::DefaultBodyLimit,
-    http::{self, Response, StatusCode},
-    routing::post,
+    Json,
+    extract::{DefaultBodyLimit, Path as RoutePath},
+    http::{self, HeaderMap, HeaderValue, Response, StatusCode},
+    routing::{get, post},
 };
-use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
 use image::ImageFormat;
-use log::info;
+use log::{error, info, warn};
 use resvg::usvg;
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, sync::Semaphore};
 
 use crate::{
-    placeholders::PlaceholderValues, render::{Renderer, RenderingError}, schema::{self, Schema}
+    cache::RenderCache, output::OutputOptions, placeholders::PlaceholderValues, render::{ImgBuf, Renderer}, report::RenderReport, resources::ResourcePolicy, schema::{self, Schema}
 };
 
-const DEFAULT_EXPORT_DIR: &str = "templates";
-
-
-lazy_static::lazy_static! {
-    pub static ref USVG_OPTIONS: Mutex<usvg::Options<'static>> = Mutex::new(usvg::Options::default());
-}
-
-#[derive(Debug, TryFromMultipart)]
-/// Form data for creating a new render.
-struct CreateRenderData {
-    #[form_data(limit = "10MB")]
-    /// The background image to use for the render.
-    background_image: Option<FieldData<axum::body::Bytes>>,
+mod error;
+mod request;
+mod templates;
+pub use error::{ErrorCode, ErrorResponse};
+use request::RenderRequest;
+pub use templates::{LayoutInfo, PlaceholderInfo, RasterSizeInfo, ScaleInfo, TemplateInfo};
+use templates::TemplateRegistry;
 
-    /// The placeholder values to use for the render.
-    pub placeholder_values: FieldData<String>,
-
-    /// The name of the scale to use.
-    pub scale: Option<FieldData<String>>
-}
+const DEFAULT_EXPORT_DIR: &str = "templates";
+const DEFAULT_FONTS_DIR: &str = "./fonts/";
+const RENDER_REPORT_HEADER: &str = "x-render-report";
 
 #[derive(Debug)]
 /// Errors that can occur during template discovery.
@@ -59,23 +50,169 @@ impl From<schema::SchemaError> for DiscoveryError {
     }
 }
 
+impl Default for AxumRenderingServer {
+    fn default() -> Self {
+        AxumRenderingServer::new()
+    }
+}
+
 /// An HTTP server that can be used to render templates with the provided placeholder values.
 pub struct AxumRenderingServer {
     /// The router for the HTTP server.
     app_router: Router,
+    /// The asset cache shared by all renderer routes.
+    cache: Arc<RenderCache>,
+    /// The immutable usvg options shared by all renderer routes.
+    usvg_options: Arc<usvg::Options<'static>>,
+    /// Limits the number of renders running at the same time.
+    render_permits: Arc<Semaphore>,
+    /// The maximum number of renders running at the same time.
+    max_concurrent_renders: usize,
+    /// The schemas of all registered renderer routes, used by the introspection routes.
+    templates: Arc<RwLock<TemplateRegistry>>,
+    /// Whether renderer routes render strictly unless a request specifies otherwise.
+    strict: bool,
+    /// Determines which resources image placeholders may reference.
+    resource_policy: Arc<ResourcePolicy>,
 }
 
 impl AxumRenderingServer {
     /// Creates a new AxumRenderingServer.
+    ///
+    /// Fonts are loaded from the default fonts directory and the number of concurrent renders is
+    /// limited to the available parallelism of the host.
     pub fn new() -> Self {
-        let mut options = USVG_OPTIONS.lock().unwrap();
-        options.fontdb_mut().load_fonts_dir("./fonts/");
+        let mut options = usvg::Options::default();
+        options.fontdb_mut().load_fonts_dir(DEFAULT_FONTS_DIR);
+
+        let max_concurrent_renders = thread::available_parallelism()
+            .map(NonZeroUsize::get)
+            .unwrap_or(1);
+
+        let templates: Arc<RwLock<TemplateRegistry>> = Arc::default();
+
+        let list_templates = templates.clone();
+        let get_template = templates.clone();
+        let app_router = Router::new()
+            .route(
+                "/templates",
+                get(async move || -> Json<Vec<TemplateInfo>> {
+                    let templates = list_templates.read().unwrap_or_else(|e| e.into_inner());
+
+                    Json(templates
+                        .iter()
+                        .map(|(name, schema)| TemplateInfo::new(name, schema))
+                        .collect())
+                }),
+            )
+            .route(
+                "/templates/{*name}",
+                get(async move |RoutePath(name): RoutePath<String>| -> Result<Json<TemplateInfo>, ErrorResponse> {
+                    let templates = get_template.read().unwrap_or_else(|e| e.into_inner());
+                    let schema = templates.get(&name).ok_or_else(|| {
+                        ErrorResponse::new(
+                            StatusCode::NOT_FOUND,
+                            ErrorCode::TemplateNotFound,
+                            format!("Template '{name}' does not exist."),
+                        )
+                    })?;
+
+                    Ok(Json(TemplateInfo::new(&name, schema)))
+                }),
+            );
 
         AxumRenderingServer {
-            app_router: Router::new(),
+            app_router,
+            cache: Arc::new(RenderCache::default()),
+            usvg_options: Arc::new(options),
+            render_permits: Arc::new(Semaphore::new(max_concurrent_renders)),
+            max_concurrent_renders,
+            templates,
+            strict: false,
+            resource_policy: Arc::default(),
         }
     }
 
+    /// Uses the specified usvg options for all renderer routes added afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// - `options` - The options to share between renderer routes. Fonts should already be
+    ///   loaded into its font database.
+    pub fn with_usvg_options(mut self, options: usvg::Options<'static>) -> Self {
+        self.usvg_options = Arc::new(options);
+        self
+    }
+
+    /// Sets the maximum number of renders that may run at the same time. Renders run on a
+    /// blocking thread pool and requests beyond the limit wait for a free slot.
+    ///
+    /// This should be called before the server starts serving. Slots held by running renders
+    /// cannot be taken away, so lowering the limit while renders run only lowers it by the number
+    /// of free slots.
+    ///
+    /// # Arguments
+    ///
+    /// - `limit` - The maximum number of concurrent renders.
+    pub fn with_max_concurrent_renders(mut self, limit: NonZeroUsize) -> Self {
+        let limit = limit.get();
+
+        // The semaphore is shared with routes that were already added, so adjust it in place.
+        if limit > self.max_concurrent_renders {
+            self.render_permits.add_permits(limit - self.max_concurrent_renders);
+            self.max_concurrent_renders = limit;
+        } else {
+            let forgotten = self.render_permits.forget_permits(self.max_concurrent_renders - limit);
+            self.max_concurrent_renders -= forgotten;
+
+            if self.max_concurrent_renders > limit {
+                warn!(
+                    "Limited concurrent renders to {} instead of {limit} as renders are running.",
+                    self.max_concurrent_renders
+                );
+            }
+        }
+
+        self
+    }
+
+    /// Uses the specified asset cache for all renderer routes added afterwards.
+    ///
+    /// # Arguments
+    ///
+    /// - `cache` - The cache to share between renderer routes.
+    pub fn with_cache(mut self, cache: Arc<RenderCache>) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Sets whether renderer routes added afterwards render strictly by default. Requests can
+    /// override this with the `strict` field.
+    ///
+    /// # Arguments
+    ///
+    /// - `strict` - Whether to render strictly by default.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets the policy that determines which resources image placeholders and the images of
+    /// fragments may reference for all renderer routes added afterwards. By default only `data:` image URIs are allowed.
+    ///
+    /// # Arguments
+    ///
+    /// - `policy` - The resource policy to use.
+    pub fn with_resource_policy(mut self, policy: ResourcePolicy) -> Self {
+        self.resource_policy = Arc::new(policy);
+        self
+    }
+
+    /// Returns the asset cache shared by the renderer routes.
+    pub fn cache(&self) -> &Arc<RenderCache> {
+        &self.cache
+    }
+
     /// Starts the AxumRenderingServer.
     ///
     /// # Arguments
@@ -91,12 +228,27 @@ impl AxumRenderingServer {
     }
 
     /// Discovers templates in the default templates directory and adds them to the AxumRenderingServer.
-    pub fn discover_templates(mut self) -> Result<Self, DiscoveryError> {
-        let templates_dir = Path::new(DEFAULT_EXPORT_DIR);
+    /// Templates that fail to load, such as templates whose schemas fail validation, are logged
+    /// and skipped. Only errors reading the templates directory itself are returned.
+    pub fn discover_templates(self) -> Result<Self, DiscoveryError> {
+        self.discover_templates_in(Path::new(DEFAULT_EXPORT_DIR))
+    }
 
+    /// Discovers templates in a directory and adds them to the AxumRenderingServer.
+    ///
+    /// # Arguments
+    ///
+    /// - `templates_dir` - The directory containing a directory for every template.
+    fn discover_templates_in(mut self, templates_dir: &Path) -> Result<Self, DiscoveryError> {
         if templates_dir.try_exists()? && templates_dir.is_dir() {
             for entry in templates_dir.read_dir()? {
-                let entry = entry?;
+                let entry = match entry {
+                    Ok(entry) => entry,
+                    Err(e) => {
+                        error!("Failed to read an entry of '{}': {e}", templates_dir.display());
+                        continue;
+                    }
+                };
 
                 if !entry.path().is_dir() {
                     continue;
@@ -106,12 +258,29 @@ impl AxumRenderingServer {
                 schema_path.push("schema.json");
 
                 let dir_filename = entry.file_name();
-                let dirname = dir_filename
-                    .to_str()
-                    .ok_or(std::io::Error::other("Failed to read dirname"))?;
+                let Some(dirname) = dir_filename.to_str() else {
+                    warn!("Skipped template '{}' because its name is not valid UTF-8.", entry.path().display());
+                    continue;
+                };
+
+                // A template that fails to load is skipped, so it does not stop the others from
+                // being served.
+                let schema = match schema::load_schema_from_file(&schema_path.to_string_lossy()) {
+                    Ok(schema) => schema,
+                    Err(schema::SchemaError::ValidationFailed(findings)) => {
+                        for finding in findings.iter().filter(|finding| finding.is_error()) {
+                            error!("{}: {finding}", schema_path.display());
+                        }
 
-                let schema =
-                    schema::load_schema_from_file(&schema_path.to_string_lossy().to_string())?;
+                        warn!("Skipped template '{dirname}' because its schema failed validation.");
+                        continue;
+                    }
+                    Err(e) => {
+                        error!("{}: {e}", schema_path.display());
+                        warn!("Skipped template '{dirname}' because its schema failed to load.");
+                        continue;
+                    }
+                };
 
                 self = self.add_renderer(schema, &format!("/{dirname}"));
             }
@@ -120,7 +289,8 @@ impl AxumRenderingServer {
         Ok(self)
     }
 
-    /// Adds a new renderer route to the rendering server.
+    /// Adds a new renderer route to the rendering server. The template is listed by the
+    /// introspection routes under its route path without the leading slash.
     ///
     /// # Arguments
     ///
@@ -128,87 +298,436 @@ impl AxumRenderingServer {
     /// - `route_path` - The route path to use for the renderer.
     pub fn add_renderer(mut self, schema: Schema, route_path: &str) -> Self {
         let schema_file = schema.schema_file.clone();
+        let schema = Arc::new(schema);
+
+        self.templates
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(route_path.trim_start_matches('/').to_string(), schema.clone());
+
+        let route = RendererRoute {
+            schema,
+            usvg_options: self.usvg_options.clone(),
+            cache: self.cache.clone(),
+            render_permits: self.render_permits.clone(),
+            strict: self.strict,
+            resource_policy: self.resource_policy.clone(),
+        };
 
         self.app_router = self.app_router.route(
             route_path,
             post(
-                async |TypedMultipart(form): TypedMultipart<CreateRenderData>| -> Result<Response<Body>, StatusCode> {
-                    let placeholder_values: PlaceholderValues =
-                        serde_json::from_str(&form.placeholder_values.contents)
-                            .map_err(|_| StatusCode::BAD_REQUEST)?;
-
-                    let options = USVG_OPTIONS.lock().map_err(|e| {
-                        log::error!("Failed to acquire options: {e}");
-                        StatusCode::INTERNAL_SERVER_ERROR
-                    })?;
+                async move |headers: HeaderMap, request: RenderRequest| -> Result<Response<Body>, ErrorResponse> {
+                    route.handle(headers, request).await
+                },
+            ),
+        ).layer(DefaultBodyLimit::max(10*1024*1025));
 
-                    let layout = match form.scale {
-                        Some(layout_name) => {
-                            schema
-                                .layout_by_scale_name(&layout_name.contents)
-                                .ok_or(StatusCode::BAD_REQUEST)?
-                        }
-                        None => {
-                            schema.default_layout().ok_or(StatusCode::INTERNAL_SERVER_ERROR)?
-                        }
-                    }.clone();
-
-                    let mut renderer = Renderer::build(schema, layout, placeholder_values, &options);
-
-                    let start_time = time::Instant::now();
-                    let output = match form.background_image {
-                        None => renderer.render_opaque().map_err(|e| {
-                            log::error!("Failed to add renderer: {e:#?}");
-                            StatusCode::INTERNAL_SERVER_ERROR})?,
-                        Some(background_image) => {
-                            match background_image.metadata.content_type {
-                                Some(content_type) => {
-                                    if content_type != "image/png" {
-                                        return Err(StatusCode::BAD_REQUEST);
-                                    }
-                                },
-                                None => return Err(StatusCode::BAD_REQUEST)
-                            }
-
-                            let cursor = Cursor::new(background_image.contents);
-                            let image = image::load(cursor, ImageFormat::Png).map_err(|_| StatusCode::BAD_REQUEST)?.to_rgba8();
-                            renderer.render_translucent(image).map_err(|e| {
-                                match e {
-                                    RenderingError::BackgroundsNotSupported(_) => StatusCode::BAD_REQUEST,
-                                    _ => {
-                                log::error!("Rendering failed: {e:#?}");
-                                StatusCode::INTERNAL_SERVER_ERROR
-                                    }
-                                }
-                            })?
-                        }
-                    };
+        info!("Registered {route_path} for schema at '{schema_file}'");
+        self
+    }
+}
 
-                    let render_time =  time::Instant::now() - start_time;
-                    let start_time = time::Instant::now();
+#[derive(Clone)]
+/// The shared state of a single renderer route.
+struct RendererRoute {
+    /// The schema to render.
+    schema: Arc<Schema>,
+    /// The usvg options to render with.
+    usvg_options: Arc<usvg::Options<'static>>,
+    /// The asset cache shared by all renderer routes.
+    cache: Arc<RenderCache>,
+    /// Limits the number of renders running at the same time.
+    render_permits: Arc<Semaphore>,
+    /// Whether to render strictly unless the request specifies otherwise.
+    strict: bool,
+    /// Determines which resources image placeholders may reference.
+    resource_policy: Arc<ResourcePolicy>,
+}
 
-                    let mut output_buffer = Vec::new();
-                    output.write_to(&mut Cursor::new(&mut output_buffer), ImageFormat::Png).map_err(|e| {
-                        log::error!("Failed to write PNG image to buffer: {e:#?}");
-                        StatusCode::INTERNAL_SERVER_ERROR})?;
+impl RendererRoute {
+    /// Handles a render request.
+    ///
+    /// # Arguments
+    ///
+    /// - `headers` - The headers of the request.
+    /// - `request` - The validated render request.
+    async fn handle(&self, headers: HeaderMap, request: RenderRequest) -> Result<Response<Body>, ErrorResponse> {
+        let output_options = request.output_options(&headers)?;
+
+        let layout = match (&request.scale, request.custom_scale) {
+            (Some(layout_name), _) => {
+                self.schema.layout_by_scale_name(layout_name).ok_or_else(|| {
+                    ErrorResponse::bad_request(
+                        ErrorCode::UnknownScale,
+                        format!("Unknown scale '{layout_name}'."),
+                    )
+                    .with_field("scale")
+                })?
+            }
+            (None, Some(custom_scale)) => {
+                self.schema.layout_for_scale(custom_scale).ok_or(schema::SchemaError::MissingDefaultLayout)?
+            }
+            (None, None) => {
+                self.schema.default_layout().ok_or(schema::SchemaError::MissingDefaultLayout)?
+            }
+        }.clone();
+        let debug = request.debug;
+        let strict = request.strict.unwrap_or(self.strict);
+        let custom_scale = request.custom_scale;
+        let placeholder_values = request.placeholder_values;
+        let background_image = request.background_image;
+
+        let permit = self.render_permits.clone().acquire_owned().await.map_err(|e| {
+            ErrorResponse::internal(format!("Failed to acquire render permit: {e}"))
+        })?;
+
+        // The permit is moved into the blocking task, so it is only released once the render
+        // finishes, even if the client disconnects and this future is dropped first.
+        let route = self.clone();
+        let (output_buffer, report, encode_time) = tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            route.render(layout, custom_scale, strict, placeholder_values, background_image, output_options)
+        })
+        .await
+        .map_err(|e| ErrorResponse::internal(format!("Render task failed: {e}")))??;
+
+        let mut response = Response::builder()
+            .status(StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, output_options.mime_type())
+            .header("server-timing", server_timing(&report, encode_time));
+
+        if debug {
+            match serde_json::to_string(&report).map(HeaderValue::try_from) {
+                Ok(Ok(value)) => response = response.header(RENDER_REPORT_HEADER, value),
+                _ => warn!("Failed to serialize the render report into a header."),
+            }
+        }
+
+        response
+            .body(Body::from(output_buffer))
+            .map_err(|e| ErrorResponse::internal(format!("Failed to send response: {e:#?}")))
+    }
+
+    /// Renders and encodes the output image. This blocks, so it must run on the blocking thread pool.
+    /// Returns the encoded image, the render report and the time spent encoding.
+    ///
+    /// # Arguments
+    ///
+    /// - `layout` - The layout to render.
+    /// - `custom_scale` - Optional: The custom scale to render at.
+    /// - `strict` - Whether to fail on missing or unknown placeholders and unresolved variables.
+    /// - `placeholder_values` - The placeholder values to use.
+    /// - `background_image` - Optional: The PNG encoded background image to use.
+    /// - `output_options` - Determines how the output image is encoded.
+    fn render(
+        &self,
+        layout: schema::SchemaLayout,
+        custom_scale: Option<f32>,
+        strict: bool,
+        placeholder_values: PlaceholderValues,
+        background_image: Option<axum::body::Bytes>,
+        output_options: OutputOptions,
+    ) -> Result<(Vec<u8>, RenderReport, Duration), ErrorResponse> {
+        let mut renderer = Renderer::build(
+            self.schema.clone(),
+            layout,
+            placeholder_values,
+            &self.usvg_options,
+        )
+        .with_cache(self.cache.clone())
+        .with_strict(strict)
+        .with_resource_policy(self.resource_policy.as_ref().clone());
+
+        if let Some(custom_scale) = custom_scale {
+            renderer = renderer.with_scale(custom_scale);
+        }
 
-                    let write_time = time::Instant::now() - start_time;
+        let output: ImgBuf = match background_image {
+            None => renderer.render_opaque()?,
+            Some(background_image) => {
+                let cursor = Cursor::new(background_image);
+                let image = image::load(cursor, ImageFormat::Png)
+                    .map_err(|e| {
+                        ErrorResponse::bad_request(
+                            ErrorCode::InvalidBackgroundImage,
+                            format!("Failed to decode the background image: {e}"),
+                        )
+                        .with_field("background_image")
+                    })?
+                    .to_rgba8();
+                renderer.render_translucent(image)?
+            }
+        };
 
-                    info!("Render Time: {}ms", render_time.as_millis());
-                    info!("Write Time: {}ms", write_time.as_millis());
+        let report = renderer.take_report();
+        let start_time = time::Instant::now();
 
-                    Ok(Response::builder()
-                        .status(StatusCode::OK)
-                        .header(http::header::CONTENT_TYPE, "image/png")
-                        .body(Body::from(output_buffer))
-                        .map_err(|e| {
-                            log::error!("Failed to send response: {e:#?}");
-                            StatusCode::INTERNAL_SERVER_ERROR})?)
-                },
+        let output_buffer = output_options.encode(&output)?;
+
+        let write_time = time::Instant::now() - start_time;
+
+        info!("Render Time: {}ms", report.timings.total.as_millis());
+        info!("Write Time: {}ms", write_time.as_millis());
+
+        Ok((output_buffer, report, write_time))
+    }
+}
+
+/// Formats the timings of a render as a `Server-Timing` header value.
+///
+/// # Arguments
+///
+/// - `report` - The report of the render.
+/// - `encode_time` - The time spent encoding the output image.
+fn server_timing(report: &RenderReport, encode_time: Duration) -> String {
+    let millis = |duration: Duration| duration.as_secs_f64() * 1000.0;
+    let timings = &report.timings;
+
+    format!(
+        "static_base;dur={:.2}, fragments;dur={:.2}, output;dur={:.2}, encode;dur={:.2}",
+        millis(timings.static_base),
+        millis(timings.fragments),
+        millis(timings.output),
+        millis(encode_time),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::Request;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::test_support::{self, TempDir, fragment, fragments, shape_fragment};
+
+    /// A template with a single 10x10px layout with the fragment `name`.
+    fn template(name: &str) -> (TempDir, Schema) {
+        let dir = TempDir::new(&format!("http-{name}"));
+        dir.image("base.png", 10, 10, [0, 0, 0, 0]);
+        dir.write(
+            "fragments/name.svg",
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><text>{name}</text></svg>"#,
+        );
+
+        let mut layout = test_support::layout(1, "1x", 1.0);
+        layout["fragments"]["text"] = json!([fragment("name", &["name"], json!({}))]);
+        let schema = dir.load("schema.json", &test_support::schema(vec![layout])).unwrap();
+
+        (dir, schema)
+    }
+
+    fn route(schema: Schema) -> RendererRoute {
+        RendererRoute {
+            schema: Arc::new(schema),
+            usvg_options: Arc::new(usvg::Options::default()),
+            cache: Arc::new(RenderCache::default()),
+            render_permits: Arc::new(Semaphore::new(1)),
+            strict: false,
+            resource_policy: Arc::new(ResourcePolicy::default()),
+        }
+    }
+
+    fn request(debug: bool) -> RenderRequest {
+        RenderRequest {
+            placeholder_values: serde_json::from_value(json!({})).unwrap(),
+            scale: None,
+            custom_scale: None,
+            background_image: None,
+            format: None,
+            quality: None,
+            png_compression: None,
+            debug,
+            strict: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn sends_render_report_in_debug_mode() {
+        let (_dir, schema) = template("debug");
+        let response = route(schema).handle(HeaderMap::new(), request(true)).await.unwrap();
+
+        let header = response.headers().get(RENDER_REPORT_HEADER).expect("The report header is sent.");
+        let report: serde_json::Value = serde_json::from_slice(header.as_bytes()).unwrap();
+
+        assert_eq!(report["missing_placeholders"], json!(["name"]));
+        assert!(response.headers().contains_key("server-timing"));
+    }
+
+    #[tokio::test]
+    async fn omits_render_report_outside_debug_mode() {
+        let (_dir, schema) = template("no-debug");
+        let response = route(schema).handle(HeaderMap::new(), request(false)).await.unwrap();
+
+        assert!(!response.headers().contains_key(RENDER_REPORT_HEADER));
+        assert!(response.headers().contains_key("server-timing"));
+    }
+
+    /// A template whose layout has a flag and a repeater with a text and a shape placeholder.
+    fn list_template(name: &str) -> (TempDir, Schema) {
+        let dir = TempDir::new(&format!("http-{name}"));
+        dir.image("base.png", 10, 10, [0, 0, 0, 0]);
+        for id in ["title", "player", "bar"] {
+            dir.write(
+                &format!("fragments/{id}.svg"),
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"/>"#,
+            );
+        }
+
+        let title = fragment("title", &["title"], json!({"visibleWhen": {"flag": "show_title"}}));
+        let repeater = json!({
+            "id": "players",
+            "fragments": fragments(
+                vec![fragment("player", &["player"], json!({}))],
+                vec![],
+                vec![shape_fragment("bar", &["bar_width"], json!({}))],
             ),
-        ).layer(DefaultBodyLimit::max(10*1024*1025));
+            "rowOffset": {"x": 0, "y": 2},
+            "maxRows": 4
+        });
 
-        info!("Registered {route_path} for schema at '{schema_file}'");
-        self
+        let mut layout = test_support::layout(1, "1x", 1.0);
+        layout["fragments"]["text"] = json!([title]);
+        layout["repeaters"] = json!([repeater]);
+        let schema = dir.load("schema.json", &test_support::schema(vec![layout])).unwrap();
+
+        (dir, schema)
+    }
+
+    /// Sends a GET request to the router of the server and returns the status and JSON body.
+    async fn get(server: &AxumRenderingServer, uri: &str) -> (StatusCode, Value) {
+        let request = Request::get(uri).body(Body::empty()).unwrap();
+        let response = server.router().clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn lists_templates() {
+        let (_first_dir, first) = template("list-first");
+        let (_second_dir, second) = template("list-second");
+        let server = AxumRenderingServer::new()
+            .add_renderer(second, "/stats/second")
+            .add_renderer(first, "/first");
+
+        let (status, body) = get(&server, "/templates").await;
+
+        assert_eq!(status, StatusCode::OK);
+        let names: Vec<&Value> = body.as_array().unwrap().iter().map(|t| &t["template"]).collect();
+        assert_eq!(names, [&json!("first"), &json!("stats/second")]);
+        assert_eq!(body[0]["id"], "test");
+        assert_eq!(body[0]["schema_version"], 3);
+    }
+
+    #[tokio::test]
+    async fn describes_template_placeholders_and_lists() {
+        let (_dir, schema) = list_template("details");
+        let server = AxumRenderingServer::new().add_renderer(schema, "/stats/details");
+
+        let (status, body) = get(&server, "/templates/stats/details").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["template"], "stats/details");
+
+        let layout = &body["layouts"][0];
+        assert_eq!(layout["scale"], json!({"name": "1x", "scale": 1.0, "is_default": true}));
+        assert_eq!(layout["raster_size"], json!({"width": 10, "height": 10}));
+        assert_eq!(layout["supports_background"], false);
+        assert_eq!(
+            layout["placeholders"],
+            json!({
+                "text": ["title"],
+                "images": [],
+                "shapes": [],
+                "flags": ["show_title"],
+                "lists": [{
+                    "id": "players",
+                    "max_rows": 4,
+                    "text": ["player"],
+                    "images": [],
+                    "shapes": ["bar_width"],
+                    "flags": []
+                }]
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn responds_not_found_for_unknown_templates() {
+        let (_dir, schema) = template("not-found");
+        let server = AxumRenderingServer::new().add_renderer(schema, "/known");
+
+        let (status, body) = get(&server, "/templates/unknown").await;
+
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "template_not_found");
+        assert_eq!(body["message"], "Template 'unknown' does not exist.");
+    }
+
+    #[tokio::test]
+    async fn adjusts_render_permits_to_the_limit() {
+        let limit = |limit| NonZeroUsize::new(limit).unwrap();
+
+        let server = AxumRenderingServer::new().with_max_concurrent_renders(limit(3));
+        assert_eq!(server.render_permits.available_permits(), 3);
+
+        let server = server.with_max_concurrent_renders(limit(1));
+        assert_eq!(server.render_permits.available_permits(), 1);
+        assert_eq!(server.max_concurrent_renders, 1);
+
+        // Held permits cannot be forgotten, so the limit follows the permits that are.
+        let server = server.with_max_concurrent_renders(limit(3));
+        let _held = server.render_permits.clone().acquire_many_owned(2).await.unwrap();
+        let server = server.with_max_concurrent_renders(limit(1));
+        assert_eq!(server.render_permits.available_permits(), 0);
+        assert_eq!(server.max_concurrent_renders, 2);
+
+        let server = server.with_max_concurrent_renders(limit(4));
+        assert_eq!(server.render_permits.available_permits(), 2);
+        assert_eq!(server.max_concurrent_renders, 4);
+    }
+
+    #[tokio::test]
+    async fn renders_beyond_the_limit_wait() {
+        let (_dir, schema) = template("limit");
+        let server = AxumRenderingServer::new()
+            .with_max_concurrent_renders(NonZeroUsize::MIN)
+            .add_renderer(schema, "/limit");
+
+        let running = server.render_permits.clone().acquire_owned().await.unwrap();
+
+        let request = Request::post("/limit")
+            .header(http::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(json!({"placeholder_values": {}}).to_string()))
+            .unwrap();
+        let mut waiting = tokio::spawn(server.router().clone().oneshot(request));
+
+        let timeout = tokio::time::timeout(Duration::from_millis(200), &mut waiting).await;
+        assert!(timeout.is_err(), "The render waits for a free slot.");
+
+        drop(running);
+        let response = waiting.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn skips_templates_that_fail_to_load() {
+        let dir = TempDir::new("http-discovery");
+        let (template_dir, _) = template("discovery-valid");
+        std::fs::rename(&template_dir.root, dir.path("valid")).unwrap();
+        dir.write("malformed/schema.json", "{");
+        dir.write("unknown-version/schema.json", json!({"schemaVersion": 99}).to_string());
+        dir.write("no-layouts/schema.json", test_support::schema(vec![]).to_string());
+        std::fs::create_dir(dir.path("missing-schema")).unwrap();
+        dir.write("not-a-template.txt", "");
+
+        let server = AxumRenderingServer::new().discover_templates_in(&dir.root).unwrap();
+
+        let (_, body) = get(&server, "/templates").await;
+        let names: Vec<&Value> = body.as_array().unwrap().iter().map(|t| &t["template"]).collect();
+        assert_eq!(names, [&json!("valid")]);
     }
 }
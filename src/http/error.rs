@@ -0,0 +1,395 @@
+use axum::{
+    Json,
+    extract::rejection::JsonRejection,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use axum_typed_multipart::TypedMultipartError;
+use serde::Serialize;
+
+use crate::{output::OutputOptionsError, render::RenderingError, schema::SchemaError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+/// Machine readable error codes returned by the HTTP server.
+pub enum ErrorCode {
+    /// The request body could not be parsed.
+    MalformedRequest,
+    /// The placeholder values are not valid.
+    InvalidPlaceholderValues,
+    /// The background image is missing a content type, is not a PNG or could not be decoded.
+    InvalidBackgroundImage,
+    /// The requested layout scale does not exist.
+    UnknownScale,
+    /// The requested custom scale is invalid.
+    InvalidScale,
+    /// The layout does not support background images.
+    BackgroundsNotSupported,
+    /// The requested output format or its options are invalid.
+    InvalidOutputOptions,
+    /// A strict render found missing or unknown placeholders or unresolved variables.
+    StrictModeViolation,
+    /// An image placeholder references a resource that is not allowed.
+    ResourceRejected,
+    /// The requested template does not exist.
+    TemplateNotFound,
+    /// The schema of the template is invalid.
+    SchemaError,
+    /// The render failed due to a server side error.
+    RenderFailed,
+    /// An unexpected server side error occurred.
+    InternalError,
+}
+
+#[derive(Debug, Serialize)]
+/// A JSON error response.
+pub struct ErrorResponse {
+    #[serde(skip)]
+    /// The HTTP status of the response.
+    pub status: StatusCode,
+    /// The machine readable error code.
+    pub code: ErrorCode,
+    /// A human readable description of the error.
+    pub message: String,
+    /// Optional: The request field that caused the error.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+}
+
+impl ErrorResponse {
+    /// Creates a new error response.
+    ///
+    /// # Arguments
+    ///
+    /// - `status` - The HTTP status of the response.
+    /// - `code` - The machine readable error code.
+    /// - `message` - A human readable description of the error.
+    pub fn new(status: StatusCode, code: ErrorCode, message: impl Into<String>) -> Self {
+        ErrorResponse {
+            status,
+            code,
+            message: message.into(),
+            field: None,
+        }
+    }
+
+    /// Creates a new `400 Bad Request` error response.
+    ///
+    /// # Arguments
+    ///
+    /// - `code` - The machine readable error code.
+    /// - `message` - A human readable description of the error.
+    pub fn bad_request(code: ErrorCode, message: impl Into<String>) -> Self {
+        ErrorResponse::new(StatusCode::BAD_REQUEST, code, message)
+    }
+
+    /// Creates a new `500 Internal Server Error` error response. The message is only logged, as
+    /// internal details should not be exposed to clients.
+    ///
+    /// # Arguments
+    ///
+    /// - `message` - A description of the error to log.
+    pub fn internal(message: impl Into<String>) -> Self {
+        log::error!("{}", message.into());
+
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::InternalError,
+            "An internal server error occurred.",
+        )
+    }
+
+    /// Sets the request field that caused the error.
+    ///
+    /// # Arguments
+    ///
+    /// - `field` - The name of the field.
+    pub fn with_field(mut self, field: impl Into<String>) -> Self {
+        self.field = Some(field.into());
+        self
+    }
+}
+
+impl IntoResponse for ErrorResponse {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+impl From<RenderingError> for ErrorResponse {
+    fn from(value: RenderingError) -> Self {
+        match value {
+            RenderingError::BackgroundsNotSupported(_) => {
+                ErrorResponse::bad_request(ErrorCode::BackgroundsNotSupported, value.to_string())
+                    .with_field("background_image")
+            }
+            RenderingError::InvalidScale(_) => {
+                ErrorResponse::bad_request(ErrorCode::InvalidScale, value.to_string())
+                    .with_field("custom_scale")
+            }
+            RenderingError::PlaceholderError(_) => {
+                ErrorResponse::bad_request(ErrorCode::InvalidPlaceholderValues, value.to_string())
+                    .with_field("placeholder_values")
+            }
+            RenderingError::ResourceRejected(_) => {
+                ErrorResponse::bad_request(ErrorCode::ResourceRejected, value.to_string())
+                    .with_field("placeholder_values")
+            }
+            RenderingError::StrictModeViolation(_) => ErrorResponse::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::StrictModeViolation,
+                value.to_string(),
+            )
+            .with_field("placeholder_values"),
+            RenderingError::InvalidOutputOptions(e) => e.into(),
+            _ => {
+                // Server side errors can contain asset paths and IO errors, so the details are
+                // only logged.
+                log::error!("Rendering failed: {value:#?}");
+
+                ErrorResponse::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorCode::RenderFailed,
+                    "The render failed due to a server side error.",
+                )
+            }
+        }
+    }
+}
+
+impl From<OutputOptionsError> for ErrorResponse {
+    fn from(value: OutputOptionsError) -> Self {
+        ErrorResponse::bad_request(ErrorCode::InvalidOutputOptions, value.to_string())
+            .with_field(value.field())
+    }
+}
+
+impl From<SchemaError> for ErrorResponse {
+    fn from(value: SchemaError) -> Self {
+        log::error!("Schema error: {value:#?}");
+
+        ErrorResponse::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::SchemaError,
+            "The schema of the template is invalid.",
+        )
+    }
+}
+
+impl From<TypedMultipartError> for ErrorResponse {
+    fn from(value: TypedMultipartError) -> Self {
+        let field = match &value {
+            TypedMultipartError::MissingField { field_name }
+            | TypedMultipartError::WrongFieldType { field_name, .. }
+            | TypedMultipartError::DuplicateField { field_name }
+            | TypedMultipartError::UnknownField { field_name }
+            | TypedMultipartError::InvalidEnumValue { field_name, .. }
+            | TypedMultipartError::FieldTooLarge { field_name, .. } => Some(field_name.clone()),
+            _ => None,
+        };
+
+        let mut response =
+            ErrorResponse::new(value.get_status(), ErrorCode::MalformedRequest, value.to_string());
+        response.field = field;
+        response
+    }
+}
+
+impl From<JsonRejection> for ErrorResponse {
+    fn from(value: JsonRejection) -> Self {
+        ErrorResponse::new(value.status(), ErrorCode::MalformedRequest, value.body_text())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Mutex, Once};
+
+    use axum::body::to_bytes;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::{output::OutputFormat, placeholders::PlaceholderError, report::RenderReport};
+
+    /// A path that must only ever be logged.
+    const SECRET_PATH: &str = "/srv/templates/secret/fragment.svg";
+
+    /// Records every log message, so tests can check what was logged.
+    struct CapturingLogger;
+
+    static LOGS: Mutex<Vec<String>> = Mutex::new(vec![]);
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            LOGS.lock().unwrap_or_else(|e| e.into_inner()).push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    fn capture_logs() {
+        static INIT: Once = Once::new();
+        INIT.call_once(|| {
+            log::set_logger(&CapturingLogger).expect("No other logger is set.");
+            log::set_max_level(log::LevelFilter::Trace);
+        });
+    }
+
+    fn logged(text: &str) -> bool {
+        LOGS.lock().unwrap_or_else(|e| e.into_inner()).iter().any(|log| log.contains(text))
+    }
+
+    /// Converts an error response into a response and returns its status and JSON body.
+    async fn respond(error: impl Into<ErrorResponse>) -> (StatusCode, Value) {
+        let response = error.into().into_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    fn secret_io_error() -> std::io::Error {
+        std::io::Error::new(std::io::ErrorKind::NotFound, format!("{SECRET_PATH} does not exist"))
+    }
+
+    #[tokio::test]
+    async fn maps_client_errors_to_status_code_and_field() {
+        let cases: Vec<(RenderingError, StatusCode, &str, Option<&str>)> = vec![
+            (
+                RenderingError::BackgroundsNotSupported("Not supported.".to_string()),
+                StatusCode::BAD_REQUEST,
+                "backgrounds_not_supported",
+                Some("background_image"),
+            ),
+            (
+                RenderingError::InvalidScale(-1.0),
+                StatusCode::BAD_REQUEST,
+                "invalid_scale",
+                Some("custom_scale"),
+            ),
+            (
+                RenderingError::PlaceholderError(PlaceholderError::InvalidFill {
+                    placeholder: "name".to_string(),
+                    fill: "nope".to_string(),
+                }),
+                StatusCode::BAD_REQUEST,
+                "invalid_placeholder_values",
+                Some("placeholder_values"),
+            ),
+            (
+                RenderingError::ResourceRejected("Rejected.".to_string()),
+                StatusCode::BAD_REQUEST,
+                "resource_rejected",
+                Some("placeholder_values"),
+            ),
+            (
+                RenderingError::StrictModeViolation(Box::new(RenderReport {
+                    missing_placeholders: vec!["name".to_string()],
+                    ..Default::default()
+                })),
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "strict_mode_violation",
+                Some("placeholder_values"),
+            ),
+            (
+                RenderingError::InvalidOutputOptions(OutputOptionsError::InvalidQuality(0)),
+                StatusCode::BAD_REQUEST,
+                "invalid_output_options",
+                Some("quality"),
+            ),
+            (
+                RenderingError::InvalidOutputOptions(OutputOptionsError::UnsupportedFormat(
+                    "gif".to_string(),
+                )),
+                StatusCode::BAD_REQUEST,
+                "invalid_output_options",
+                Some("format"),
+            ),
+        ];
+
+        for (error, status, code, field) in cases {
+            let message = error.to_string();
+            let (actual_status, body) = respond(error).await;
+
+            assert_eq!(actual_status, status, "{code}");
+            assert_eq!(body["code"], code);
+            assert_eq!(body["message"], message.as_str(), "{code}");
+            assert_eq!(body["field"].as_str(), field, "{code}");
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_output_option_errors_to_their_field() {
+        let cases = [
+            (OutputOptionsError::UnsupportedFormat("gif".to_string()), "format"),
+            (OutputOptionsError::UnsupportedPngCompression("max".to_string()), "png_compression"),
+            (OutputOptionsError::InvalidQuality(0), "quality"),
+            (OutputOptionsError::QualityNotSupported(OutputFormat::WebP), "quality"),
+        ];
+
+        for (error, field) in cases {
+            let (status, body) = respond(error).await;
+
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["code"], "invalid_output_options");
+            assert_eq!(body["field"], field);
+        }
+    }
+
+    #[tokio::test]
+    async fn maps_multipart_errors_to_their_field() {
+        let error = TypedMultipartError::MissingField {
+            field_name: "placeholder_values".to_string(),
+        };
+        let (status, body) = respond(error).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "malformed_request");
+        assert_eq!(body["field"], "placeholder_values");
+    }
+
+    #[tokio::test]
+    async fn logs_but_hides_server_side_render_errors() {
+        capture_logs();
+
+        let (status, body) = respond(RenderingError::FileSystemError(secret_io_error())).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "render_failed");
+        assert_eq!(body["message"], "The render failed due to a server side error.");
+        assert!(body.get("field").is_none());
+        assert!(!body.to_string().contains(SECRET_PATH));
+        assert!(logged(SECRET_PATH));
+    }
+
+    #[tokio::test]
+    async fn logs_but_hides_schema_errors() {
+        capture_logs();
+
+        let error = SchemaError::AssetOutsideTemplate(format!("{SECRET_PATH}.schema"));
+        let (status, body) = respond(error).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "schema_error");
+        assert!(!body.to_string().contains(SECRET_PATH));
+        assert!(logged(&format!("{SECRET_PATH}.schema")));
+    }
+
+    #[tokio::test]
+    async fn logs_but_hides_internal_errors() {
+        capture_logs();
+
+        let error = ErrorResponse::internal(format!("{SECRET_PATH}.internal"));
+        let (status, body) = respond(error).await;
+
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["code"], "internal_error");
+        assert_eq!(body["message"], "An internal server error occurred.");
+        assert!(!body.to_string().contains(SECRET_PATH));
+        assert!(logged(&format!("{SECRET_PATH}.internal")));
+    }
+}
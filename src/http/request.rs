@@ -0,0 +1,488 @@
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{FromRequest, Request},
+    http,
+};
+use axum_typed_multipart::{FieldData, TryFromMultipart, TypedMultipart};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::Deserialize;
+
+use super::error::{ErrorCode, ErrorResponse};
+use crate::{
+    output::{OutputFormat, OutputOptions, PngCompression},
+    placeholders::PlaceholderValues,
+};
+
+#[derive(Debug, TryFromMultipart)]
+/// Form data for creating a new render.
+struct CreateRenderData {
+    #[form_data(limit = "10MB")]
+    /// The background image to use for the render.
+    background_image: Option<FieldData<Bytes>>,
+
+    /// The placeholder values to use for the render.
+    pub placeholder_values: FieldData<String>,
+
+    /// The name of the scale to use.
+    pub scale: Option<FieldData<String>>,
+
+    /// A custom scale to render at, where `1.0` is the original design size.
+    pub custom_scale: Option<f32>,
+
+    /// The output format (`png`, `jpeg`, `webp` or `avif`). Takes precedence over the `Accept` header.
+    pub format: Option<String>,
+
+    /// The quality of lossy output formats from 1 to 100.
+    pub quality: Option<u8>,
+
+    /// The compression level of PNG output (`fast`, `balanced` or `best`).
+    pub png_compression: Option<String>,
+
+    /// Whether to return the render report in the `X-Render-Report` header.
+    pub debug: Option<bool>,
+
+    /// Whether to fail the render on missing or unknown placeholders and unresolved variables.
+    pub strict: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+/// JSON body for creating a new render.
+struct CreateRenderJson {
+    /// The placeholder values to use for the render.
+    placeholder_values: PlaceholderValues,
+
+    /// The name of the scale to use.
+    scale: Option<String>,
+
+    /// A custom scale to render at, where `1.0` is the original design size.
+    custom_scale: Option<f32>,
+
+    /// The base64 encoded PNG background image to use for the render. May be a `data:` URI.
+    background_image_base64: Option<String>,
+
+    /// The output format (`png`, `jpeg`, `webp` or `avif`). Takes precedence over the `Accept` header.
+    format: Option<String>,
+
+    /// The quality of lossy output formats from 1 to 100.
+    quality: Option<u8>,
+
+    /// The compression level of PNG output (`fast`, `balanced` or `best`).
+    png_compression: Option<String>,
+
+    /// Whether to return the render report in the `X-Render-Report` header.
+    #[serde(default)]
+    debug: bool,
+
+    /// Whether to fail the render on missing or unknown placeholders and unresolved variables.
+    strict: Option<bool>,
+}
+
+#[derive(Debug)]
+/// A validated render request, regardless of whether it was sent as multipart form data or JSON.
+pub struct RenderRequest {
+    /// The placeholder values to use for the render.
+    pub placeholder_values: PlaceholderValues,
+    /// Optional: The name of the scale to use.
+    pub scale: Option<String>,
+    /// Optional: A custom scale to render at.
+    pub custom_scale: Option<f32>,
+    /// Optional: The PNG encoded background image to use.
+    pub background_image: Option<Bytes>,
+    /// Optional: The requested output format. Falls back to the `Accept` header when unset.
+    pub format: Option<OutputFormat>,
+    /// Optional: The quality of lossy output formats.
+    pub quality: Option<u8>,
+    /// Optional: The compression level of PNG output.
+    pub png_compression: Option<PngCompression>,
+    /// Whether to return the render report with the response.
+    pub debug: bool,
+    /// Optional: Whether to render strictly. Falls back to the server default when unset.
+    pub strict: Option<bool>,
+}
+
+impl RenderRequest {
+    /// Returns the output options for the request. They are validated here, so invalid options
+    /// are rejected before rendering.
+    ///
+    /// # Arguments
+    ///
+    /// - `headers` - The headers of the request, used to negotiate the format if none was requested.
+    pub fn output_options(&self, headers: &http::HeaderMap) -> Result<OutputOptions, ErrorResponse> {
+        let format = self.format.unwrap_or_else(|| {
+            headers
+                .get(http::header::ACCEPT)
+                .and_then(|accept| accept.to_str().ok())
+                .map(output_format_from_accept)
+                .unwrap_or_default()
+        });
+
+        let mut output_options = OutputOptions::new(format);
+        if let Some(quality) = self.quality {
+            output_options = output_options.with_quality(quality);
+        }
+        if let Some(png_compression) = self.png_compression {
+            output_options = output_options.with_png_compression(png_compression);
+        }
+
+        output_options.validate()?;
+        Ok(output_options)
+    }
+
+    /// Parses the output format and PNG compression fields shared by both request bodies.
+    fn parse_output_fields(
+        format: Option<&str>,
+        png_compression: Option<&str>,
+    ) -> Result<(Option<OutputFormat>, Option<PngCompression>), ErrorResponse> {
+        let format = format.map(str::parse).transpose().map_err(ErrorResponse::from)?;
+        let png_compression = png_compression.map(str::parse).transpose().map_err(ErrorResponse::from)?;
+
+        Ok((format, png_compression))
+    }
+}
+
+impl TryFrom<CreateRenderData> for RenderRequest {
+    type Error = ErrorResponse;
+
+    fn try_from(form: CreateRenderData) -> Result<Self, Self::Error> {
+        let placeholder_values: PlaceholderValues =
+            serde_json::from_str(&form.placeholder_values.contents).map_err(|e| {
+                ErrorResponse::bad_request(
+                    ErrorCode::InvalidPlaceholderValues,
+                    format!("Invalid placeholder values: {e}"),
+                )
+                .with_field("placeholder_values")
+            })?;
+
+        let background_image = match form.background_image {
+            None => None,
+            Some(background_image) => {
+                match background_image.metadata.content_type.as_deref() {
+                    Some("image/png") => {}
+                    Some(content_type) => {
+                        return Err(ErrorResponse::bad_request(
+                            ErrorCode::InvalidBackgroundImage,
+                            format!("Background images must be 'image/png', got '{content_type}'."),
+                        )
+                        .with_field("background_image"));
+                    }
+                    None => {
+                        return Err(ErrorResponse::bad_request(
+                            ErrorCode::InvalidBackgroundImage,
+                            "Background images must have the 'image/png' content type.",
+                        )
+                        .with_field("background_image"));
+                    }
+                }
+
+                Some(background_image.contents)
+            }
+        };
+
+        let (format, png_compression) = RenderRequest::parse_output_fields(
+            form.format.as_deref(),
+            form.png_compression.as_deref(),
+        )?;
+
+        Ok(RenderRequest {
+            placeholder_values,
+            scale: form.scale.map(|scale| scale.contents),
+            custom_scale: form.custom_scale,
+            background_image,
+            format,
+            quality: form.quality,
+            png_compression,
+            debug: form.debug.unwrap_or(false),
+            strict: form.strict,
+        })
+    }
+}
+
+impl TryFrom<CreateRenderJson> for RenderRequest {
+    type Error = ErrorResponse;
+
+    fn try_from(body: CreateRenderJson) -> Result<Self, Self::Error> {
+        let invalid_background = |message: String| {
+            ErrorResponse::bad_request(ErrorCode::InvalidBackgroundImage, message)
+                .with_field("background_image_base64")
+        };
+
+        let background_image = match body.background_image_base64 {
+            None => None,
+            Some(encoded) => {
+                let encoded = match encoded.strip_prefix("data:") {
+                    Some(data_uri) => data_uri.strip_prefix("image/png;base64,").ok_or_else(|| {
+                        invalid_background(
+                            "Background image data URIs must be base64 encoded PNG images."
+                                .to_string(),
+                        )
+                    })?,
+                    None => encoded.as_str(),
+                };
+
+                Some(Bytes::from(BASE64.decode(encoded.trim()).map_err(|e| {
+                    invalid_background(format!("Invalid base64 background image: {e}"))
+                })?))
+            }
+        };
+
+        let (format, png_compression) = RenderRequest::parse_output_fields(
+            body.format.as_deref(),
+            body.png_compression.as_deref(),
+        )?;
+
+        Ok(RenderRequest {
+            placeholder_values: body.placeholder_values,
+            scale: body.scale,
+            custom_scale: body.custom_scale,
+            background_image,
+            format,
+            quality: body.quality,
+            png_compression,
+            debug: body.debug,
+            strict: body.strict,
+        })
+    }
+}
+
+impl<S: Send + Sync> FromRequest<S> for RenderRequest {
+    type Rejection = ErrorResponse;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_json = req
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.starts_with("application/json"));
+
+        if is_json {
+            let Json(body) = Json::<CreateRenderJson>::from_request(req, state).await?;
+            body.try_into()
+        } else {
+            let TypedMultipart(form) =
+                TypedMultipart::<CreateRenderData>::from_request(req, state).await?;
+            form.try_into()
+        }
+    }
+}
+
+/// Selects the output format from an `Accept` header, falling back to PNG when no supported
+/// format is listed.
+///
+/// # Arguments
+///
+/// - `accept` - The value of the `Accept` header.
+fn output_format_from_accept(accept: &str) -> OutputFormat {
+    let mut candidates: Vec<(f32, usize, OutputFormat)> = accept
+        .split(',')
+        .enumerate()
+        .filter_map(|(index, entry)| {
+            let mut parts = entry.split(';');
+            let format = OutputFormat::from_mime_type(parts.next()?)?;
+            let quality = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+
+            (quality > 0.0).then_some((quality, index, format))
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+    candidates.first().map(|c| c.2).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+
+    use super::*;
+
+    /// A 1x1px PNG image.
+    const PNG: &[u8] = &[
+        0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x48, 0x44, 0x52,
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1f, 0x15, 0xc4,
+        0x89, 0x00, 0x00, 0x00, 0x0d, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9c, 0x63, 0xf8, 0xcf, 0xc0, 0xf0,
+        0x1f, 0x00, 0x05, 0x00, 0x01, 0xff, 0x89, 0x99, 0x3d, 0x1d, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45,
+        0x4e, 0x44, 0xae, 0x42, 0x60, 0x82,
+    ];
+
+    const BOUNDARY: &str = "fractyl-boundary";
+
+    async fn extract(content_type: &str, body: impl Into<Body>) -> Result<RenderRequest, ErrorResponse> {
+        let request = Request::builder()
+            .method("POST")
+            .header(http::header::CONTENT_TYPE, content_type)
+            .body(body.into())
+            .unwrap();
+
+        RenderRequest::from_request(request, &()).await
+    }
+
+    async fn extract_json(body: serde_json::Value) -> Result<RenderRequest, ErrorResponse> {
+        extract("application/json", body.to_string()).await
+    }
+
+    /// Encodes multipart form data fields, each with an optional content type.
+    async fn extract_multipart(fields: &[(&str, Option<&str>, &[u8])]) -> Result<RenderRequest, ErrorResponse> {
+        let mut body = vec![];
+
+        for (name, content_type, contents) in fields {
+            body.extend(format!("--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"").bytes());
+            if let Some(content_type) = content_type {
+                body.extend(format!("; filename=\"{name}\"\r\nContent-Type: {content_type}").bytes());
+            }
+            body.extend(b"\r\n\r\n");
+            body.extend(*contents);
+            body.extend(b"\r\n");
+        }
+        body.extend(format!("--{BOUNDARY}--\r\n").bytes());
+
+        extract(&format!("multipart/form-data; boundary={BOUNDARY}"), body).await
+    }
+
+    #[tokio::test]
+    async fn extracts_json_bodies() {
+        let data_uri = format!("data:image/png;base64,{}", BASE64.encode(PNG));
+        let request = extract_json(serde_json::json!({
+            "placeholder_values": {"text": {"name": "Steve"}, "flags": {"mvp": true}},
+            "scale": "2x",
+            "custom_scale": 1.5,
+            "background_image_base64": data_uri,
+            "format": "webp",
+            "png_compression": "best",
+            "debug": true,
+            "strict": false
+        }))
+        .await
+        .unwrap();
+
+        assert!(request.placeholder_values.is_present("name"));
+        assert!(request.placeholder_values.flag("mvp"));
+        assert_eq!(request.scale.as_deref(), Some("2x"));
+        assert_eq!(request.custom_scale, Some(1.5));
+        assert_eq!(request.background_image.as_deref(), Some(PNG));
+        assert_eq!(request.format, Some(OutputFormat::WebP));
+        assert_eq!(request.png_compression, Some(PngCompression::Best));
+        assert!(request.debug);
+        assert_eq!(request.strict, Some(false));
+    }
+
+    #[tokio::test]
+    async fn extracts_plain_base64_background_images() {
+        let request = extract_json(serde_json::json!({
+            "placeholder_values": {},
+            "background_image_base64": BASE64.encode(PNG)
+        }))
+        .await
+        .unwrap();
+
+        assert_eq!(request.background_image.as_deref(), Some(PNG));
+        assert!(!request.debug);
+        assert_eq!(request.format, None);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_base64_background_images() {
+        for background in ["data:image/jpeg;base64,AAAA", "data:text/plain,hi", "not base64!"] {
+            let error = extract_json(serde_json::json!({
+                "placeholder_values": {},
+                "background_image_base64": background
+            }))
+            .await
+            .unwrap_err();
+
+            assert_eq!(error.status, http::StatusCode::BAD_REQUEST, "{background}");
+            assert_eq!(error.code, ErrorCode::InvalidBackgroundImage, "{background}");
+            assert_eq!(error.field.as_deref(), Some("background_image_base64"), "{background}");
+        }
+    }
+
+    #[tokio::test]
+    async fn extracts_multipart_bodies() {
+        let request = extract_multipart(&[
+            ("placeholder_values", None, br#"{"text": {"name": "Steve"}}"#),
+            ("background_image", Some("image/png"), PNG),
+            ("format", None, b"jpeg"),
+            ("quality", None, b"80"),
+        ])
+        .await
+        .unwrap();
+
+        assert!(request.placeholder_values.is_present("name"));
+        assert_eq!(request.background_image.as_deref(), Some(PNG));
+        assert_eq!(request.format, Some(OutputFormat::Jpeg));
+        assert_eq!(request.quality, Some(80));
+    }
+
+    #[tokio::test]
+    async fn rejects_background_images_of_wrong_content_type() {
+        let error = extract_multipart(&[
+            ("placeholder_values", None, b"{}"),
+            ("background_image", Some("image/jpeg"), PNG),
+        ])
+        .await
+        .unwrap_err();
+
+        assert_eq!(error.status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, ErrorCode::InvalidBackgroundImage);
+        assert_eq!(error.field.as_deref(), Some("background_image"));
+        assert!(error.message.contains("image/jpeg"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn rejects_unsupported_request_content_types() {
+        let error = extract("text/plain", "placeholder_values={}").await.unwrap_err();
+
+        assert_eq!(error.code, ErrorCode::MalformedRequest);
+        assert!(error.status.is_client_error(), "{}", error.status);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_json() {
+        let error = extract("application/json", "{\"placeholder_values\": ").await.unwrap_err();
+        assert_eq!(error.status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, ErrorCode::MalformedRequest);
+        assert_eq!(error.field, None);
+
+        // Well formed JSON that does not match the body is rejected as unprocessable.
+        let error = extract_json(serde_json::json!({"placeholder_values": {}, "quality": "high"})).await.unwrap_err();
+        assert_eq!(error.status, http::StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.code, ErrorCode::MalformedRequest);
+
+        let error = extract_json(serde_json::json!({"scale": "2x"})).await.unwrap_err();
+        assert_eq!(error.status, http::StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(error.message.contains("placeholder_values"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_placeholder_values_in_multipart_bodies() {
+        let error = extract_multipart(&[("placeholder_values", None, b"{")]).await.unwrap_err();
+
+        assert_eq!(error.status, http::StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, ErrorCode::InvalidPlaceholderValues);
+        assert_eq!(error.field.as_deref(), Some("placeholder_values"));
+    }
+
+    #[test]
+    fn selects_format_from_accept() {
+        assert_eq!(output_format_from_accept("image/webp"), OutputFormat::WebP);
+        assert_eq!(output_format_from_accept("text/html, image/avif, image/webp"), OutputFormat::Avif);
+        assert_eq!(output_format_from_accept(" IMAGE/JPEG ; charset=x"), OutputFormat::Jpeg);
+    }
+
+    #[test]
+    fn prefers_higher_q_values() {
+        assert_eq!(output_format_from_accept("image/webp;q=0.5, image/jpeg;q=0.9"), OutputFormat::Jpeg);
+        assert_eq!(output_format_from_accept("image/avif;q=0.8, image/webp"), OutputFormat::WebP);
+        assert_eq!(output_format_from_accept("image/webp;q=0, image/jpeg;q=0.1"), OutputFormat::Jpeg);
+        assert_eq!(output_format_from_accept("image/jpeg; q=invalid, image/webp;q=0.9"), OutputFormat::Jpeg);
+    }
+
+    #[test]
+    fn falls_back_to_png() {
+        for accept in ["", "*/*", "image/*", "text/html, image/gif", "image/webp;q=0", "image/svg+xml;q=1.0"] {
+            assert_eq!(output_format_from_accept(accept), OutputFormat::Png, "{accept}");
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::{collections::BTreeMap, sync::Arc};
+
+use serde::Serialize;
+
+use crate::schema::{Schema, SchemaFragmentType, SchemaLayout, SchemaRepeater};
+
+/// The schemas of all registered renderer routes, keyed by template name.
+pub type TemplateRegistry = BTreeMap<String, Arc<Schema>>;
+
+#[derive(Debug, Serialize)]
+/// The placeholders expected by a layout, grouped by fragment type.
+pub struct PlaceholderInfo {
+    /// Text placeholders.
+    pub text: Vec<String>,
+    /// Image placeholders.
+    pub images: Vec<String>,
+    /// Shape placeholders.
+    pub shapes: Vec<String>,
+    /// Flags that control the visibility of fragments.
+    pub flags: Vec<String>,
+    /// The placeholder value lists that fill repeaters.
+    pub lists: Vec<ListInfo>,
+}
+
+#[derive(Debug, Serialize)]
+/// The placeholders expected by every row of a repeater, grouped by fragment type.
+pub struct ListInfo {
+    /// The ID of the repeater, which is also the name of the list.
+    pub id: String,
+    /// The maximum number of rows that are rendered.
+    pub max_rows: u32,
+    /// Text placeholders of a row.
+    pub text: Vec<String>,
+    /// Image placeholders of a row.
+    pub images: Vec<String>,
+    /// Shape placeholders of a row.
+    pub shapes: Vec<String>,
+    /// Flags of a row that control the visibility of fragments.
+    pub flags: Vec<String>,
+}
+
+impl From<&SchemaRepeater> for ListInfo {
+    fn from(repeater: &SchemaRepeater) -> Self {
+        ListInfo {
+            id: repeater.id.clone(),
+            max_rows: repeater.max_rows,
+            text: repeater.fragments.placeholders(SchemaFragmentType::Text),
+            images: repeater.fragments.placeholders(SchemaFragmentType::Image),
+            shapes: repeater.fragments.placeholders(SchemaFragmentType::Shape),
+            flags: repeater.fragments.flags(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// The scale setting of a layout.
+pub struct ScaleInfo {
+    /// The name of the layout scale.
+    pub name: String,
+    /// The scale multiplier of the layout.
+    pub scale: f32,
+    /// Whether or not this is the default layout scale.
+    pub is_default: bool,
+}
+
+#[derive(Debug, Serialize)]
+/// The size of a rendered raster.
+pub struct RasterSizeInfo {
+    /// The width of the raster.
+    pub width: u32,
+    /// The height of the raster.
+    pub height: u32,
+}
+
+#[derive(Debug, Serialize)]
+/// Describes a layout of a template.
+pub struct LayoutInfo {
+    /// The ID of the layout.
+    pub id: u32,
+    /// The scale setting of the layout.
+    pub scale: ScaleInfo,
+    /// The size of the rendered raster.
+    pub raster_size: RasterSizeInfo,
+    /// Whether the layout supports custom background images.
+    pub supports_background: bool,
+    /// The placeholders expected by the layout.
+    pub placeholders: PlaceholderInfo,
+}
+
+impl From<&SchemaLayout> for LayoutInfo {
+    fn from(layout: &SchemaLayout) -> Self {
+        LayoutInfo {
+            id: layout.id,
+            scale: ScaleInfo {
+                name: layout.scale.name.clone(),
+                scale: layout.scale.scale,
+                is_default: layout.scale.is_default,
+            },
+            raster_size: RasterSizeInfo {
+                width: layout.raster_size.width,
+                height: layout.raster_size.height,
+            },
+            supports_background: layout.static_base.background.is_some(),
+            placeholders: PlaceholderInfo {
+                text: layout.fragments.placeholders(SchemaFragmentType::Text),
+                images: layout.fragments.placeholders(SchemaFragmentType::Image),
+                shapes: layout.fragments.placeholders(SchemaFragmentType::Shape),
+                flags: layout.fragments.flags(),
+                lists: layout.repeaters.iter().map(ListInfo::from).collect(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+/// Describes a registered template.
+pub struct TemplateInfo {
+    /// The name the template is registered under.
+    pub template: String,
+    /// The ID of the schema.
+    pub id: String,
+    /// The name of the schema.
+    pub name: String,
+    /// The version of the schema.
+    pub schema_version: u64,
+    /// All of the layouts of the template.
+    pub layouts: Vec<LayoutInfo>,
+}
+
+impl TemplateInfo {
+    /// Describes a registered template.
+    ///
+    /// # Arguments
+    ///
+    /// - `template` - The name the template is registered under.
+    /// - `schema` - The schema of the template.
+    pub fn new(template: &str, schema: &Schema) -> Self {
+        TemplateInfo {
+            template: template.to_string(),
+            id: schema.id.clone(),
+            name: schema.name.clone(),
+            schema_version: schema.schema_version,
+            layouts: schema.layouts.iter().map(LayoutInfo::from).collect(),
+        }
+    }
+}
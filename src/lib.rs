@@ -5,3 +5,14 @@ pub mod schema;
 pub mod render;
 pub mod http;
 pub mod placeholders;
+pub mod cache;
+pub mod output;
+pub mod report;
+pub mod resources;
+pub mod text_layout;
+pub mod formatting;
+pub mod typed_values;
+pub mod expressions;
+
+#[cfg(test)]
+mod test_support;
@@ -0,0 +1,368 @@
+use std::{fmt::Display, io::Cursor, str::FromStr};
+
+use image::{
+    ImageEncoder, Rgb, RgbImage, Rgba,
+    codecs::{
+        avif::AvifEncoder,
+        jpeg::JpegEncoder,
+        png::{CompressionType, FilterType, PngEncoder},
+        webp::WebPEncoder,
+    },
+};
+
+use crate::render::{ImgBuf, RenderingError};
+
+/// The quality used for lossy formats when none is specified.
+pub const DEFAULT_QUALITY: u8 = 85;
+
+/// The encoder speed used for AVIF output, from 1 (slowest) to 10 (fastest).
+const AVIF_SPEED: u8 = 8;
+
+/// The color that translucent pixels are composited onto for JPEG output, which has no alpha
+/// channel.
+pub const JPEG_BACKGROUND: [u8; 3] = [255, 255, 255];
+
+#[derive(Debug)]
+/// Errors caused by invalid output options.
+pub enum OutputOptionsError {
+    /// The output format is not supported.
+    UnsupportedFormat(String),
+    /// The PNG compression level is not supported.
+    UnsupportedPngCompression(String),
+    /// The quality is not between 1 and 100.
+    InvalidQuality(u8),
+    /// A quality was specified for a lossless format. Only JPEG and AVIF support a quality.
+    QualityNotSupported(OutputFormat),
+}
+
+impl OutputOptionsError {
+    /// Returns the name of the output option that caused the error.
+    pub fn field(&self) -> &'static str {
+        match self {
+            OutputOptionsError::UnsupportedFormat(_) => "format",
+            OutputOptionsError::UnsupportedPngCompression(_) => "png_compression",
+            OutputOptionsError::InvalidQuality(_) | OutputOptionsError::QualityNotSupported(_) => "quality",
+        }
+    }
+}
+
+impl Display for OutputOptionsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputOptionsError::UnsupportedFormat(format) => {
+                write!(f, "Unsupported output format '{format}'.")
+            }
+            OutputOptionsError::UnsupportedPngCompression(compression) => {
+                write!(f, "Unsupported PNG compression level '{compression}'.")
+            }
+            OutputOptionsError::InvalidQuality(quality) => {
+                write!(f, "Quality must be between 1 and 100, got {quality}.")
+            }
+            OutputOptionsError::QualityNotSupported(format) => {
+                write!(
+                    f,
+                    "The {format} output format is always lossless and does not support a quality. \
+                    Use jpeg or avif for lossy output."
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for OutputOptionsError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// The image format of a rendered output.
+pub enum OutputFormat {
+    /// Lossless PNG.
+    #[default]
+    Png,
+    /// Lossy JPEG. Translucent pixels are composited onto [`JPEG_BACKGROUND`].
+    Jpeg,
+    /// Lossless WebP. Lossy WebP encoding is not supported.
+    WebP,
+    /// Lossy AVIF.
+    Avif,
+}
+
+impl OutputFormat {
+    /// All supported output formats, in order of preference.
+    pub const ALL: [OutputFormat; 4] = [
+        OutputFormat::Png,
+        OutputFormat::WebP,
+        OutputFormat::Jpeg,
+        OutputFormat::Avif,
+    ];
+
+    /// Returns the MIME type of the format.
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "image/png",
+            OutputFormat::Jpeg => "image/jpeg",
+            OutputFormat::WebP => "image/webp",
+            OutputFormat::Avif => "image/avif",
+        }
+    }
+
+    /// Returns the format matching a MIME type, if it is supported.
+    ///
+    /// # Arguments
+    ///
+    /// - `mime_type` - The MIME type to match, without parameters.
+    pub fn from_mime_type(mime_type: &str) -> Option<Self> {
+        OutputFormat::ALL
+            .into_iter()
+            .find(|format| format.mime_type().eq_ignore_ascii_case(mime_type.trim()))
+    }
+
+    /// Returns whether the format supports a quality setting.
+    pub fn is_lossy(&self) -> bool {
+        matches!(self, OutputFormat::Jpeg | OutputFormat::Avif)
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputFormat::Png => write!(f, "png"),
+            OutputFormat::Jpeg => write!(f, "jpeg"),
+            OutputFormat::WebP => write!(f, "webp"),
+            OutputFormat::Avif => write!(f, "avif"),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = OutputOptionsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "jpeg" | "jpg" => Ok(OutputFormat::Jpeg),
+            "webp" => Ok(OutputFormat::WebP),
+            "avif" => Ok(OutputFormat::Avif),
+            other => OutputFormat::from_mime_type(other)
+                .ok_or_else(|| OutputOptionsError::UnsupportedFormat(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// The compression level of PNG output.
+pub enum PngCompression {
+    /// Fast, minimal compression.
+    Fast,
+    /// A balance between speed and size.
+    Balanced,
+    /// The smallest output at the cost of speed.
+    Best,
+}
+
+impl FromStr for PngCompression {
+    type Err = OutputOptionsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fast" => Ok(PngCompression::Fast),
+            "balanced" | "default" => Ok(PngCompression::Balanced),
+            "best" => Ok(PngCompression::Best),
+            _ => Err(OutputOptionsError::UnsupportedPngCompression(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Determines how a rendered image is encoded.
+pub struct OutputOptions {
+    /// The image format to encode to.
+    pub format: OutputFormat,
+    /// Optional: The quality of lossy formats from 1 to 100. Rejected for lossless formats.
+    pub quality: Option<u8>,
+    /// Optional: The compression level of PNG output. Defaults to the default of the PNG encoder.
+    pub png_compression: Option<PngCompression>,
+}
+
+impl OutputOptions {
+    /// Creates output options for the specified format with default settings.
+    ///
+    /// # Arguments
+    ///
+    /// - `format` - The image format to encode to.
+    pub fn new(format: OutputFormat) -> Self {
+        OutputOptions {
+            format,
+            ..OutputOptions::default()
+        }
+    }
+
+    /// Sets the quality of lossy formats.
+    ///
+    /// # Arguments
+    ///
+    /// - `quality` - The quality from 1 to 100.
+    pub fn with_quality(mut self, quality: u8) -> Self {
+        self.quality = Some(quality);
+        self
+    }
+
+    /// Sets the compression level of PNG output.
+    ///
+    /// # Arguments
+    ///
+    /// - `png_compression` - The compression level.
+    pub fn with_png_compression(mut self, png_compression: PngCompression) -> Self {
+        self.png_compression = Some(png_compression);
+        self
+    }
+
+    /// Checks that the options can be encoded. PNG and WebP are always encoded lossless, so a
+    /// quality is rejected for them rather than silently dropped.
+    pub fn validate(&self) -> Result<(), OutputOptionsError> {
+        match self.quality {
+            Some(_) if !self.format.is_lossy() => {
+                Err(OutputOptionsError::QualityNotSupported(self.format))
+            }
+            Some(quality) if !(1..=100).contains(&quality) => {
+                Err(OutputOptionsError::InvalidQuality(quality))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the MIME type of the encoded output.
+    pub fn mime_type(&self) -> &'static str {
+        self.format.mime_type()
+    }
+
+    /// Encodes a rendered image.
+    ///
+    /// # Arguments
+    ///
+    /// - `image` - The rendered image to encode.
+    pub fn encode(&self, image: &ImgBuf) -> Result<Vec<u8>, RenderingError> {
+        self.validate().map_err(RenderingError::InvalidOutputOptions)?;
+        let quality = self.quality.unwrap_or(DEFAULT_QUALITY);
+
+        let mut buffer = Vec::new();
+        let writer = Cursor::new(&mut buffer);
+        let (width, height) = image.dimensions();
+
+        match self.format {
+            OutputFormat::Png => {
+                let encoder = match self.png_compression {
+                    None => PngEncoder::new(writer),
+                    Some(png_compression) => {
+                        let compression = match png_compression {
+                            PngCompression::Fast => CompressionType::Fast,
+                            PngCompression::Balanced => CompressionType::Default,
+                            PngCompression::Best => CompressionType::Best,
+                        };
+
+                        PngEncoder::new_with_quality(writer, compression, FilterType::Adaptive)
+                    }
+                };
+
+                encoder.write_image(image, width, height, image::ExtendedColorType::Rgba8)
+            }
+            OutputFormat::Jpeg => {
+                let rgb = composite(image, JPEG_BACKGROUND);
+
+                JpegEncoder::new_with_quality(writer, quality).write_image(
+                    &rgb,
+                    width,
+                    height,
+                    image::ExtendedColorType::Rgb8,
+                )
+            }
+            OutputFormat::WebP => WebPEncoder::new_lossless(writer).write_image(
+                image,
+                width,
+                height,
+                image::ExtendedColorType::Rgba8,
+            ),
+            OutputFormat::Avif => AvifEncoder::new_with_speed_quality(writer, AVIF_SPEED, quality)
+                .write_image(image, width, height, image::ExtendedColorType::Rgba8),
+        }
+        .map_err(RenderingError::ImageEncodeError)?;
+
+        Ok(buffer)
+    }
+}
+
+/// Composites an image onto an opaque background color.
+///
+/// # Arguments
+///
+/// - `image` - The image to composite.
+/// - `background` - The color to composite the image onto.
+fn composite(image: &ImgBuf, background: [u8; 3]) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let Rgba([r, g, b, a]) = *image.get_pixel(x, y);
+        let blend = |color: u8, background: u8| {
+            ((color as u32 * a as u32 + background as u32 * (255 - a as u32) + 127) / 255) as u8
+        };
+
+        Rgb([blend(r, background[0]), blend(g, background[1]), blend(b, background[2])])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_quality_of_lossy_formats() {
+        for format in [OutputFormat::Jpeg, OutputFormat::Avif] {
+            for quality in [1, 85, 100] {
+                assert!(OutputOptions::new(format).with_quality(quality).validate().is_ok());
+            }
+
+            for quality in [0, 101, 255] {
+                assert!(matches!(
+                    OutputOptions::new(format).with_quality(quality).validate(),
+                    Err(OutputOptionsError::InvalidQuality(q)) if q == quality
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_quality_of_lossless_formats() {
+        for format in [OutputFormat::Png, OutputFormat::WebP] {
+            assert!(OutputOptions::new(format).validate().is_ok());
+
+            for quality in [0, 80] {
+                let error = OutputOptions::new(format).with_quality(quality).validate().unwrap_err();
+                assert!(matches!(error, OutputOptionsError::QualityNotSupported(f) if f == format));
+                assert_eq!(error.field(), "quality");
+            }
+        }
+    }
+
+    #[test]
+    fn composites_jpeg_output_onto_white() {
+        let mut image = ImgBuf::from_pixel(8, 8, Rgba([0, 0, 0, 0]));
+        for x in 0..8 {
+            image.put_pixel(x, 4, Rgba([255, 0, 0, 255]));
+            image.put_pixel(x, 6, Rgba([0, 0, 0, 128]));
+        }
+
+        let jpeg = OutputOptions::new(OutputFormat::Jpeg).with_quality(100).encode(&image).unwrap();
+        let decoded = image::load_from_memory(&jpeg).unwrap().to_rgb8();
+
+        let close = |pixel: &Rgb<u8>, expected: [u8; 3]| {
+            pixel.0.iter().zip(expected).all(|(&c, e)| c.abs_diff(e) <= 24)
+        };
+        assert!(close(decoded.get_pixel(1, 0), [255, 255, 255]), "{:?}", decoded.get_pixel(1, 0));
+        assert!(close(decoded.get_pixel(1, 4), [255, 0, 0]), "{:?}", decoded.get_pixel(1, 4));
+        assert!(close(decoded.get_pixel(1, 6), [127, 127, 127]), "{:?}", decoded.get_pixel(1, 6));
+    }
+
+    #[test]
+    fn composites_translucent_pixels() {
+        let image = ImgBuf::from_raw(3, 1, vec![0, 0, 0, 0, 10, 20, 30, 255, 0, 0, 0, 128]).unwrap();
+        let rgb = composite(&image, [255, 255, 255]);
+
+        assert_eq!(rgb.into_raw(), [255, 255, 255, 10, 20, 30, 127, 127, 127]);
+    }
+}
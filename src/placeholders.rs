@@ -1,20 +1,121 @@
-use std::{collections::HashMap, fmt::Display};
+use std::{collections::HashMap, fmt::Display, str::FromStr};
 
+use resvg::usvg::fontdb;
 use serde::Deserialize;
 
+use crate::formatting::{MarkupError, MarkupText, MinecraftText};
+use crate::schema::SchemaComputedPlaceholder;
+use crate::typed_values::TypedValue;
+
 pub type ExpectedPlaceholders = Vec<String>;
 pub type PlaceholderValueMap = HashMap<String, String>;
 pub type TextPlaceholderValueMap = HashMap<String, TextPlaceholderValue>;
 
+#[derive(Debug)]
+/// Errors caused by invalid placeholder values.
+pub enum PlaceholderError {
+    /// The fill of a text span is not a valid CSS color.
+    InvalidFill { placeholder: String, fill: String },
+    /// The font size of a text span is not a positive number with an optional unit.
+    InvalidFontSize { placeholder: String, font_size: String },
+    /// The font family of a text span is malformed or not loaded.
+    UnknownFontFamily { placeholder: String, font_family: String },
+    /// The font style of a text span is not `normal`, `italic` or `oblique`.
+    InvalidFontStyle { placeholder: String, font_style: String },
+    /// The text decoration of a text span is not `none` or a list of decoration lines.
+    InvalidTextDecoration { placeholder: String, text_decoration: String },
+    /// The letter spacing of a text span is not `normal` or a number with an optional unit.
+    InvalidLetterSpacing { placeholder: String, letter_spacing: String },
+    /// The opacity of a text span is not between 0 and 1.
+    InvalidOpacity { placeholder: String, opacity: f32 },
+    /// The stroke of a text span is not a valid CSS color.
+    InvalidStroke { placeholder: String, stroke: String },
+    /// The stroke width of a text span is not a non-negative number with an optional unit.
+    InvalidStrokeWidth { placeholder: String, stroke_width: String },
+    /// The baseline shift of a text span is not `baseline`, `sub`, `super` or a number with an
+    /// optional unit.
+    InvalidBaselineShift { placeholder: String, baseline_shift: String },
+    /// A key of a formatting palette is not a color code.
+    InvalidColorCode { placeholder: String, code: String },
+    /// The markup of a text value is malformed.
+    InvalidMarkup { placeholder: String, error: MarkupError },
+}
+
+impl Display for PlaceholderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlaceholderError::InvalidFill { placeholder, fill } => write!(
+                f,
+                "Invalid fill '{fill}' for placeholder '{placeholder}'. Fills must be CSS colors."
+            ),
+            PlaceholderError::InvalidFontSize { placeholder, font_size } => write!(
+                f,
+                "Invalid font size '{font_size}' for placeholder '{placeholder}'. Font sizes must be positive numbers with an optional unit."
+            ),
+            PlaceholderError::UnknownFontFamily { placeholder, font_family } => write!(
+                f,
+                "Unknown font family '{font_family}' for placeholder '{placeholder}'. Only loaded fonts and generic families can be used."
+            ),
+            PlaceholderError::InvalidFontStyle { placeholder, font_style } => write!(
+                f,
+                "Invalid font style '{font_style}' for placeholder '{placeholder}'. Font styles must be normal, italic or oblique."
+            ),
+            PlaceholderError::InvalidTextDecoration { placeholder, text_decoration } => write!(
+                f,
+                "Invalid text decoration '{text_decoration}' for placeholder '{placeholder}'. Text decorations must be none or a list of underline, overline and line-through."
+            ),
+            PlaceholderError::InvalidLetterSpacing { placeholder, letter_spacing } => write!(
+                f,
+                "Invalid letter spacing '{letter_spacing}' for placeholder '{placeholder}'. Letter spacings must be normal or numbers with an optional unit."
+            ),
+            PlaceholderError::InvalidOpacity { placeholder, opacity } => write!(
+                f,
+                "Invalid opacity '{opacity}' for placeholder '{placeholder}'. Opacities must be between 0 and 1."
+            ),
+            PlaceholderError::InvalidStroke { placeholder, stroke } => write!(
+                f,
+                "Invalid stroke '{stroke}' for placeholder '{placeholder}'. Strokes must be CSS colors."
+            ),
+            PlaceholderError::InvalidStrokeWidth { placeholder, stroke_width } => write!(
+                f,
+                "Invalid stroke width '{stroke_width}' for placeholder '{placeholder}'. Stroke widths must be non-negative numbers with an optional unit."
+            ),
+            PlaceholderError::InvalidBaselineShift { placeholder, baseline_shift } => write!(
+                f,
+                "Invalid baseline shift '{baseline_shift}' for placeholder '{placeholder}'. Baseline shifts must be baseline, sub, super or numbers with an optional unit."
+            ),
+            PlaceholderError::InvalidColorCode { placeholder, code } => write!(
+                f,
+                "Invalid color code '{code}' in the palette of placeholder '{placeholder}'. Color codes must be 0-9 or a-f."
+            ),
+            PlaceholderError::InvalidMarkup { placeholder, error } => write!(
+                f,
+                "Invalid markup for placeholder '{placeholder}': {error}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PlaceholderError {}
+
 #[derive(Deserialize, Debug, Clone)]
 /// Placeholder values.
 pub struct PlaceholderValues {
     /// Text placeholder values.
+    #[serde(default)]
     pub text: TextPlaceholderValueMap,
     /// Image placeholder values.
+    #[serde(default)]
     pub images: PlaceholderValueMap,
     /// Shape placeholder values.
+    #[serde(default)]
     pub shapes: PlaceholderValueMap,
+    /// Flags that control the visibility of fragments.
+    #[serde(default)]
+    pub flags: HashMap<String, bool>,
+    /// Rows of placeholder values for repeaters, keyed by repeater ID.
+    #[serde(default)]
+    pub lists: HashMap<String, Vec<PlaceholderValues>>,
 }
 
 impl PlaceholderValues {
@@ -29,7 +130,10 @@ impl PlaceholderValues {
                     tspans.join("")
                 }
                 TextPlaceholderValue::SingleTSpan(span) => span.to_tspan(),
-                TextPlaceholderValue::String(str_val) => str_val.to_string()
+                TextPlaceholderValue::Minecraft(text) => spans_to_tspans(&text.spans()),
+                TextPlaceholderValue::Markup(text) => spans_to_tspans(&text.spans()),
+                TextPlaceholderValue::Typed(value) => escape_xml(&value.to_string()),
+                TextPlaceholderValue::String(str_val) => str_val.clone()
             };
             map.insert(id.clone(), output_value);
         }
@@ -37,6 +141,138 @@ impl PlaceholderValues {
         map
     }
 
+    /// Validates the style attributes of all text spans, including those of list rows.
+    ///
+    /// # Arguments
+    ///
+    /// - `fontdb` - The font database that font families must be loaded in.
+    pub fn validate(&self, fontdb: &fontdb::Database) -> Result<(), PlaceholderError> {
+        let mut ids: Vec<&String> = self.text.keys().collect();
+        ids.sort();
+
+        for id in ids {
+            match &self.text[id] {
+                TextPlaceholderValue::MultiTSpan(spans) => {
+                    for span in spans {
+                        span.validate(id, fontdb)?;
+                    }
+                }
+                TextPlaceholderValue::SingleTSpan(span) => span.validate(id, fontdb)?,
+                TextPlaceholderValue::Minecraft(text) => text.validate(id)?,
+                TextPlaceholderValue::Markup(text) => text.validate(id, fontdb)?,
+                TextPlaceholderValue::Typed(_) | TextPlaceholderValue::String(_) => {}
+            }
+        }
+
+        for rows in self.lists.values() {
+            for row in rows {
+                row.validate(fontdb)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns whether a flag is set to true. Unset flags are false.
+    ///
+    /// # Arguments
+    ///
+    /// - `name` - The name of the flag.
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.get(name).copied().unwrap_or(false)
+    }
+
+    /// Returns whether a value is provided for a placeholder of any type.
+    ///
+    /// # Arguments
+    ///
+    /// - `name` - The name of the placeholder.
+    pub fn is_present(&self, name: &str) -> bool {
+        self.text.contains_key(name) || self.images.contains_key(name) || self.shapes.contains_key(name)
+    }
+
+    /// Returns whether the value provided for a placeholder of any type contains more than
+    /// whitespace.
+    ///
+    /// # Arguments
+    ///
+    /// - `name` - The name of the placeholder.
+    pub fn is_not_empty(&self, name: &str) -> bool {
+        let not_empty = |value: &str| !value.trim().is_empty();
+
+        match self.text.get(name) {
+            Some(TextPlaceholderValue::String(value)) => return not_empty(value),
+            Some(TextPlaceholderValue::SingleTSpan(span)) => return not_empty(&span.value),
+            Some(TextPlaceholderValue::MultiTSpan(spans)) => {
+                return spans.iter().any(|span| not_empty(&span.value));
+            }
+            Some(TextPlaceholderValue::Minecraft(text)) => {
+                return text.spans().iter().any(|span| not_empty(&span.value));
+            }
+            Some(TextPlaceholderValue::Markup(text)) => {
+                return text.spans().iter().any(|span| not_empty(&span.value));
+            }
+            Some(TextPlaceholderValue::Typed(_)) => return true,
+            None => {}
+        }
+
+        self.images.get(name).or(self.shapes.get(name)).is_some_and(|value| not_empty(value))
+    }
+
+    /// Returns the value provided for a placeholder of any type as plain text. Text values are
+    /// the text of their spans without styles.
+    ///
+    /// # Arguments
+    ///
+    /// - `name` - The name of the placeholder.
+    pub fn plain_text(&self, name: &str) -> Option<String> {
+        match self.text.get(name) {
+            Some(value) => Some(value.spans().into_iter().map(|span| span.value).collect()),
+            None => self.images.get(name).or(self.shapes.get(name)).cloned(),
+        }
+    }
+
+    /// Returns the numeric value of a text or shape placeholder. Typed values are used as is,
+    /// and other values are parsed as numbers. Ratios are divided and timestamps are seconds.
+    ///
+    /// # Arguments
+    ///
+    /// - `name` - The name of the placeholder.
+    pub fn number(&self, name: &str) -> Option<f64> {
+        let text = match self.text.get(name) {
+            Some(TextPlaceholderValue::Typed(value)) => return Some(value.number()),
+            Some(value) => value.spans().iter().map(|span| span.value.as_str()).collect(),
+            None => self.shapes.get(name)?.clone(),
+        };
+
+        text.trim().parse::<f64>().ok().filter(|number| number.is_finite())
+    }
+
+    /// Computes the values of computed placeholders, in order, for the values and every list
+    /// row. Computed values replace provided ones. Placeholders whose expressions are invalid or
+    /// reference placeholders without a numeric value are not set.
+    ///
+    /// # Arguments
+    ///
+    /// - `computed` - The computed placeholders declared by the schema.
+    pub fn compute(&mut self, computed: &[SchemaComputedPlaceholder]) {
+        for placeholder in computed {
+            let Ok(expression) = placeholder.parse() else {
+                continue;
+            };
+
+            if let Some(value) = expression.evaluate(&|name| self.number(name)) {
+                self.text.insert(placeholder.name.clone(), TextPlaceholderValue::Typed(TypedValue::Float(value)));
+            }
+        }
+
+        for rows in self.lists.values_mut() {
+            for row in rows {
+                row.compute(computed);
+            }
+        }
+    }
+
     /// Returns the image placeholder values.
     pub fn images(&self) -> PlaceholderValueMap {
         self.images.clone()
@@ -53,21 +289,106 @@ impl PlaceholderValues {
 pub enum TextPlaceholderValue {
     MultiTSpan(Vec<TextSpan>),
     SingleTSpan(TextSpan),
+    Minecraft(MinecraftText),
+    Markup(MarkupText),
+    Typed(TypedValue),
     String(String)
 }
 
+impl TextPlaceholderValue {
+    /// Returns the value as text spans. Strings become a single span without style overrides.
+    pub fn spans(&self) -> Vec<TextSpan> {
+        match self {
+            TextPlaceholderValue::MultiTSpan(spans) => spans.clone(),
+            TextPlaceholderValue::SingleTSpan(span) => vec![span.clone()],
+            TextPlaceholderValue::Minecraft(text) => text.spans(),
+            TextPlaceholderValue::Markup(text) => text.spans(),
+            TextPlaceholderValue::Typed(value) => vec![TextSpan::plain(&value.to_string())],
+            TextPlaceholderValue::String(value) => vec![TextSpan::plain(value)],
+        }
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(untagged)]
-pub enum FontSize {
+/// A length, either a number in user units or a string with an optional unit.
+pub enum Length {
     String(String),
     Number(f32)
 }
 
-impl Display for FontSize {
+/// Font sizes are lengths that must be positive.
+pub type FontSize = Length;
+
+impl Length {
+    /// Returns the number of the length without its unit, if it is a finite number with an
+    /// optional unit.
+    fn number(&self) -> Option<f64> {
+        let number = match self {
+            Length::String(s) => svgtypes::Length::from_str(s.trim()).ok()?.number,
+            Length::Number(n) => f64::from(*n),
+        };
+
+        number.is_finite().then_some(number)
+    }
+
+    /// Returns whether the length is a finite number with an optional unit.
+    pub fn is_valid(&self) -> bool {
+        self.number().is_some()
+    }
+
+    /// Returns whether the length is a positive number with an optional unit.
+    pub fn is_positive(&self) -> bool {
+        self.number().is_some_and(|number| number > 0.0)
+    }
+
+    /// Returns whether the length is a keyword, such as `normal`, rather than a number.
+    ///
+    /// # Arguments
+    ///
+    /// - `keywords` - The allowed keywords.
+    pub fn is_keyword(&self, keywords: &[&str]) -> bool {
+        matches!(self, Length::String(s) if keywords.contains(&s.trim()))
+    }
+
+    /// Returns the length multiplied by a factor. Lengths relative to the font size and
+    /// keywords are returned unchanged, as they scale with the font size.
+    ///
+    /// # Arguments
+    ///
+    /// - `scale` - The factor to multiply the length by.
+    pub fn scaled(&self, scale: f32) -> Length {
+        let s = match self {
+            Length::Number(n) => return Length::Number(n * scale),
+            Length::String(s) => s,
+        };
+
+        let Ok(length) = svgtypes::Length::from_str(s.trim()) else {
+            return self.clone();
+        };
+
+        let number = length.number as f32 * scale;
+        let unit = match length.unit {
+            svgtypes::LengthUnit::None | svgtypes::LengthUnit::Px => "",
+            svgtypes::LengthUnit::In => "in",
+            svgtypes::LengthUnit::Cm => "cm",
+            svgtypes::LengthUnit::Mm => "mm",
+            svgtypes::LengthUnit::Pt => "pt",
+            svgtypes::LengthUnit::Pc => "pc",
+            svgtypes::LengthUnit::Em | svgtypes::LengthUnit::Ex | svgtypes::LengthUnit::Percent => {
+                return self.clone();
+            }
+        };
+
+        Length::String(format!("{number}{unit}"))
+    }
+}
+
+impl Display for Length {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            FontSize::String(s) => write!(f, "{s}"),
-            FontSize::Number(n) => write!(f, "{n}")
+            Length::String(s) => write!(f, "{s}"),
+            Length::Number(n) => write!(f, "{n}")
         }
     }
 }
@@ -85,12 +406,97 @@ pub struct TextSpan {
     pub font_weight: Option<u32>,
     /// Optional: The font family.
     pub font_family: Option<String>,
+    /// Optional: The font style, `normal`, `italic` or `oblique`.
+    pub font_style: Option<String>,
+    /// Optional: The text decoration, such as `underline` or `underline line-through`.
+    pub text_decoration: Option<String>,
+    /// Optional: The additional space between characters, or `normal`.
+    pub letter_spacing: Option<Length>,
+    /// Optional: The opacity of the fill and stroke, from 0 to 1.
+    pub opacity: Option<f32>,
+    /// Optional: The stroke color.
+    pub stroke: Option<String>,
+    /// Optional: The stroke width.
+    pub stroke_width: Option<Length>,
+    /// Optional: The shift of the baseline, `sub`, `super` or a length such as `"40%"`.
+    pub baseline_shift: Option<Length>,
+}
+
+/// Escapes special characters that could be interpreted as XML, in both text content and
+/// attribute values.
+///
+/// # Arguments
+///
+/// - `value` - The value to escape.
+pub fn escape_xml(value: &str) -> String {
+    value
+        .replace("&", "&amp;")
+        .replace(">", "&gt;")
+        .replace("<", "&lt;")
+        .replace("\"", "&quot;")
+        .replace("'", "&apos;")
+}
+
+/// Converts text spans to a string of XML tspan elements.
+///
+/// # Arguments
+///
+/// - `spans` - The text spans to convert.
+pub fn spans_to_tspans(spans: &[TextSpan]) -> String {
+    spans.iter().map(TextSpan::to_tspan).collect()
 }
 
 impl TextSpan {
-    /// Escapes special characters that could be interpreted as XML.
-    fn escaped_value(&self) -> String {
-        self.value.replace("&", "&amp;").replace(">", "&gt;").replace("<", "&lt;")
+    /// Creates a text span without style overrides.
+    ///
+    /// # Arguments
+    ///
+    /// - `value` - The text value.
+    pub fn plain(value: &str) -> Self {
+        TextSpan {
+            value: value.to_string(),
+            fill: None,
+            font_size: None,
+            font_weight: None,
+            font_family: None,
+            font_style: None,
+            text_decoration: None,
+            letter_spacing: None,
+            opacity: None,
+            stroke: None,
+            stroke_width: None,
+            baseline_shift: None,
+        }
+    }
+
+    /// Returns a copy of the text span with its font size and other lengths multiplied by a
+    /// factor.
+    ///
+    /// # Arguments
+    ///
+    /// - `scale` - The factor to multiply the lengths by.
+    pub fn scaled(&self, scale: f32) -> Self {
+        let scaled = |length: &Option<Length>| length.as_ref().map(|length| length.scaled(scale));
+
+        TextSpan {
+            font_size: scaled(&self.font_size),
+            letter_spacing: scaled(&self.letter_spacing),
+            stroke_width: scaled(&self.stroke_width),
+            baseline_shift: scaled(&self.baseline_shift),
+            ..self.clone()
+        }
+    }
+
+    /// Returns a copy of the text span with a different text value.
+    ///
+    /// # Arguments
+    ///
+    /// - `value` - The new text value.
+    pub fn with_value(&self, value: &str) -> Self {
+        TextSpan {
+            value: value.to_string(),
+            ..self.clone()
+        }
     }
 
     /// Converts the text span to an XML tspan element string.
@@ -98,11 +504,11 @@ impl TextSpan {
         let mut attributes = vec![];
 
         if let Some(fill) = &self.fill {
-            attributes.push(format!("fill=\"{fill}\""));
+            attributes.push(format!("fill=\"{}\"", escape_xml(fill)));
         }
 
         if let Some(font_size) = &self.font_size {
-            attributes.push(format!("font-size=\"{font_size}\""));
+            attributes.push(format!("font-size=\"{}\"", escape_xml(&font_size.to_string())));
         }
 
         if let Some(font_weight) = self.font_weight {
@@ -110,14 +516,204 @@ impl TextSpan {
         }
 
         if let Some(font_family) = &self.font_family {
-            attributes.push(format!("font-family=\"{font_family}\""));
+            attributes.push(format!("font-family=\"{}\"", escape_xml(font_family)));
+        }
+
+        if let Some(font_style) = &self.font_style {
+            attributes.push(format!("font-style=\"{}\"", escape_xml(font_style)));
+        }
+
+        if let Some(text_decoration) = &self.text_decoration {
+            attributes.push(format!("text-decoration=\"{}\"", escape_xml(text_decoration)));
+        }
+
+        if let Some(letter_spacing) = &self.letter_spacing {
+            attributes.push(format!("letter-spacing=\"{}\"", escape_xml(&letter_spacing.to_string())));
+        }
+
+        // Opacity does not apply to text spans, so it is applied to their fill and stroke.
+        if let Some(opacity) = self.opacity {
+            attributes.push(format!("fill-opacity=\"{opacity}\" stroke-opacity=\"{opacity}\""));
+        }
+
+        if let Some(stroke) = &self.stroke {
+            attributes.push(format!("stroke=\"{}\"", escape_xml(stroke)));
+        }
+
+        if let Some(stroke_width) = &self.stroke_width {
+            attributes.push(format!("stroke-width=\"{}\"", escape_xml(&stroke_width.to_string())));
+        }
+
+        if let Some(baseline_shift) = &self.baseline_shift {
+            attributes.push(format!("baseline-shift=\"{}\"", escape_xml(&baseline_shift.to_string())));
         }
 
         format!("<tspan {} xml:space=\"preserve\">{}</tspan>",
             attributes.join(" "),
-            self.escaped_value()
+            escape_xml(&self.value)
         )
     }
+
+    /// Validates the style attributes of the text span.
+    ///
+    /// # Arguments
+    ///
+    /// - `placeholder` - The ID of the placeholder the span belongs to, used in errors.
+    /// - `fontdb` - The font database that font families must be loaded in.
+    pub fn validate(&self, placeholder: &str, fontdb: &fontdb::Database) -> Result<(), PlaceholderError> {
+        if let Some(fill) = &self.fill
+            && !is_valid_color(fill)
+        {
+            return Err(PlaceholderError::InvalidFill {
+                placeholder: placeholder.to_string(),
+                fill: fill.clone(),
+            });
+        }
+
+        if let Some(font_size) = &self.font_size
+            && !font_size.is_positive()
+        {
+            return Err(PlaceholderError::InvalidFontSize {
+                placeholder: placeholder.to_string(),
+                font_size: font_size.to_string(),
+            });
+        }
+
+        if let Some(font_family) = &self.font_family
+            && !is_loaded_font_family(font_family, fontdb)
+        {
+            return Err(PlaceholderError::UnknownFontFamily {
+                placeholder: placeholder.to_string(),
+                font_family: font_family.clone(),
+            });
+        }
+
+        if let Some(font_style) = &self.font_style
+            && !matches!(font_style.trim(), "normal" | "italic" | "oblique")
+        {
+            return Err(PlaceholderError::InvalidFontStyle {
+                placeholder: placeholder.to_string(),
+                font_style: font_style.clone(),
+            });
+        }
+
+        if let Some(text_decoration) = &self.text_decoration
+            && !is_valid_text_decoration(text_decoration)
+        {
+            return Err(PlaceholderError::InvalidTextDecoration {
+                placeholder: placeholder.to_string(),
+                text_decoration: text_decoration.clone(),
+            });
+        }
+
+        if let Some(letter_spacing) = &self.letter_spacing
+            && !letter_spacing.is_valid()
+            && !letter_spacing.is_keyword(&["normal"])
+        {
+            return Err(PlaceholderError::InvalidLetterSpacing {
+                placeholder: placeholder.to_string(),
+                letter_spacing: letter_spacing.to_string(),
+            });
+        }
+
+        if let Some(opacity) = self.opacity
+            && !(0.0..=1.0).contains(&opacity)
+        {
+            return Err(PlaceholderError::InvalidOpacity {
+                placeholder: placeholder.to_string(),
+                opacity,
+            });
+        }
+
+        if let Some(stroke) = &self.stroke
+            && !is_valid_color(stroke)
+        {
+            return Err(PlaceholderError::InvalidStroke {
+                placeholder: placeholder.to_string(),
+                stroke: stroke.clone(),
+            });
+        }
+
+        if let Some(stroke_width) = &self.stroke_width
+            && !stroke_width.number().is_some_and(|number| number >= 0.0)
+        {
+            return Err(PlaceholderError::InvalidStrokeWidth {
+                placeholder: placeholder.to_string(),
+                stroke_width: stroke_width.to_string(),
+            });
+        }
+
+        if let Some(baseline_shift) = &self.baseline_shift
+            && !baseline_shift.is_valid()
+            && !baseline_shift.is_keyword(&["baseline", "sub", "super"])
+        {
+            return Err(PlaceholderError::InvalidBaselineShift {
+                placeholder: placeholder.to_string(),
+                baseline_shift: baseline_shift.to_string(),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns whether a value is `none` or a list of distinct text decoration lines.
+///
+/// # Arguments
+///
+/// - `value` - The value to check.
+fn is_valid_text_decoration(value: &str) -> bool {
+    let lines: Vec<&str> = value.split_whitespace().collect();
+
+    if lines == ["none"] {
+        return true;
+    }
+
+    !lines.is_empty()
+        && lines.iter().enumerate().all(|(i, line)| {
+            matches!(*line, "underline" | "overline" | "line-through") && !lines[..i].contains(line)
+        })
+}
+
+/// Returns whether a value is a CSS color, `none` or `currentColor`.
+///
+/// # Arguments
+///
+/// - `value` - The value to check.
+pub(crate) fn is_valid_color(value: &str) -> bool {
+    let value = value.trim();
+
+    if value.eq_ignore_ascii_case("none") || value.eq_ignore_ascii_case("currentColor") {
+        return true;
+    }
+
+    // The color parser treats non hex digits as zero, so hex colors are checked separately.
+    if let Some(hex) = value.strip_prefix('#') {
+        return matches!(hex.len(), 3 | 4 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit());
+    }
+
+    svgtypes::Color::from_str(value).is_ok()
+}
+
+/// Returns whether every named family in a font family list is loaded. Generic families are
+/// always allowed.
+///
+/// # Arguments
+///
+/// - `value` - The font family list to check.
+/// - `fontdb` - The font database that the families must be loaded in.
+fn is_loaded_font_family(value: &str, fontdb: &fontdb::Database) -> bool {
+    let Ok(families) = svgtypes::parse_font_families(value) else {
+        return false;
+    };
+
+    !families.is_empty()
+        && families.iter().all(|family| match family {
+            svgtypes::FontFamily::Named(name) => fontdb
+                .faces()
+                .any(|face| face.families.iter().any(|(n, _)| n == name)),
+            _ => true,
+        })
 }
 
 #[derive(Debug)]
@@ -129,6 +725,8 @@ pub struct UsedPlaceholders {
     pub images: Vec<String>,
     /// Shape placeholders that have been used.
     pub shapes: Vec<String>,
+    /// Placeholders that have been used by every rendered row of a repeater, keyed by repeater ID.
+    pub lists: HashMap<String, Vec<UsedPlaceholders>>,
 }
 
 impl UsedPlaceholders {
@@ -138,7 +736,217 @@ impl UsedPlaceholders {
             text: vec![],
             images: vec![],
             shapes: vec![],
+            lists: HashMap::new(),
+        }
+    }
+
+    /// Returns the placeholders used by a row of a repeater.
+    ///
+    /// # Arguments
+    ///
+    /// - `repeater` - The ID of the repeater.
+    /// - `row` - The index of the row.
+    pub fn row_mut(&mut self, repeater: &str, row: usize) -> &mut UsedPlaceholders {
+        let rows = self.lists.entry(repeater.to_string()).or_default();
+
+        if rows.len() <= row {
+            rows.resize_with(row + 1, UsedPlaceholders::new);
         }
+
+        &mut rows[row]
     }
 }
 
+impl Default for UsedPlaceholders {
+    fn default() -> Self {
+        UsedPlaceholders::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+
+    /// Returns a font database with a single face of the `Test Sans` family, so the tests do not
+    /// depend on the installed fonts.
+    fn fontdb() -> fontdb::Database {
+        let mut fontdb = fontdb::Database::new();
+        fontdb.push_face_info(fontdb::FaceInfo {
+            id: fontdb::ID::dummy(),
+            source: fontdb::Source::Binary(Arc::new(Vec::<u8>::new())),
+            index: 0,
+            families: vec![("Test Sans".to_string(), fontdb::Language::English_UnitedStates)],
+            post_script_name: "TestSans".to_string(),
+            style: fontdb::Style::Normal,
+            weight: fontdb::Weight::NORMAL,
+            stretch: fontdb::Stretch::Normal,
+            monospaced: false,
+        });
+        fontdb
+    }
+
+    fn span(value: &str) -> TextSpan {
+        TextSpan::plain(value)
+    }
+
+    #[test]
+    fn rejects_attribute_injection() {
+        let fill = TextSpan { fill: Some("red\" onload=\"x".to_string()), ..span("a") };
+        assert!(matches!(fill.validate("text", &fontdb()), Err(PlaceholderError::InvalidFill { .. })));
+
+        for font_family in ["Test Sans\" onload=\"x", "\"Test Sans\"\" onload=\"x"] {
+            let font = TextSpan { font_family: Some(font_family.to_string()), ..span("a") };
+            assert!(
+                matches!(font.validate("text", &fontdb()), Err(PlaceholderError::UnknownFontFamily { .. })),
+                "{font_family}"
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_valid_style_attributes() {
+        let span = TextSpan {
+            fill: Some("#ff0".to_string()),
+            font_size: Some(Length::String("1.5em".to_string())),
+            font_weight: Some(700),
+            font_family: Some("'Test Sans', sans-serif".to_string()),
+            ..span("a")
+        };
+        assert!(span.validate("text", &fontdb()).is_ok());
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(escape_xml("plain text"), "plain text");
+        assert_eq!(
+            escape_xml("<a href=\"x\">Tom & Jerry's</a>"),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;"
+        );
+        assert_eq!(escape_xml("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn escapes_tspan_values_and_attributes() {
+        let span = TextSpan { fill: Some("a\"b".to_string()), ..span("<b>") };
+        assert_eq!(
+            span.to_tspan(),
+            "<tspan fill=\"a&quot;b\" xml:space=\"preserve\">&lt;b&gt;</tspan>"
+        );
+    }
+
+    #[test]
+    fn validates_colors() {
+        for color in [
+            "red", "#f00", "#f00a", "#ff0000", "#ff0000aa", "rgb(255, 0, 0)", "hsl(0, 100%, 50%)", "none",
+            "currentColor", " transparent ",
+        ] {
+            assert!(is_valid_color(color), "{color} is valid");
+        }
+
+        for color in ["", "#ff", "#gggggg", "#ff00000", "notacolor", "red\" onload=\"x", "url(#grad)"] {
+            assert!(!is_valid_color(color), "{color} is invalid");
+        }
+    }
+
+    #[test]
+    fn validates_loaded_font_families() {
+        let fontdb = fontdb();
+
+        assert!(is_loaded_font_family("Test Sans", &fontdb));
+        assert!(is_loaded_font_family("sans-serif", &fontdb));
+        assert!(is_loaded_font_family("'Test Sans', monospace", &fontdb));
+
+        assert!(!is_loaded_font_family("Unknown Family", &fontdb));
+        assert!(!is_loaded_font_family("Test Sans, Unknown Family", &fontdb));
+        assert!(!is_loaded_font_family("", &fontdb));
+    }
+
+    #[test]
+    fn inserts_plain_strings_as_is() {
+        let values: PlaceholderValues = serde_json::from_str(
+            r#"{"text": {"markup": "<tspan fill=\"red\">A</tspan>"}, "shapes": {"fill": "url(#grad)"}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(values.text()["markup"], "<tspan fill=\"red\">A</tspan>");
+        assert!(values.validate(&fontdb()).is_ok());
+    }
+
+    #[test]
+    fn converts_style_attributes_to_tspan() {
+        let span = TextSpan {
+            font_style: Some("italic".to_string()),
+            text_decoration: Some("underline line-through".to_string()),
+            letter_spacing: Some(Length::String("0.1em".to_string())),
+            opacity: Some(0.5),
+            stroke: Some("#000".to_string()),
+            stroke_width: Some(Length::Number(2.0)),
+            baseline_shift: Some(Length::String("super".to_string())),
+            ..span("1st")
+        };
+
+        assert_eq!(
+            span.to_tspan(),
+            "<tspan font-style=\"italic\" text-decoration=\"underline line-through\" letter-spacing=\"0.1em\" \
+             fill-opacity=\"0.5\" stroke-opacity=\"0.5\" stroke=\"#000\" stroke-width=\"2\" baseline-shift=\"super\" \
+             xml:space=\"preserve\">1st</tspan>"
+        );
+        assert!(span.validate("text", &fontdb()).is_ok());
+    }
+
+    #[test]
+    fn accepts_style_attribute_keywords_and_lengths() {
+        let span = TextSpan {
+            font_style: Some("oblique".to_string()),
+            text_decoration: Some("none".to_string()),
+            letter_spacing: Some(Length::String("normal".to_string())),
+            opacity: Some(0.0),
+            stroke_width: Some(Length::String("0".to_string())),
+            baseline_shift: Some(Length::String("-40%".to_string())),
+            ..span("a")
+        };
+        assert!(span.validate("text", &fontdb()).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_style_attributes() {
+        let validate = |span: TextSpan| span.validate("text", &fontdb());
+
+        for opacity in [-0.1, 1.5, f32::NAN] {
+            let span = TextSpan { opacity: Some(opacity), ..span("a") };
+            assert!(matches!(validate(span), Err(PlaceholderError::InvalidOpacity { .. })), "{opacity}");
+        }
+
+        for decoration in ["blink", "underline underline", "", "none underline"] {
+            let span = TextSpan { text_decoration: Some(decoration.to_string()), ..span("a") };
+            assert!(
+                matches!(validate(span), Err(PlaceholderError::InvalidTextDecoration { .. })),
+                "{decoration}"
+            );
+        }
+
+        for letter_spacing in ["wide", "1px\" onload=\"x", ""] {
+            let span = TextSpan { letter_spacing: Some(Length::String(letter_spacing.to_string())), ..span("a") };
+            assert!(
+                matches!(validate(span), Err(PlaceholderError::InvalidLetterSpacing { .. })),
+                "{letter_spacing}"
+            );
+        }
+
+        for stroke in ["notacolor", "#12", "red\" onload=\"x"] {
+            let span = TextSpan { stroke: Some(stroke.to_string()), ..span("a") };
+            assert!(matches!(validate(span), Err(PlaceholderError::InvalidStroke { .. })), "{stroke}");
+        }
+
+        let font_style = TextSpan { font_style: Some("slanted".to_string()), ..span("a") };
+        assert!(matches!(validate(font_style), Err(PlaceholderError::InvalidFontStyle { .. })));
+
+        let stroke_width = TextSpan { stroke_width: Some(Length::Number(-1.0)), ..span("a") };
+        assert!(matches!(validate(stroke_width), Err(PlaceholderError::InvalidStrokeWidth { .. })));
+
+        let baseline_shift = TextSpan { baseline_shift: Some(Length::String("up".to_string())), ..span("a") };
+        assert!(matches!(validate(baseline_shift), Err(PlaceholderError::InvalidBaselineShift { .. })));
+    }
+}
@@ -1,19 +1,35 @@
-use std::string::FromUtf8Error;
+use std::{
+    fmt::Display,
+    string::FromUtf8Error,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
 
 use image::{
-    ImageBuffer, ImageError, ImageFormat, ImageReader, Rgba,
-    imageops::{FilterType, crop, overlay, resize},
+    ImageBuffer, ImageError, Rgba,
+    imageops::{FilterType, crop, resize},
 };
-use log::warn;
 use regex::Regex;
 use resvg::{
-    tiny_skia::{Pixmap, PixmapMut},
+    tiny_skia::{FilterQuality, IntSize, Pixmap, PixmapMut, PixmapPaint, PremultipliedColorU8},
     usvg::{self, Options, Transform},
 };
 
-use crate::schema::{Fragment, Schema, SchemaFragmentType, SchemaLayout};
+use crate::cache::RenderCache;
+use crate::output::OutputOptionsError;
+use crate::report::RenderReport;
+use crate::resources::ResourcePolicy;
+use crate::schema::{
+    AssetPathError, Fragment, Schema, SchemaDynamicFragments, SchemaFragmentType, SchemaLayout, SchemaRepeater,
+    SchemaTextBounds, SchemaVisibilityRule,
+};
+use crate::text_layout::{TextFitter, text_bounding_box};
+use crate::typed_values::TypedValue;
 
-use crate::placeholders::{PlaceholderValueMap, PlaceholderValues, UsedPlaceholders};
+use crate::placeholders::{
+    PlaceholderError, PlaceholderValueMap, PlaceholderValues, TextPlaceholderValue, TextSpan,
+    UsedPlaceholders, escape_xml,
+};
 
 #[derive(Debug)]
 /** Rendering errors. */
@@ -29,6 +45,78 @@ pub enum RenderingError {
     UnknownLayoutId(u32),
     RegexError(regex::Error),
     BackgroundsNotSupported(String),
+    /// The requested scale is not positive, not finite or results in an oversized raster.
+    InvalidScale(f32),
+    /// The rendered image could not be encoded to the output format.
+    ImageEncodeError(ImageError),
+    /// The output options are invalid.
+    InvalidOutputOptions(OutputOptionsError),
+    /// A strict render found missing or unknown placeholders or unresolved variables.
+    StrictModeViolation(Box<RenderReport>),
+    /// A placeholder value is invalid.
+    PlaceholderError(PlaceholderError),
+    /// An image placeholder references a resource that the resource policy does not allow.
+    ResourceRejected(String),
+    /// An asset specified by the schema is outside of the template directory.
+    AssetOutsideTemplate(String),
+}
+
+impl Display for RenderingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderingError::FileSystemError(e) => write!(f, "Failed to read a template asset: {e}"),
+            RenderingError::UTF8EncodingError(e) => write!(f, "A fragment is not valid UTF-8: {e}"),
+            RenderingError::SVGParseError(e) => write!(f, "Failed to parse a fragment SVG: {e}"),
+            RenderingError::PixmapAllocationError => write!(f, "Failed to allocate a pixmap."),
+            RenderingError::ReadStaticPNGError => write!(f, "Failed to read a static base image."),
+            RenderingError::PngEncodeError => write!(f, "Failed to encode a PNG image."),
+            RenderingError::PngDecodeError(e) => write!(f, "Failed to decode a PNG image: {e}"),
+            RenderingError::ImageError(e) => write!(f, "Failed to load an image: {e}"),
+            RenderingError::UnknownLayoutId(id) => write!(f, "Unknown layout ID {id}."),
+            RenderingError::RegexError(e) => write!(f, "Invalid regular expression: {e}"),
+            RenderingError::BackgroundsNotSupported(message) => write!(f, "{message}"),
+            RenderingError::InvalidScale(scale) => write!(
+                f,
+                "Invalid scale {scale}. Scales must be positive and result in a raster no larger than {MAX_RASTER_DIMENSION}px."
+            ),
+            RenderingError::ImageEncodeError(e) => write!(f, "Failed to encode the output image: {e}"),
+            RenderingError::InvalidOutputOptions(e) => write!(f, "{e}"),
+            RenderingError::StrictModeViolation(report) => {
+                write!(f, "Strict render failed. {}", report.strict_violations().join(" "))
+            }
+            RenderingError::PlaceholderError(e) => write!(f, "{e}"),
+            RenderingError::ResourceRejected(message) => write!(f, "{message}"),
+            RenderingError::AssetOutsideTemplate(path) => {
+                write!(f, "The asset '{path}' is outside of the template directory.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for RenderingError {}
+
+/// The maximum width or height of a rendered raster.
+pub const MAX_RASTER_DIMENSION: u32 = 8192;
+
+impl From<PlaceholderError> for RenderingError {
+    fn from(value: PlaceholderError) -> Self {
+        RenderingError::PlaceholderError(value)
+    }
+}
+
+impl From<std::io::Error> for RenderingError {
+    fn from(value: std::io::Error) -> Self {
+        RenderingError::FileSystemError(value)
+    }
+}
+
+impl From<AssetPathError> for RenderingError {
+    fn from(value: AssetPathError) -> Self {
+        match value {
+            AssetPathError::OutsideTemplate(path) => RenderingError::AssetOutsideTemplate(path),
+            AssetPathError::Io(e) => RenderingError::FileSystemError(e),
+        }
+    }
 }
 
 /// An RGBA image buffer.
@@ -38,7 +126,7 @@ pub type ImgBuf = ImageBuffer<Rgba<u8>, Vec<u8>>;
 /** The main renderer. */
 pub struct Renderer<'a> {
     /// The schema that determines the layout of the render and all of its elements.
-    schema: Schema,
+    schema: Arc<Schema>,
     layout: SchemaLayout,
     /// Tracks placeholders that have been used.
     used_placeholders: UsedPlaceholders,
@@ -46,6 +134,26 @@ pub struct Renderer<'a> {
     values: PlaceholderValues,
     /// The usvg options to use.
     usvg_options: &'a Options<'a>,
+    /// Optional: The shared cache for template assets.
+    cache: Option<Arc<RenderCache>>,
+    /// Optional: The scale to render at. Defaults to the scale of the layout.
+    scale: Option<f32>,
+    /// Diagnostics collected during the last render.
+    report: RenderReport,
+    /// Whether missing or unknown placeholders and unresolved variables fail the render.
+    strict: bool,
+    /// Determines which resources image placeholders may reference.
+    resource_policy: ResourcePolicy,
+    /// Optional: The time that relative timestamps are relative to. Defaults to the current time.
+    reference_time: Option<SystemTime>,
+}
+
+/// A fragment to render. Fragments of repeaters are rendered once for every row.
+struct FragmentInstance<'f> {
+    /// The fragment to render.
+    fragment: &'f dyn Fragment,
+    /// Optional: The repeater of the fragment and the index of the row to render.
+    row: Option<(&'f SchemaRepeater, usize)>,
 }
 
 impl<'a> Renderer<'a> {
@@ -54,129 +162,305 @@ impl<'a> Renderer<'a> {
     /// # Arguments
     ///
     /// - `schema` - The schema that determines the layout of the render and all of its elements.
+    ///   Renderers of the same schema can share it through an [`Arc`] instead of cloning it.
     /// - `values` - The placeholder values to use.
     /// - `options` - The usvg options to use.
     pub fn build(
-        schema: Schema,
+        schema: impl Into<Arc<Schema>>,
         layout: SchemaLayout,
         values: PlaceholderValues,
         options: &'a usvg::Options<'a>,
     ) -> Self {
         Renderer {
-            schema: schema,
-            layout: layout,
+            schema: schema.into(),
+            layout,
             used_placeholders: UsedPlaceholders::new(),
             values,
             usvg_options: options,
+            cache: None,
+            scale: None,
+            report: RenderReport::default(),
+            strict: false,
+            resource_policy: ResourcePolicy::default(),
+            reference_time: None,
+        }
+    }
+
+    /// Returns the diagnostics collected during the last render.
+    pub fn report(&self) -> &RenderReport {
+        &self.report
+    }
+
+    /// Takes the diagnostics collected during the last render, leaving an empty report behind.
+    pub fn take_report(&mut self) -> RenderReport {
+        std::mem::take(&mut self.report)
+    }
+
+    /// Renders at a custom scale instead of the scale the layout was exported at. Fragments are
+    /// rasterized at the target scale and the static bases are resampled.
+    ///
+    /// # Arguments
+    ///
+    /// - `scale` - The absolute scale to render at, where `1.0` is the original design size.
+    pub fn with_scale(mut self, scale: f32) -> Self {
+        self.scale = Some(scale);
+        self
+    }
+
+    /// Returns the factor to scale the layout by to reach the requested scale.
+    fn scale_factor(&self) -> f32 {
+        match self.scale {
+            Some(scale) => scale / self.layout.scale.scale,
+            None => 1.0,
         }
     }
 
-    /// Returns the X position with respect to the content box.
+    /// Returns the size of the output raster after scaling.
+    fn raster_size(&self) -> Result<(u32, u32), RenderingError> {
+        let factor = self.scale_factor();
+        let invalid = || RenderingError::InvalidScale(self.scale.unwrap_or(self.layout.scale.scale));
+
+        if !factor.is_finite() || factor <= 0.0 {
+            return Err(invalid());
+        }
+
+        let width = (self.layout.raster_size.width as f32 * factor).round();
+        let height = (self.layout.raster_size.height as f32 * factor).round();
+
+        if width < 1.0
+            || height < 1.0
+            || width > MAX_RASTER_DIMENSION as f32
+            || height > MAX_RASTER_DIMENSION as f32
+        {
+            return Err(invalid());
+        }
+
+        Ok((width as u32, height as u32))
+    }
+
+    /// Fails renders with [`RenderingError::StrictModeViolation`] if a placeholder expected by
+    /// the layout has no value, a value is provided for a placeholder the schema does not
+    /// declare or a fragment references an undefined variable.
+    ///
+    /// # Arguments
+    ///
+    /// - `strict` - Whether to enable strict rendering.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Sets the policy that determines which resources image placeholders and the images of
+    /// fragments may reference. By default only `data:` image URIs are allowed.
+    ///
+    /// # Arguments
+    ///
+    /// - `policy` - The resource policy to use.
+    pub fn with_resource_policy(mut self, policy: ResourcePolicy) -> Self {
+        self.resource_policy = policy;
+        self
+    }
+
+    /// Sets the time that timestamps formatted as relative times are relative to, instead of the
+    /// current time.
+    ///
+    /// # Arguments
+    ///
+    /// - `time` - The reference time.
+    pub fn with_reference_time(mut self, time: SystemTime) -> Self {
+        self.reference_time = Some(time);
+        self
+    }
+
+    /// Uses a shared cache for fragment sources, static base images and parsed trees.
+    ///
+    /// # Arguments
+    ///
+    /// - `cache` - The cache to use. It should only be shared between renderers using the same
+    ///   usvg options.
+    pub fn with_cache(mut self, cache: Arc<RenderCache>) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// Returns the scaled X position with respect to the content box.
     ///
     /// # Arguments
     ///
     /// - `x` - The X position specified in the schema.
-    fn get_x(&self, x: i32) -> i64 {
-        (self.layout.content_box.raster_x as i32 + x).into()
+    fn get_x(&self, x: i32) -> f32 {
+        (self.layout.content_box.raster_x as i32).saturating_add(x) as f32 * self.scale_factor()
     }
 
-    /// Returns the Y position with respect to the content box.
+    /// Returns the scaled Y position with respect to the content box.
     ///
     /// # Arguments
     ///
     /// - `y` - The Y position specified in the schema.
-    fn get_y(&self, y: i32) -> i64 {
-        (self.layout.content_box.raster_y as i32 + y).into()
+    fn get_y(&self, y: i32) -> f32 {
+        (self.layout.content_box.raster_y as i32).saturating_add(y) as f32 * self.scale_factor()
     }
 
-    /// Creates a new pixmap for rendering fragments onto.
-    fn create_composite_pixmap(&self) -> Result<Pixmap, RenderingError> {
-        let raster_size = &self.layout.raster_size;
+    /// Parses SVG code into a tree. Images are resolved through the resource policy, so
+    /// fragments cannot reference files that image placeholders could not.
+    ///
+    /// # Arguments
+    ///
+    /// - `svg_code` - The SVG code to parse.
+    fn parse_svg(&self, svg_code: &str) -> Result<usvg::Tree, RenderingError> {
+        let options = self.usvg_options;
+        let font_resolver = &options.font_resolver;
 
-        let pixmap = Pixmap::new(raster_size.width, raster_size.height)
-            .ok_or(RenderingError::PixmapAllocationError)?;
+        let options = Options {
+            resources_dir: options.resources_dir.clone(),
+            dpi: options.dpi,
+            font_family: options.font_family.clone(),
+            font_size: options.font_size,
+            languages: options.languages.clone(),
+            shape_rendering: options.shape_rendering,
+            text_rendering: options.text_rendering,
+            image_rendering: options.image_rendering,
+            default_size: options.default_size,
+            image_href_resolver: self.resource_policy.image_href_resolver(&options.image_href_resolver),
+            font_resolver: usvg::FontResolver {
+                select_font: Box::new(|font, fontdb| (font_resolver.select_font)(font, fontdb)),
+                select_fallback: Box::new(|c, used, fontdb| (font_resolver.select_fallback)(c, used, fontdb)),
+            },
+            fontdb: options.fontdb.clone(),
+            style_sheet: options.style_sheet.clone(),
+        };
 
-        Ok(pixmap)
+        usvg::Tree::from_str(svg_code, &options).map_err(RenderingError::SVGParseError)
     }
 
-    /// Renders an SVG onto a pixmap.
+    /// Renders a parsed SVG tree onto a pixmap.
     ///
     /// # Arguments
     ///
-    /// - `svg_code` - The SVG code to render.
+    /// - `tree` - The SVG tree to render.
     /// - `x` - The X position to render at.
     /// - `y` - The Y position to render at.
+    /// - `scale` - The factor to scale the tree by.
     /// - `pixmap_mut` - The pixmap to render onto.
-    fn render_svg(
-        &self,
-        svg_code: &str,
-        x: f32,
-        y: f32,
-        pixmap_mut: &mut PixmapMut,
-    ) -> Result<(), RenderingError> {
-        // TODO: use cache
-        let tree = usvg::Tree::from_str(svg_code, &self.usvg_options)
-            .map_err(|e| RenderingError::SVGParseError(e))?;
+    fn render_tree(tree: &usvg::Tree, x: f32, y: f32, scale: f32, pixmap_mut: &mut PixmapMut) {
+        resvg::render(
+            tree,
+            Transform::from_translate(x, y).pre_scale(scale, scale),
+            pixmap_mut,
+        );
+    }
 
-        resvg::render(&tree, Transform::from_translate(x, y), pixmap_mut);
+    /// Reads the source code of a fragment SVG file, using the cache if available.
+    ///
+    /// # Arguments
+    ///
+    /// - `src` - The path of the fragment file specified by the schema.
+    fn read_fragment_source(&self, src: &str) -> Result<Arc<String>, RenderingError> {
+        let load = || -> Result<String, RenderingError> {
+            let svg_code = self.schema.read_schema_asset_file(src)?;
+            String::from_utf8(svg_code).map_err(RenderingError::UTF8EncodingError)
+        };
 
-        Ok(())
+        match &self.cache {
+            Some(cache) => cache.fragment_source(&self.schema.absolute_asset_path(src)?, load),
+            None => Ok(Arc::new(load()?)),
+        }
     }
 
-    /// Converts a pixmap to an RGBA image buffer.
-    fn pixmap_to_png(pixmap: Pixmap) -> Result<ImgBuf, RenderingError> {
-        let encoded_png = pixmap
-            .to_owned()
-            .encode_png()
-            .map_err(|_| RenderingError::PngEncodeError)?;
-        let cursor = std::io::Cursor::new(encoded_png);
+    /// Converts an RGBA image buffer to a pixmap by premultiplying its alpha.
+    ///
+    /// # Arguments
+    ///
+    /// - `img_buf` - The image buffer to convert.
+    pub fn img_buf_to_pixmap(img_buf: ImgBuf) -> Result<Pixmap, RenderingError> {
+        let size = IntSize::from_wh(img_buf.width(), img_buf.height())
+            .ok_or(RenderingError::PixmapAllocationError)?;
 
-        let mut reader = ImageReader::new(cursor);
-        reader.set_format(ImageFormat::Png);
+        let mut data = img_buf.into_raw();
+        for pixel in data.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u16;
+            if alpha != 255 {
+                for channel in &mut pixel[..3] {
+                    *channel = ((*channel as u16 * alpha + 127) / 255) as u8;
+                }
+            }
+        }
 
-        Ok(reader
-            .decode()
-            .map_err(|e| RenderingError::PngDecodeError(e))?
-            .to_rgba8())
+        Pixmap::from_vec(data, size).ok_or(RenderingError::PixmapAllocationError)
+    }
+
+    /// Converts a pixmap to an RGBA image buffer by demultiplying its alpha.
+    ///
+    /// # Arguments
+    ///
+    /// - `pixmap` - The pixmap to convert.
+    pub fn pixmap_to_img_buf(pixmap: Pixmap) -> Result<ImgBuf, RenderingError> {
+        let (width, height) = (pixmap.width(), pixmap.height());
+
+        let mut data = pixmap.take();
+        for pixel in data.chunks_exact_mut(4) {
+            if pixel[3] != 255 {
+                let color = PremultipliedColorU8::from_rgba(pixel[0], pixel[1], pixel[2], pixel[3])
+                    .ok_or(RenderingError::PixmapAllocationError)?
+                    .demultiply();
+                pixel.copy_from_slice(&[color.red(), color.green(), color.blue(), color.alpha()]);
+            }
+        }
+
+        ImgBuf::from_raw(width, height, data).ok_or(RenderingError::PixmapAllocationError)
     }
 
     /// Replaces placeholders with values in SVG code.
     ///
     /// # Arguments
     ///
-    /// - `schema_placeholders` - The placeholders specified in the schema.
     /// - `placeholder_values` - The placeholder values to use.
     /// - `svg_code` - The SVG code to replace placeholders in.
     /// - `used_placeholders` - The placeholders that have been used.
-    /// - `unused_placeholders` - The placeholders that have not been used.
     fn replace_placeholders(
-        schema_placeholders: &Vec<String>,
         placeholder_values: &PlaceholderValueMap,
         mut svg_code: String,
         used_placeholders: &mut Vec<String>,
-        mut unused_placeholders: Vec<String>,
     ) -> String {
         for (name, value) in placeholder_values {
-            svg_code = svg_code.replace(&("{".to_string() + &name + "}"), &value);
-            used_placeholders.push(name.to_string());
+            let token = "{".to_string() + name + "}";
 
-            if !schema_placeholders.contains(&name.to_string()) {
-                warn!("Placeholder '{name}' is not specified in the schema!");
+            if svg_code.contains(&token) {
+                svg_code = svg_code.replace(&token, value);
+                used_placeholders.push(name.to_string());
             }
-
-            match unused_placeholders.iter().position(|p| p == name) {
-                Some(index) => {
-                    unused_placeholders.remove(index);
-                }
-                None => (),
-            };
         }
 
-        if unused_placeholders.len() > 0 {
-            warn!("Unused placeholders: {}", unused_placeholders.join(", "));
+        svg_code
+    }
+
+    /// Replaces variables with values in SVG code without recording diagnostics.
+    ///
+    /// # Arguments
+    ///
+    /// - `svg_code` - The SVG code to replace variables in.
+    ///
+    /// Returns the SVG code with variables replaced and the names of unknown variables, which
+    /// are left in place.
+    fn substitute_variables(&self, svg_code: &str) -> Result<(String, Vec<String>), RenderingError> {
+        let re = Regex::new(r"\{variable:([a-zA-Z0-9_.]+)\}")
+            .map_err(RenderingError::RegexError)?;
+
+        let mut updated_svg_code = svg_code.to_string();
+        let mut unresolved = vec![];
+
+        for caps in re.captures_iter(svg_code) {
+            let variable = self.schema.get_variable(&caps[1]);
+            match variable {
+                Some(variable) => {
+                    updated_svg_code = updated_svg_code
+                        .replace(&format!("{{variable:{}}}", &caps[1]), &variable.value);
+                }
+                None => unresolved.push(caps[1].to_string()),
+            }
         }
 
-        svg_code
+        Ok((updated_svg_code, unresolved))
     }
 
     /// Replaces variables with values in SVG code. Must be called after replacing placeholders as
@@ -186,98 +470,440 @@ impl<'a> Renderer<'a> {
     ///
     /// - `svg_code` - The SVG code to replace variables in.
     ///
-    /// Returns the SVG code with variables replaced.
-    fn replace_variables(&self, svg_code: String) -> Result<String, RenderingError> {
-        let re = Regex::new(r"\{variable:([a-zA-Z0-9_.]+)\}")
-            .map_err(|e| RenderingError::RegexError(e))?;
+    /// Returns the SVG code with variables replaced. Unknown variables are left in place and
+    /// recorded in the render report.
+    fn replace_variables(&mut self, svg_code: String) -> Result<String, RenderingError> {
+        let (svg_code, unresolved) = self.substitute_variables(&svg_code)?;
+        self.report.unresolved_variables.extend(unresolved);
 
-        let mut updated_svg_code = svg_code.clone();
+        Ok(svg_code)
+    }
 
-        for caps in re.captures_iter(&svg_code) {
-            let variable = self.schema.get_variable(&caps[1]);
-            if let Some(variable) = variable {
-                updated_svg_code = updated_svg_code
-                    .replace(&format!("{{variable:{}}}", &caps[1]), &variable.value);
+    /// Fits the text of a fragment into its bounds, measured with the loaded fonts. The value of
+    /// the last placeholder of the fragment that has a value is the one shrunk, truncated or
+    /// wrapped.
+    ///
+    /// Plain strings are inserted as is, like in fragments without bounds. Text is fitted
+    /// character by character, which would break markup and entities, so plain strings that
+    /// contain any are not fitted.
+    ///
+    /// # Arguments
+    ///
+    /// - `fragment` - The text fragment to fit.
+    /// - `bounds` - The bounds of the fragment.
+    /// - `svg_code` - The source code of the fragment.
+    /// - `values` - The placeholder values the fragment is rendered with.
+    /// - `placeholder_values` - The text placeholder values of the fragment, including defaults.
+    fn fit_text(
+        &self,
+        fragment: &dyn Fragment,
+        bounds: &SchemaTextBounds,
+        svg_code: &str,
+        values: &PlaceholderValues,
+        placeholder_values: PlaceholderValueMap,
+    ) -> Result<PlaceholderValueMap, RenderingError> {
+        let measure = |placeholder_values: &PlaceholderValueMap| {
+            let svg_code =
+                Renderer::replace_placeholders(placeholder_values, svg_code.to_string(), &mut vec![]);
+            let (svg_code, _) = self.substitute_variables(&svg_code)?;
+            Ok(text_bounding_box(&self.parse_svg(&svg_code)?))
+        };
+
+        let Some(placeholder) = fragment
+            .placeholders()
+            .iter()
+            .rev()
+            .find(|name| placeholder_values.contains_key(*name))
+        else {
+            return Ok(placeholder_values);
+        };
+
+        let spans = match values.text.get(placeholder) {
+            Some(TextPlaceholderValue::Typed(value)) => {
+                vec![TextSpan::plain(&self.format_typed_value(fragment, placeholder, value))]
+            }
+            Some(TextPlaceholderValue::String(_)) | None => {
+                let value = &placeholder_values[placeholder];
+                if value.contains(['<', '&']) {
+                    return Ok(placeholder_values);
+                }
+
+                vec![TextSpan::plain(value)]
+            }
+            Some(value) => value.spans(),
+        };
+
+        TextFitter::new(bounds, measure).fit(placeholder_values, placeholder, &spans)
+    }
+
+    /// Converts a typed placeholder value to text with the format the fragment declares for it.
+    ///
+    /// # Arguments
+    ///
+    /// - `fragment` - The fragment the value is rendered in.
+    /// - `placeholder` - The placeholder of the value.
+    /// - `value` - The typed value.
+    fn format_typed_value(&self, fragment: &dyn Fragment, placeholder: &str, value: &TypedValue) -> String {
+        match fragment.formats().and_then(|formats| formats.get(placeholder)) {
+            Some(format) => value.format(format, self.reference_time.unwrap_or_else(SystemTime::now)),
+            None => value.to_string(),
+        }
+    }
+
+    /// Records font families referenced by SVG code that are not loaded in the font database.
+    /// The source is scanned rather than the parsed tree, as usvg drops text it has no font for.
+    ///
+    /// # Arguments
+    ///
+    /// - `svg_code` - The SVG code to search for font families.
+    fn collect_missing_fonts(&mut self, svg_code: &str) -> Result<(), RenderingError> {
+        let re = Regex::new(r#"font-family\s*(?:=\s*(?:"([^"]*)"|'([^']*)')|:\s*([^;"}<>]+))"#)
+            .map_err(RenderingError::RegexError)?;
+
+        for caps in re.captures_iter(svg_code) {
+            let Some(families) = caps.get(1).or(caps.get(2)).or(caps.get(3)) else {
+                continue;
+            };
+
+            for family in families.as_str().split(',') {
+                let name = family.trim().trim_matches(|c| c == '\'' || c == '"').trim();
+                let generic = matches!(
+                    name,
+                    "serif" | "sans-serif" | "cursive" | "fantasy" | "monospace" | "inherit"
+                );
+
+                if name.is_empty() || generic {
+                    continue;
+                }
+
+                let loaded = self
+                    .usvg_options
+                    .fontdb
+                    .faces()
+                    .any(|face| face.families.iter().any(|(n, _)| n == name));
+
+                if !loaded {
+                    self.report.missing_fonts.push(name.to_string());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records placeholder diagnostics for a group of fragments by comparing the provided
+    /// placeholder values against the placeholders declared by the schema and the ones that were
+    /// used.
+    ///
+    /// # Arguments
+    ///
+    /// - `fragments` - The fragments that were rendered with the values.
+    /// - `declared` - The fragments of every layout that the values could be for.
+    /// - `values` - The provided placeholder values.
+    /// - `used` - The placeholders that have been used.
+    /// - `computed` - The computed placeholders and their inputs, which are neither unknown nor
+    ///   unused.
+    /// - `prefix` - The prefix for reported placeholder names, which identifies repeater rows.
+    /// - `report` - The report to record the diagnostics in.
+    fn classify_placeholders(
+        fragments: &SchemaDynamicFragments,
+        declared: &[&SchemaDynamicFragments],
+        values: &PlaceholderValues,
+        used: &UsedPlaceholders,
+        computed: &[String],
+        prefix: &str,
+        report: &mut RenderReport,
+    ) {
+        let provided = [
+            (SchemaFragmentType::Text, values.text.keys().collect::<Vec<_>>(), &used.text),
+            (SchemaFragmentType::Image, values.images.keys().collect(), &used.images),
+            (SchemaFragmentType::Shape, values.shapes.keys().collect(), &used.shapes),
+        ];
+
+        for (fragment_type, provided, used) in provided {
+            let declared: Vec<String> = declared
+                .iter()
+                .flat_map(|fragments| fragments.placeholders(fragment_type.clone()))
+                .collect();
+
+            // Placeholders of hidden fragments are not expected. Defaults only apply to the
+            // fragment that declares them, so every fragment is checked on its own.
+            let expected = fragments
+                .ordered()
+                .into_iter()
+                .filter(|f| f.fragment_type() == fragment_type)
+                .filter(|f| Renderer::is_visible(*f, values));
+
+            for fragment in expected {
+                for name in fragment.placeholders() {
+                    if provided.contains(&name) {
+                        continue;
+                    }
+
+                    match fragment.defaults().contains_key(name) {
+                        true => report.defaulted_placeholders.push(format!("{prefix}{name}")),
+                        false => report.missing_placeholders.push(format!("{prefix}{name}")),
+                    }
+                }
+            }
+
+            for name in provided {
+                if computed.contains(name) {
+                    continue;
+                }
+
+                if !declared.contains(name) {
+                    report.unknown_placeholders.push(format!("{prefix}{name}"));
+                } else if !used.contains(name) {
+                    report.unused_placeholders.push(format!("{prefix}{name}"));
+                }
+            }
+        }
+    }
+
+    /// Records placeholder diagnostics for the render and every provided repeater row. Values of
+    /// rows are reported as `repeater[row].placeholder`.
+    fn collect_placeholder_report(&mut self) {
+        let declared: Vec<&SchemaDynamicFragments> =
+            self.schema.layouts.iter().map(|layout| &layout.fragments).collect();
+        let computed = self.schema.computed_placeholders();
+
+        Renderer::classify_placeholders(
+            &self.layout.fragments,
+            &declared,
+            &self.values,
+            &self.used_placeholders,
+            &computed,
+            "",
+            &mut self.report,
+        );
+
+        let unused_row = UsedPlaceholders::new();
+
+        for (id, rows) in &self.values.lists {
+            let declared: Vec<&SchemaDynamicFragments> = self
+                .schema
+                .layouts
+                .iter()
+                .filter_map(|layout| layout.repeater(id))
+                .map(|repeater| &repeater.fragments)
+                .collect();
+
+            let Some(repeater) = self.layout.repeater(id) else {
+                match declared.is_empty() {
+                    true => self.report.unknown_placeholders.push(id.clone()),
+                    false => self.report.unused_placeholders.push(id.clone()),
+                }
+                continue;
+            };
+
+            for (row, values) in rows.iter().enumerate() {
+                if row >= repeater.max_rows as usize {
+                    self.report.unused_placeholders.push(format!("{id}[{row}]"));
+                    continue;
+                }
+
+                let used = self
+                    .used_placeholders
+                    .lists
+                    .get(id)
+                    .and_then(|rows| rows.get(row))
+                    .unwrap_or(&unused_row);
+
+                Renderer::classify_placeholders(
+                    &repeater.fragments,
+                    &declared,
+                    values,
+                    used,
+                    &computed,
+                    &format!("{id}[{row}]."),
+                    &mut self.report,
+                );
+            }
+        }
+    }
+
+    /// Returns whether a visibility rule is satisfied by placeholder values.
+    ///
+    /// # Arguments
+    ///
+    /// - `rule` - The rule to evaluate.
+    /// - `values` - The placeholder values to evaluate the rule against.
+    fn evaluate_visibility(rule: &SchemaVisibilityRule, values: &PlaceholderValues) -> bool {
+        match rule {
+            SchemaVisibilityRule::Flag(name) => values.flag(name),
+            SchemaVisibilityRule::Present(name) => values.is_present(name),
+            SchemaVisibilityRule::NotEmpty(name) => values.is_not_empty(name),
+            SchemaVisibilityRule::Equals { placeholder, value } => {
+                values.plain_text(placeholder).is_some_and(|text| text == *value)
+            }
+            SchemaVisibilityRule::NotEquals { placeholder, value } => {
+                values.plain_text(placeholder).is_none_or(|text| text != *value)
+            }
+            SchemaVisibilityRule::Not(rule) => !Renderer::evaluate_visibility(rule, values),
+            SchemaVisibilityRule::All(rules) => {
+                rules.iter().all(|r| Renderer::evaluate_visibility(r, values))
+            }
+            SchemaVisibilityRule::Any(rules) => {
+                rules.iter().any(|r| Renderer::evaluate_visibility(r, values))
+            }
+        }
+    }
+
+    /// Returns whether a fragment should be rendered.
+    ///
+    /// # Arguments
+    ///
+    /// - `fragment` - The fragment to check.
+    /// - `values` - The placeholder values the fragment is rendered with.
+    fn is_visible(fragment: &dyn Fragment, values: &PlaceholderValues) -> bool {
+        fragment
+            .visible_when()
+            .is_none_or(|rule| Renderer::evaluate_visibility(rule, values))
+    }
+
+    /// Returns the fragments of a layout and of every provided repeater row in render order,
+    /// sorted by z-index from bottom to top. Rows beyond the maximum of a repeater are skipped.
+    ///
+    /// # Arguments
+    ///
+    /// - `layout` - The layout to render.
+    fn fragment_instances<'f>(&self, layout: &'f SchemaLayout) -> Vec<FragmentInstance<'f>> {
+        let mut instances: Vec<FragmentInstance> = layout
+            .fragments
+            .ordered()
+            .into_iter()
+            .map(|fragment| FragmentInstance { fragment, row: None })
+            .collect();
+
+        for repeater in &layout.repeaters {
+            let rows = self
+                .values
+                .lists
+                .get(&repeater.id)
+                .map_or(0, |rows| rows.len().min(repeater.max_rows as usize));
+
+            for row in 0..rows {
+                instances.extend(repeater.fragments.ordered().into_iter().map(|fragment| {
+                    FragmentInstance { fragment, row: Some((repeater, row)) }
+                }));
             }
         }
 
-        Ok(updated_svg_code)
+        // The sort is stable, so rows keep their order among fragments with equal z-indices.
+        instances.sort_by_key(|instance| instance.fragment.z_index());
+        instances
     }
 
-    /// Renders all specified fragments onto a pixmap. Fragments can be of any fragment type.
+    /// Renders fragment instances onto a pixmap in order. Hidden fragments are skipped. Fragments
+    /// can be of any fragment type.
     ///
     /// # Arguments
     ///
-    /// - `fragments` - The fragments to render.
+    /// - `instances` - The fragment instances to render, from bottom to top.
     /// - `fragments_pixmap_mut` - The pixmap to render onto.
-    fn render_fragments<T: Fragment>(
+    fn render_fragments(
         &mut self,
-        fragments: &Vec<T>,
+        instances: &[FragmentInstance],
         fragments_pixmap_mut: &mut PixmapMut,
     ) -> Result<(), RenderingError> {
-        for fragment in fragments {
-            let (placeholder_values, used_placeholders) = match fragment.fragment_type() {
-                SchemaFragmentType::Text => (&self.values.text(), &mut self.used_placeholders.text),
-                SchemaFragmentType::Image => {
-                    (&self.values.images(), &mut self.used_placeholders.images)
-                }
-                SchemaFragmentType::Shape => {
-                    (&self.values.shapes(), &mut self.used_placeholders.shapes)
-                }
+        for instance in instances {
+            let fragment = instance.fragment;
+            let values = match instance.row {
+                Some((repeater, row)) => &self.values.lists[&repeater.id][row],
+                None => &self.values,
             };
 
-            let svg_code = self
-                .schema
-                .read_schema_asset_file(&fragment.src())
-                .map_err(|e| RenderingError::FileSystemError(e))?;
-            let mut svg_code =
-                String::from_utf8(svg_code).map_err(|e| RenderingError::UTF8EncodingError(e))?;
+            if !Renderer::is_visible(fragment, values) {
+                continue;
+            }
 
-            let unused_placeholders = fragment.placeholders().clone();
+            let (offset_x, offset_y) = instance
+                .row
+                .map_or((0, 0), |(repeater, row)| repeater.offset(row));
+            let position = fragment.position();
+            let x = self.get_x(position.x.saturating_add(offset_x));
+            let y = self.get_y(position.y.saturating_add(offset_y));
+            let scale = self.scale_factor();
 
-            svg_code = Renderer::replace_placeholders(
-                &fragment.placeholders(),
-                &placeholder_values,
-                svg_code,
-                used_placeholders,
-                unused_placeholders,
-            );
+            let mut svg_code = self.read_fragment_source(fragment.src())?.to_string();
 
-            svg_code = self.replace_variables(svg_code)?;
+            // Fragments without placeholders render identically for the same variables, so their
+            // parsed trees can be reused. The cache key includes the substituted source, as the
+            // variables of the schema can change without the fragment file changing.
+            if fragment.placeholders().is_empty() {
+                let svg_code = self.replace_variables(svg_code)?;
+                self.collect_missing_fonts(&svg_code)?;
+                let load = || self.parse_svg(&svg_code);
 
-            let position = fragment.position();
+                let tree = match &self.cache {
+                    Some(cache) => {
+                        cache.tree(&self.schema.absolute_asset_path(fragment.src())?, &svg_code, load)?
+                    }
+                    None => Arc::new(load()?),
+                };
+
+                Renderer::render_tree(&tree, x, y, scale, fragments_pixmap_mut);
+                continue;
+            }
+
+            let mut placeholder_values = match fragment.fragment_type() {
+                SchemaFragmentType::Text => values.text(),
+                SchemaFragmentType::Image => values.images(),
+                SchemaFragmentType::Shape => values.shapes(),
+            };
+
+            // Typed values are formatted with the formats of the fragment before substitution.
+            if fragment.fragment_type() == SchemaFragmentType::Text {
+                for name in fragment.placeholders() {
+                    if let Some(TextPlaceholderValue::Typed(value)) = values.text.get(name) {
+                        let value = self.format_typed_value(fragment, name, value);
+                        placeholder_values.insert(name.clone(), escape_xml(&value));
+                    }
+                }
+            }
+
+            for (name, value) in fragment.defaults() {
+                placeholder_values.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+
+            if let Some(bounds) = fragment.text_bounds() {
+                placeholder_values = self.fit_text(fragment, bounds, &svg_code, values, placeholder_values)?;
+            }
+
+            let used_placeholders = match instance.row {
+                Some((repeater, row)) => self.used_placeholders.row_mut(&repeater.id, row),
+                None => &mut self.used_placeholders,
+            };
+            let used_placeholders = match fragment.fragment_type() {
+                SchemaFragmentType::Text => &mut used_placeholders.text,
+                SchemaFragmentType::Image => &mut used_placeholders.images,
+                SchemaFragmentType::Shape => &mut used_placeholders.shapes,
+            };
+
+            svg_code = Renderer::replace_placeholders(&placeholder_values, svg_code, used_placeholders);
+
+            svg_code = self.replace_variables(svg_code)?;
 
-            self.render_svg(
-                &svg_code,
-                self.get_x(position.x) as f32,
-                self.get_y(position.y) as f32,
-                fragments_pixmap_mut,
-            )?;
+            self.collect_missing_fonts(&svg_code)?;
+            let tree = self.parse_svg(&svg_code)?;
+            Renderer::render_tree(&tree, x, y, scale, fragments_pixmap_mut);
         }
 
         Ok(())
     }
 
-    /// Renders all fragments onto a background image.
-    fn render_to_background(&mut self, background_img: &mut ImgBuf) -> Result<(), RenderingError> {
-        let mut fragments_pixmap = self.create_composite_pixmap()?;
-        let mut fragments_pixmap_mut = fragments_pixmap.as_mut();
-        self.render_fragments(
-            &self.layout.fragments.text.clone(),
-            &mut fragments_pixmap_mut,
-        )?;
-        self.render_fragments(
-            &self.layout.fragments.images.clone(),
-            &mut fragments_pixmap_mut,
-        )?;
-        self.render_fragments(
-            &self.layout.fragments.shapes.clone(),
-            &mut fragments_pixmap_mut,
-        )?;
+    /// Renders all fragments directly onto a background pixmap in z-index order.
+    fn render_to_background(&mut self, background: &mut Pixmap) -> Result<(), RenderingError> {
+        let start_time = Instant::now();
+        let mut fragments_pixmap_mut = background.as_mut();
 
-        let fragments_img = Renderer::pixmap_to_png(fragments_pixmap)?;
+        let layout = self.layout.clone();
+        let instances = self.fragment_instances(&layout);
+        self.render_fragments(&instances, &mut fragments_pixmap_mut)?;
 
-        overlay(background_img, &fragments_img, 0, 0);
+        self.collect_placeholder_report();
+        self.report.timings.fragments = start_time.elapsed();
 
         Ok(())
     }
@@ -315,42 +941,129 @@ impl<'a> Renderer<'a> {
         ]
     }
 
-    /// Utility function for overlaying two images with a mask.
+    /// Utility function for overlaying two premultiplied pixmaps with a mask.
     ///
     /// - If the mask pixel is not white, the bottom pixel is replaced with the top pixel.
     ///
     /// Otherwise if the mask pixel is white:
     /// - If the top pixel is opaque, the bottom pixel is replaced with the top pixel.
-    /// - Otherwise if the top pixel is not completely transparent, the bottom pixel is blended with the top pixel.
+    /// - Otherwise if the top pixel is not completely transparent, the top pixel is composited over the bottom pixel.
     /// - Otherwise the bottom pixel is left unchanged.
-    fn overlay_with_mask(bottom: &mut ImgBuf, top: &ImgBuf, mask: &ImgBuf) {
-        for ((bottom_pixel, top_pixel), mask_pixel) in
-            bottom.pixels_mut().zip(top.pixels()).zip(mask.pixels())
+    fn overlay_with_mask(bottom: &mut Pixmap, top: &Pixmap, mask: &Pixmap) {
+        for ((bottom_pixel, top_pixel), mask_pixel) in bottom
+            .pixels_mut()
+            .iter_mut()
+            .zip(top.pixels())
+            .zip(mask.pixels())
         {
-            if mask_pixel[0] != 255 {
+            if mask_pixel.demultiply().red() != 255 {
                 *bottom_pixel = *top_pixel;
                 continue;
             }
 
-            let sa = top_pixel[3];
+            let sa = top_pixel.alpha();
             if sa == 255 {
                 *bottom_pixel = *top_pixel;
             } else if sa != 0 {
-                *bottom_pixel = Rgba(Renderer::blend_rgba(top_pixel.0, bottom_pixel.0));
+                let inverse_alpha = 255 - sa as u16;
+                let blend = |s: u8, d: u8| -> u8 { s + ((d as u16 * inverse_alpha + 127) / 255) as u8 };
+
+                *bottom_pixel = PremultipliedColorU8::from_rgba(
+                    blend(top_pixel.red(), bottom_pixel.red()),
+                    blend(top_pixel.green(), bottom_pixel.green()),
+                    blend(top_pixel.blue(), bottom_pixel.blue()),
+                    blend(sa, bottom_pixel.alpha()),
+                )
+                .unwrap_or(*top_pixel);
             }
         }
     }
 
     /// Loads an RGBA image buffer from a file.
     pub fn load_rgba_img_buf(&self, schema_asset_fp: &str) -> Result<ImgBuf, RenderingError> {
-        Ok(image::open(
-            &self
-                .schema
-                .absolute_asset_path(schema_asset_fp)
-                .map_err(|e| RenderingError::FileSystemError(e))?,
-        )
-        .map_err(|e| RenderingError::ImageError(e))?
-        .to_rgba8())
+        Ok(image::open(self.schema.absolute_asset_path(schema_asset_fp)?)
+            .map_err(RenderingError::ImageError)?
+            .to_rgba8())
+    }
+
+    /// Loads a premultiplied pixmap from a file, using the cache if available.
+    pub fn load_pixmap(&self, schema_asset_fp: &str) -> Result<Pixmap, RenderingError> {
+        let load = || Renderer::img_buf_to_pixmap(self.load_rgba_img_buf(schema_asset_fp)?);
+
+        match &self.cache {
+            Some(cache) => Ok(cache
+                .image(&self.schema.absolute_asset_path(schema_asset_fp)?, load)?
+                .as_ref()
+                .clone()),
+            None => load(),
+        }
+    }
+
+    /// Resamples a pixmap to the specified size.
+    ///
+    /// # Arguments
+    ///
+    /// - `pixmap` - The pixmap to resample.
+    /// - `size` - The size to resample to.
+    fn resample_pixmap(pixmap: &Pixmap, size: (u32, u32)) -> Result<Pixmap, RenderingError> {
+        let mut resampled =
+            Pixmap::new(size.0, size.1).ok_or(RenderingError::PixmapAllocationError)?;
+
+        let paint = PixmapPaint {
+            quality: FilterQuality::Bicubic,
+            ..PixmapPaint::default()
+        };
+
+        resampled.draw_pixmap(
+            0,
+            0,
+            pixmap.as_ref(),
+            &paint,
+            Transform::from_scale(
+                size.0 as f32 / pixmap.width() as f32,
+                size.1 as f32 / pixmap.height() as f32,
+            ),
+            None,
+        );
+
+        Ok(resampled)
+    }
+
+    /// Loads a static base image at the scaled raster size, using the cache if available.
+    ///
+    /// # Arguments
+    ///
+    /// - `schema_asset_fp` - The path of the static base image specified by the schema.
+    fn load_static_base(&self, schema_asset_fp: &str) -> Result<Pixmap, RenderingError> {
+        let size = self.raster_size()?;
+
+        let Some(cache) = &self.cache else {
+            let pixmap = self.load_pixmap(schema_asset_fp)?;
+
+            return match (pixmap.width(), pixmap.height()) == size {
+                true => Ok(pixmap),
+                false => Renderer::resample_pixmap(&pixmap, size),
+            };
+        };
+
+        // The resampled copy is looked up first, so the full size image is not copied for it.
+        let path = self.schema.absolute_asset_path(schema_asset_fp)?;
+        if let Some(scaled) = cache.cached_scaled_image(&path, size)? {
+            return Ok(scaled.as_ref().clone());
+        }
+
+        let pixmap = cache.image(&path, || {
+            Renderer::img_buf_to_pixmap(self.load_rgba_img_buf(schema_asset_fp)?)
+        })?;
+
+        if (pixmap.width(), pixmap.height()) == size {
+            return Ok(pixmap.as_ref().clone());
+        }
+
+        Ok(cache
+            .scaled_image(&path, size, || Renderer::resample_pixmap(&pixmap, size))?
+            .as_ref()
+            .clone())
     }
 
     fn resize_background_image(background_img: ImgBuf, size: (u32, u32)) -> ImgBuf {
@@ -379,7 +1092,7 @@ impl<'a> Renderer<'a> {
             return crop(&mut resized, 0, 0, size.0, size.1).to_image();
         }
 
-        return resized;
+        resized
     }
 
     /// Creates a translucent base image by blending the background image with the translucent base image.
@@ -387,6 +1100,16 @@ impl<'a> Renderer<'a> {
         &mut self,
         background_img: ImgBuf,
     ) -> Result<ImgBuf, RenderingError> {
+        Renderer::pixmap_to_img_buf(self.translucent_base(background_img)?)
+    }
+
+    /// Creates the translucent base pixmap that fragments are rendered onto by blending the
+    /// background image with the translucent base image.
+    ///
+    /// # Arguments
+    ///
+    /// - `background_img` - The background image, which is resized to the translucent base.
+    fn translucent_base(&mut self, background_img: ImgBuf) -> Result<Pixmap, RenderingError> {
         let background_base = match &self.layout.static_base.background {
             Some(background) => background,
             None => {
@@ -396,31 +1119,697 @@ impl<'a> Renderer<'a> {
             }
         };
 
-        let translucent_base = self.load_rgba_img_buf(&background_base.translucent)?;
-        let mask = self.load_rgba_img_buf(&background_base.mask)?;
+        let translucent_base = self.load_static_base(&background_base.translucent)?;
+        let mask = self.load_static_base(&background_base.mask)?;
+
+        let background_img =
+            Renderer::resize_background_image(background_img, (mask.width(), mask.height()));
+        let mut background = Renderer::img_buf_to_pixmap(background_img)?;
+
+        Renderer::overlay_with_mask(&mut background, &translucent_base, &mask);
+
+        Ok(background)
+    }
+
+    /// Resets the diagnostics and placeholder tracking, validates the placeholder values and
+    /// computes the computed placeholders before a render.
+    fn begin_render(&mut self) -> Result<(), RenderingError> {
+        self.report = RenderReport::default();
+        self.used_placeholders = UsedPlaceholders::new();
+
+        self.values.validate(&self.usvg_options.fontdb)?;
+        self.values.compute(&self.schema.computed);
+
+        Renderer::resolve_hrefs(&self.resource_policy, &mut self.values)?;
+
+        Ok(())
+    }
+
+    /// Replaces image placeholder values, including those of list rows, with the hrefs allowed
+    /// by a resource policy.
+    ///
+    /// # Arguments
+    ///
+    /// - `policy` - The resource policy to check the values against.
+    /// - `values` - The placeholder values to resolve.
+    fn resolve_hrefs(
+        policy: &ResourcePolicy,
+        values: &mut PlaceholderValues,
+    ) -> Result<(), RenderingError> {
+        for (name, href) in values.images.iter_mut() {
+            *href = policy.resolve_href(name, href)?;
+        }
+
+        for row in values.lists.values_mut().flatten() {
+            Renderer::resolve_hrefs(policy, row)?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders the fragments onto the static base and converts the result to the output image.
+    ///
+    /// # Arguments
+    ///
+    /// - `static_base` - The static base to render onto.
+    /// - `start_time` - When the render started.
+    fn finish_render(
+        &mut self,
+        mut static_base: Pixmap,
+        start_time: Instant,
+    ) -> Result<ImgBuf, RenderingError> {
+        self.report.timings.static_base = start_time.elapsed();
+        self.render_to_background(&mut static_base)?;
+
+        if self.strict && self.report.has_strict_violations() {
+            self.report.normalize();
+            return Err(RenderingError::StrictModeViolation(Box::new(self.take_report())));
+        }
 
-        let mut background_img =
-            Renderer::resize_background_image(background_img, mask.dimensions());
-        // let mut background_img = background_img;
+        let output_start_time = Instant::now();
+        let output = Renderer::pixmap_to_img_buf(static_base)?;
+        self.report.timings.output = output_start_time.elapsed();
+        self.report.timings.total = start_time.elapsed();
 
-        Renderer::overlay_with_mask(&mut background_img, &translucent_base, &mask);
+        self.report.normalize();
+        self.report.log_warnings();
 
-        Ok(background_img)
+        Ok(output)
     }
 
-    /// Renders the layout to the opaque base image.
+    /// Renders the layout to the opaque base image. Diagnostics are available from
+    /// [`Renderer::report`] afterwards.
     pub fn render_opaque(&mut self) -> Result<ImgBuf, RenderingError> {
+        self.begin_render()?;
+        let start_time = Instant::now();
+
         let opaque_base_src = &self.layout.static_base.default;
-        let mut opaque_base = self.load_rgba_img_buf(opaque_base_src)?;
+        let opaque_base = self.load_static_base(opaque_base_src)?;
 
-        self.render_to_background(&mut opaque_base)?;
-        Ok(opaque_base)
+        self.finish_render(opaque_base, start_time)
     }
 
     /// Renders the layout to the translucent base image using the specified background image.
+    /// Diagnostics are available from [`Renderer::report`] afterwards.
     pub fn render_translucent(&mut self, background_img: ImgBuf) -> Result<ImgBuf, RenderingError> {
-        let mut static_base = self.create_translucent_base(background_img)?;
-        self.render_to_background(&mut static_base)?;
-        Ok(static_base)
+        self.begin_render()?;
+        let start_time = Instant::now();
+
+        let static_base = self.translucent_base(background_img)?;
+
+        self.finish_render(static_base, start_time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::test_support::{self, TempDir, fragment};
+
+    /// A template directory with a white 10x10px base image.
+    fn template(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("render-{name}"));
+        dir.image("base.png", 10, 10, [255, 255, 255, 255]);
+        dir
+    }
+
+    /// Loads a schema with a single 10x10px layout of the fragments and repeaters.
+    fn load(dir: &TempDir, fragments: Value, repeaters: Value) -> Schema {
+        let mut layout = test_support::layout(1, "1x", 1.0);
+        layout["fragments"] = fragments;
+        layout["repeaters"] = repeaters;
+
+        dir.load("schema.json", &test_support::schema(vec![layout])).unwrap()
+    }
+
+    /// Renders the only layout of a schema to its opaque base.
+    fn render(schema: Schema, values: Value, strict: bool) -> Result<(ImgBuf, RenderReport), RenderingError> {
+        let options = usvg::Options::default();
+        let layout = schema.layouts[0].clone();
+        let mut renderer =
+            Renderer::build(schema, layout, placeholder_values(values), &options).with_strict(strict);
+
+        let output = renderer.render_opaque()?;
+        Ok((output, renderer.take_report()))
+    }
+
+    /// A fragment that renders the `name` text placeholder.
+    const NAME_SVG: &str =
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10"><text>{name}</text></svg>"#;
+
+    /// A fragment that fills its 10x10px with red and uses the `badge` text placeholder.
+    const BADGE_SVG: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
+        <desc>{badge}</desc><rect width="10" height="10" fill="#ff0000"/>
+    </svg>"##;
+
+    fn placeholder_values(values: Value) -> PlaceholderValues {
+        serde_json::from_value(values).unwrap()
+    }
+
+    /// Fits the text of the first text fragment of a schema into its bounds.
+    fn fit(schema: Schema, values: Value) -> PlaceholderValueMap {
+        let options = usvg::Options::default();
+        let layout = schema.layouts[0].clone();
+        let fragment = layout.fragments.text[0].clone();
+        let values = placeholder_values(values);
+        let renderer = Renderer::build(schema, layout, values.clone(), &options);
+
+        renderer
+            .fit_text(&fragment, fragment.bounds.as_ref().unwrap(), NAME_SVG, &values, values.text())
+            .unwrap()
+    }
+
+    fn text_fragments(text: Vec<Value>) -> Value {
+        test_support::fragments(text, vec![], vec![])
+    }
+
+    /// Classifies the placeholders of fragments that none of the provided values were used for.
+    fn classify(text: Vec<Value>, values: Value) -> RenderReport {
+        let fragments: SchemaDynamicFragments = serde_json::from_value(text_fragments(text)).unwrap();
+        let mut report = RenderReport::default();
+
+        Renderer::classify_placeholders(
+            &fragments,
+            &[&fragments],
+            &placeholder_values(values),
+            &UsedPlaceholders::new(),
+            &[],
+            "",
+            &mut report,
+        );
+
+        report.normalize();
+        report
+    }
+
+    #[test]
+    fn reports_missing_placeholders() {
+        let report = classify(
+            vec![fragment("name", &["name", "title"], json!({}))],
+            json!({"text": {"title": "Sir"}}),
+        );
+
+        assert_eq!(report.missing_placeholders, ["name"]);
+        assert!(report.defaulted_placeholders.is_empty());
+        assert!(report.has_strict_violations());
+    }
+
+    #[test]
+    fn reports_unknown_placeholders() {
+        let report = classify(
+            vec![fragment("name", &["name"], json!({}))],
+            json!({"text": {"name": "Steve", "nickname": "S"}}),
+        );
+
+        assert_eq!(report.unknown_placeholders, ["nickname"]);
+        assert!(report.missing_placeholders.is_empty());
+        assert!(report.has_strict_violations());
+    }
+
+    #[test]
+    fn reports_defaulted_placeholders() {
+        let report = classify(
+            vec![fragment("name", &["name"], json!({"defaults": {"name": "Anonymous"}}))],
+            json!({}),
+        );
+
+        assert_eq!(report.defaulted_placeholders, ["name"]);
+        assert!(report.missing_placeholders.is_empty());
+        assert!(!report.has_strict_violations());
+    }
+
+    #[test]
+    fn applies_defaults_per_fragment() {
+        let report = classify(
+            vec![
+                fragment("name", &["name"], json!({"defaults": {"name": "Anonymous"}})),
+                fragment("greeting", &["name"], json!({})),
+            ],
+            json!({}),
+        );
+
+        assert_eq!(report.defaulted_placeholders, ["name"]);
+        assert_eq!(report.missing_placeholders, ["name"]);
+        assert!(report.has_strict_violations());
+    }
+
+    #[test]
+    fn ignores_placeholders_of_hidden_fragments() {
+        let report = classify(
+            vec![fragment("title", &["title"], json!({"visibleWhen": {"flag": "show_title"}}))],
+            json!({"flags": {"show_title": false}}),
+        );
+
+        assert!(report.missing_placeholders.is_empty());
+        assert!(!report.has_strict_violations());
+    }
+
+    #[test]
+    fn fails_strict_renders_with_violations() {
+        let dir = template("strict");
+        dir.write("fragments/name.svg", NAME_SVG);
+        let fragments = text_fragments(vec![fragment("name", &["name"], json!({}))]);
+        let schema = load(&dir, fragments, json!([]));
+
+        let (_, report) = render(schema.clone(), json!({}), false).unwrap();
+        assert_eq!(report.missing_placeholders, ["name"]);
+
+        match render(schema, json!({}), true) {
+            Err(RenderingError::StrictModeViolation(report)) => {
+                assert_eq!(report.missing_placeholders, ["name"]);
+            }
+            other => panic!("Expected a strict mode violation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn passes_strict_renders_without_violations() {
+        let dir = template("strict-pass");
+        dir.write("fragments/name.svg", NAME_SVG);
+        let defaults = json!({"defaults": {"name": "Anonymous"}});
+        let schema = load(&dir, text_fragments(vec![fragment("name", &["name"], defaults)]), json!([]));
+
+        let (_, report) = render(schema, json!({}), true).unwrap();
+
+        assert_eq!(report.defaulted_placeholders, ["name"]);
+    }
+
+    #[test]
+    fn reports_missing_unused_and_defaulted_placeholders() {
+        let dir = template("report");
+        dir.write("fragments/name.svg", NAME_SVG);
+        dir.write("fragments/title.svg", NAME_SVG);
+        dir.write("fragments/badge.svg", NAME_SVG);
+
+        let schema = load(&dir, 
+            text_fragments(vec![
+                fragment("name", &["name"], json!({"defaults": {"name": "Anonymous"}})),
+                fragment("title", &["title"], json!({})),
+                fragment("badge", &["badge"], json!({"visibleWhen": {"flag": "show_badge"}})),
+            ]),
+            json!([]),
+        );
+
+        let values = json!({"text": {"badge": "Admin"}, "flags": {"show_badge": false}});
+        let (_, report) = render(schema, values, false).unwrap();
+
+        assert_eq!(report.missing_placeholders, ["title"]);
+        assert_eq!(report.defaulted_placeholders, ["name"]);
+        assert_eq!(report.unused_placeholders, ["badge"]);
+        assert!(report.unknown_placeholders.is_empty());
+    }
+
+    fn visible(rule: Value, values: Value) -> bool {
+        let rule: SchemaVisibilityRule = serde_json::from_value(rule).unwrap();
+        Renderer::evaluate_visibility(&rule, &placeholder_values(values))
+    }
+
+    #[test]
+    fn evaluates_flags() {
+        let rule = json!({"flag": "show_badge"});
+
+        assert!(visible(rule.clone(), json!({"flags": {"show_badge": true}})));
+        assert!(!visible(rule.clone(), json!({"flags": {"show_badge": false}})));
+        assert!(!visible(rule, json!({})));
+    }
+
+    #[test]
+    fn evaluates_placeholder_comparisons() {
+        let equals = json!({"equals": {"placeholder": "rank", "value": "Admin"}});
+        let not_equals = json!({"notEquals": {"placeholder": "rank", "value": "Admin"}});
+        let admin = json!({"text": {"rank": "Admin"}});
+        let styled_admin =
+            json!({"text": {"rank": [{"value": "Ad", "fill": "#ff0000"}, {"value": "min"}]}});
+        let member = json!({"text": {"rank": "Member"}});
+
+        assert!(visible(equals.clone(), admin.clone()));
+        assert!(visible(equals.clone(), styled_admin));
+        assert!(!visible(equals.clone(), member.clone()));
+        assert!(!visible(equals, json!({})));
+
+        assert!(!visible(not_equals.clone(), admin));
+        assert!(visible(not_equals.clone(), member));
+        assert!(visible(not_equals, json!({})));
+    }
+
+    #[test]
+    fn evaluates_combined_rules() {
+        let rule = json!({
+            "all": [{"flag": "show_badge"}, {"not": {"equals": {"placeholder": "badge", "value": ""}}}]
+        });
+
+        assert!(visible(rule.clone(), json!({"flags": {"show_badge": true}, "text": {"badge": "VIP"}})));
+        assert!(!visible(rule.clone(), json!({"flags": {"show_badge": true}, "text": {"badge": ""}})));
+        assert!(!visible(rule, json!({"text": {"badge": "VIP"}})));
+    }
+
+    #[test]
+    fn skips_hidden_fragments() {
+        let dir = template("hidden");
+        dir.write("fragments/badge.svg", BADGE_SVG);
+        let badge = fragment("badge", &["badge"], json!({"visibleWhen": {"flag": "show_badge"}}));
+        let schema = load(&dir, text_fragments(vec![badge]), json!([]));
+
+        let (output, report) = render(schema.clone(), json!({}), true).unwrap();
+
+        assert_eq!(output.get_pixel(5, 5), &Rgba([255, 255, 255, 255]));
+        assert!(report.missing_placeholders.is_empty());
+
+        let values = json!({"flags": {"show_badge": true}, "text": {"badge": "VIP"}});
+        let (output, _) = render(schema, values, true).unwrap();
+
+        assert_eq!(output.get_pixel(5, 5), &Rgba([255, 0, 0, 255]));
+    }
+
+    /// A schema with a repeater of a 2x2px red square that renders at most 2 rows, 3px apart.
+    fn repeater_schema(dir: &TempDir) -> Schema {
+        dir.write(
+            "fragments/row.svg",
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="2" height="2">
+                <rect width="2" height="2" fill="#ff0000"/>
+            </svg>"##,
+        );
+
+        let row = fragment("row", &[], json!({"position": {"x": 1, "y": 1}}));
+        let repeater = json!({
+            "id": "rows",
+            "fragments": text_fragments(vec![row]),
+            "rowOffset": {"x": 2, "y": 3},
+            "maxRows": 2
+        });
+
+        load(dir, text_fragments(vec![]), json!([repeater]))
+    }
+
+    #[test]
+    fn expands_repeater_rows_up_to_max_rows() {
+        let dir = template("repeater-rows");
+        let schema = repeater_schema(&dir);
+        let options = usvg::Options::default();
+        let layout = schema.layouts[0].clone();
+        let values = placeholder_values(json!({"lists": {"rows": [{}, {}, {}]}}));
+        let renderer = Renderer::build(schema, layout.clone(), values, &options);
+
+        let offsets: Vec<(usize, (i32, i32))> = renderer
+            .fragment_instances(&layout)
+            .into_iter()
+            .map(|instance| instance.row.expect("Only repeater fragments are rendered."))
+            .map(|(repeater, row)| (row, repeater.offset(row)))
+            .collect();
+
+        assert_eq!(offsets, [(0, (0, 0)), (1, (2, 3))]);
+    }
+
+    #[test]
+    fn renders_repeater_rows_at_their_offsets() {
+        let dir = template("repeater-render");
+        let schema = repeater_schema(&dir);
+
+        let values = json!({"lists": {"rows": [{}, {}, {}]}});
+        let (output, report) = render(schema, values, false).unwrap();
+
+        let red = Rgba([255, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+
+        assert_eq!(output.get_pixel(1, 1), &red);
+        assert_eq!(output.get_pixel(2, 2), &red);
+        assert_eq!(output.get_pixel(3, 4), &red);
+        assert_eq!(output.get_pixel(4, 5), &red);
+        // The third row would be at (5, 7), but only 2 rows are rendered.
+        assert_eq!(output.get_pixel(5, 7), &white);
+        assert_eq!(output.get_pixel(0, 0), &white);
+        assert_eq!(report.unused_placeholders, ["rows[2]"]);
+    }
+
+    /// Builds a single row image of straight RGBA pixels.
+    fn row_image(pixels: &[[u8; 4]]) -> ImgBuf {
+        ImgBuf::from_fn(pixels.len() as u32, 1, |x, _| Rgba(pixels[x as usize]))
+    }
+
+    /// Asserts that every channel of two pixels differs by at most a tolerance.
+    fn assert_close(actual: [u8; 4], expected: [u8; 4], tolerance: u8, context: &str) {
+        let close = actual.iter().zip(expected).all(|(a, e)| a.abs_diff(e) <= tolerance);
+        assert!(close, "{context}: {actual:?} is not within {tolerance} of {expected:?}");
+    }
+
+    #[test]
+    fn round_trips_premultiplied_pixels() {
+        let pixels =
+            [[200, 100, 50, 128], [255, 255, 255, 255], [12, 34, 56, 255], [90, 180, 30, 64], [0, 0, 0, 0]];
+
+        let pixmap = Renderer::img_buf_to_pixmap(row_image(&pixels)).unwrap();
+
+        assert_eq!(pixmap.pixel(0, 0), PremultipliedColorU8::from_rgba(100, 50, 25, 128));
+        assert_eq!(pixmap.pixel(2, 0), PremultipliedColorU8::from_rgba(12, 34, 56, 255));
+
+        let img_buf = Renderer::pixmap_to_img_buf(pixmap).unwrap();
+
+        for (x, pixel) in pixels.iter().enumerate() {
+            let actual = img_buf.get_pixel(x as u32, 0).0;
+
+            // Premultiplying quantizes the channels of translucent pixels to steps of 255 / alpha,
+            // so they round-trip to within half a step.
+            assert_eq!(actual[3], pixel[3]);
+            match pixel[3] {
+                0 => assert_eq!(actual, [0, 0, 0, 0]),
+                255 => assert_eq!(actual, *pixel),
+                alpha => {
+                    let tolerance = (255 / alpha).div_ceil(2);
+                    assert_close(actual, *pixel, tolerance, &format!("pixel {x}"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn composites_like_straight_alpha_blending() {
+        let bottoms = [[255, 255, 255, 255], [20, 120, 220, 255], [200, 40, 90, 200], [10, 200, 100, 128]];
+        let tops = [[0, 0, 0, 0], [255, 0, 0, 64], [40, 160, 240, 128], [120, 60, 30, 200], [5, 250, 125, 255]];
+
+        let (bottom, top): (Vec<[u8; 4]>, Vec<[u8; 4]>) = bottoms
+            .iter()
+            .flat_map(|bottom| tops.iter().map(move |top| (*bottom, *top)))
+            .unzip();
+        let mask = vec![[255, 255, 255, 255]; bottom.len()];
+
+        let mut composited = Renderer::img_buf_to_pixmap(row_image(&bottom)).unwrap();
+        let top_pixmap = Renderer::img_buf_to_pixmap(row_image(&top)).unwrap();
+        let mask_pixmap = Renderer::img_buf_to_pixmap(row_image(&mask)).unwrap();
+
+        Renderer::overlay_with_mask(&mut composited, &top_pixmap, &mask_pixmap);
+        let composited = Renderer::pixmap_to_img_buf(composited).unwrap();
+
+        for (x, (bottom, top)) in bottom.iter().zip(&top).enumerate() {
+            let expected = Renderer::blend_rgba(*top, *bottom);
+            let actual = composited.get_pixel(x as u32, 0).0;
+
+            assert_close(actual, expected, 1, &format!("{top:?} over {bottom:?}"));
+        }
+    }
+
+    #[test]
+    fn replaces_pixels_outside_of_the_mask() {
+        let mut bottom = Renderer::img_buf_to_pixmap(row_image(&[[255, 255, 255, 255]; 2])).unwrap();
+        let top = Renderer::img_buf_to_pixmap(row_image(&[[40, 160, 240, 128]; 2])).unwrap();
+        let mask = Renderer::img_buf_to_pixmap(row_image(&[[0, 0, 0, 255], [255, 255, 255, 255]])).unwrap();
+
+        Renderer::overlay_with_mask(&mut bottom, &top, &mask);
+
+        assert_eq!(bottom.pixel(0, 0), top.pixel(0, 0));
+        assert_ne!(bottom.pixel(1, 0), top.pixel(1, 0));
+    }
+
+    /// A schema with a 1x layout and a 2x layout of a 2x2px red square at (3, 3).
+    fn scaled_schema(dir: &TempDir) -> Schema {
+        dir.write(
+            "fragments/dot.svg",
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="2" height="2">
+                <rect width="2" height="2" fill="#ff0000"/>
+            </svg>"##,
+        );
+
+        let dot = fragment("dot", &[], json!({"position": {"x": 3, "y": 3}}));
+        let layouts = [(1, "1x", 1.0), (2, "2x", 2.0)].map(|(id, name, scale)| {
+            let mut layout = test_support::layout(id, name, scale);
+            layout["fragments"] = text_fragments(vec![dot.clone()]);
+            layout
+        });
+
+        dir.load("schema.json", &test_support::schema(layouts.to_vec())).unwrap()
+    }
+
+    /// A renderer of a layout of a schema without placeholder values.
+    fn renderer<'a>(schema: &Schema, layout: usize, options: &'a usvg::Options<'a>) -> Renderer<'a> {
+        Renderer::build(schema.clone(), schema.layouts[layout].clone(), placeholder_values(json!({})), options)
+    }
+
+    #[test]
+    fn scales_raster_size() {
+        let dir = template("raster-size");
+        let schema = scaled_schema(&dir);
+        let options = usvg::Options::default();
+        let raster_size = |layout: usize, scale: Option<f32>| {
+            let mut renderer = renderer(&schema, layout, &options);
+            if let Some(scale) = scale {
+                renderer = renderer.with_scale(scale);
+            }
+            renderer.raster_size()
+        };
+
+        assert_eq!(raster_size(0, None).unwrap(), (10, 10));
+        assert_eq!(raster_size(0, Some(2.5)).unwrap(), (25, 25));
+        assert_eq!(raster_size(1, None).unwrap(), (20, 20));
+        assert_eq!(raster_size(1, Some(3.0)).unwrap(), (30, 30));
+        assert_eq!(raster_size(1, Some(0.5)).unwrap(), (5, 5));
+    }
+
+    #[test]
+    fn rejects_invalid_scales() {
+        let dir = template("invalid-scale");
+        let schema = scaled_schema(&dir);
+        let options = usvg::Options::default();
+        let too_large = (MAX_RASTER_DIMENSION + 1) as f32 / 10.0;
+
+        for scale in [0.0, -1.0, f32::NAN, f32::INFINITY, 0.01, too_large] {
+            let mut renderer = renderer(&schema, 0, &options).with_scale(scale);
+
+            assert!(matches!(renderer.raster_size(), Err(RenderingError::InvalidScale(_))), "{scale}");
+            assert!(matches!(renderer.render_opaque(), Err(RenderingError::InvalidScale(_))), "{scale}");
+        }
+
+        let max_scale = MAX_RASTER_DIMENSION as f32 / 10.0;
+        let renderer = renderer(&schema, 0, &options).with_scale(max_scale);
+        assert_eq!(renderer.raster_size().unwrap(), (MAX_RASTER_DIMENSION, MAX_RASTER_DIMENSION));
+    }
+
+    #[test]
+    fn scales_fragment_positions() {
+        let dir = template("scaled-positions");
+        let schema = scaled_schema(&dir);
+        let options = usvg::Options::default();
+        let mut renderer = renderer(&schema, 0, &options).with_scale(2.0);
+
+        let output = renderer.render_opaque().unwrap();
+        let red = Rgba([255, 0, 0, 255]);
+        let white = Rgba([255, 255, 255, 255]);
+
+        // The square at (3, 3) of 2x2px is drawn at (6, 6) of 4x4px.
+        assert_eq!(output.dimensions(), (20, 20));
+        assert_eq!(output.get_pixel(6, 6), &red);
+        assert_eq!(output.get_pixel(9, 9), &red);
+        assert_eq!(output.get_pixel(5, 5), &white);
+        assert_eq!(output.get_pixel(10, 10), &white);
+    }
+
+    #[test]
+    fn picks_layouts_for_scales() {
+        let dir = template("layout-for-scale");
+        let schema = scaled_schema(&dir);
+        let layout_id = |scale: f32| schema.layout_for_scale(scale).map(|layout| layout.id);
+
+        // The smallest layout exported at or above the scale is picked.
+        assert_eq!(layout_id(0.5), Some(1));
+        assert_eq!(layout_id(1.0), Some(1));
+        assert_eq!(layout_id(1.5), Some(2));
+        assert_eq!(layout_id(2.0), Some(2));
+        // Above every layout, the largest one is picked.
+        assert_eq!(layout_id(3.0), Some(2));
+        // Without a scale, the default layout is used.
+        assert_eq!(schema.default_layout().map(|layout| layout.id), Some(1));
+        assert_eq!(schema.layout_by_scale_name("2x").map(|layout| layout.id), Some(2));
+        assert!(schema.layout_by_scale_name("3x").is_none());
+    }
+
+    /// A fragment that fills its 10x10px with a color.
+    fn fill_svg(color: &str) -> String {
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="10" height="10">
+                <rect width="10" height="10" fill="{color}"/>
+            </svg>"#
+        )
+    }
+
+    /// Renders a red text fragment, a green image fragment and a blue shape fragment with
+    /// z-indices and returns the color on top.
+    fn top_color(name: &str, z_indices: [i32; 3]) -> Rgba<u8> {
+        let dir = template(name);
+        dir.write("fragments/text.svg", fill_svg("#ff0000"));
+        dir.write("fragments/image.svg", fill_svg("#00ff00"));
+        dir.write("fragments/shape.svg", fill_svg("#0000ff"));
+
+        let [text, image, shape] = z_indices.map(|z_index| json!({"zIndex": z_index}));
+        let fragments = test_support::fragments(
+            vec![fragment("text", &[], text)],
+            vec![fragment("image", &[], image)],
+            vec![test_support::shape_fragment("shape", &[], shape)],
+        );
+
+        let (output, _) = render(load(&dir, fragments, json!([])), json!({}), false).unwrap();
+        *output.get_pixel(5, 5)
+    }
+
+    #[test]
+    fn renders_fragments_by_z_index() {
+        let (red, green, blue) = (Rgba([255, 0, 0, 255]), Rgba([0, 255, 0, 255]), Rgba([0, 0, 255, 255]));
+
+        // A shape with a lower z-index is drawn under text and images.
+        assert_eq!(top_color("z-text", [2, 1, 0]), red);
+        assert_eq!(top_color("z-image", [1, 2, 0]), green);
+        assert_eq!(top_color("z-shape", [0, 1, 2]), blue);
+        assert_eq!(top_color("z-negative", [0, 0, -1]), green);
+        // Fragments with equal z-indices are drawn as text, images and then shapes.
+        assert_eq!(top_color("z-tie", [0, 0, 0]), blue);
+        assert_eq!(top_color("z-tie-text-image", [1, 1, 0]), green);
+    }
+
+    #[test]
+    fn keeps_declaration_order_of_equal_z_indices() {
+        let text = (0..5).map(|i| fragment(&format!("text-{i}"), &[], json!({"zIndex": i % 2}))).collect();
+        let fragments: SchemaDynamicFragments = serde_json::from_value(text_fragments(text)).unwrap();
+
+        let ids: Vec<&str> = fragments.ordered().into_iter().map(|f| f.id().as_str()).collect();
+
+        assert_eq!(ids, ["text-0", "text-2", "text-4", "text-1", "text-3"]);
+    }
+
+    #[test]
+    fn keeps_row_order_of_equal_z_indices() {
+        let dir = template("row-order");
+        let schema = repeater_schema(&dir);
+        let options = usvg::Options::default();
+        let values = placeholder_values(json!({"lists": {"rows": [{}, {}]}}));
+        let mut layout = schema.layouts[0].clone();
+        layout.fragments.text.push(serde_json::from_value(fragment("row", &[], json!({}))).unwrap());
+        let renderer = Renderer::build(schema, layout.clone(), values, &options);
+
+        let order: Vec<(&str, Option<usize>)> = renderer
+            .fragment_instances(&layout)
+            .into_iter()
+            .map(|instance| (instance.fragment.id().as_str(), instance.row.map(|(_, row)| row)))
+            .collect();
+
+        // The fragments of the layout come before repeater rows, which keep their order.
+        assert_eq!(order, [("row", None), ("row", Some(0)), ("row", Some(1))]);
+    }
+
+    #[test]
+    fn inserts_plain_strings_with_markup_in_bounded_fragments_as_is() {
+        let dir = template("bounded-markup");
+        dir.write("fragments/name.svg", NAME_SVG);
+        let bounds = json!({"width": 10, "overflow": {"mode": "wrap"}});
+        let name = fragment("name", &["name"], json!({"bounds": bounds}));
+        let schema = load(&dir, text_fragments(vec![name]), json!([]));
+
+        for value in [r#"<tspan fill="red">Steve</tspan>"#, "Steve &amp; Alex", "A < B"] {
+            let values = fit(schema.clone(), json!({"text": {"name": value}}));
+            assert_eq!(values["name"], value);
+        }
+
+        // Plain strings without markup are still fitted, so wrapped text is laid out in lines.
+        let values = fit(schema, json!({"text": {"name": "Steve's \"name\""}}));
+        assert!(values["name"].starts_with("<tspan x="), "{}", values["name"]);
+        assert!(values["name"].contains("Steve&apos;s &quot;name&quot;"), "{}", values["name"]);
     }
 }
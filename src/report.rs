@@ -0,0 +1,186 @@
+use std::time::Duration;
+
+use log::warn;
+use serde::{Serialize, Serializer};
+
+/// Serializes a duration as fractional milliseconds.
+fn serialize_millis<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64() * 1000.0)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+/// The time spent in each stage of a render.
+pub struct RenderTimings {
+    /// Loading, resampling and compositing the static base images.
+    #[serde(serialize_with = "serialize_millis")]
+    pub static_base: Duration,
+    /// Substituting, parsing and rendering the fragments.
+    #[serde(serialize_with = "serialize_millis")]
+    pub fragments: Duration,
+    /// Converting the composited pixmap to the output image.
+    #[serde(serialize_with = "serialize_millis")]
+    pub output: Duration,
+    /// The total render time.
+    #[serde(serialize_with = "serialize_millis")]
+    pub total: Duration,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+/// Diagnostics collected during a render.
+pub struct RenderReport {
+    /// Placeholders expected by the rendered layout that no value was provided for.
+    pub missing_placeholders: Vec<String>,
+    /// Placeholders expected by the rendered layout that no value was provided for, but that the
+    /// fragment using them has a default value for.
+    pub defaulted_placeholders: Vec<String>,
+    /// Provided placeholder values that are not declared anywhere in the schema.
+    pub unknown_placeholders: Vec<String>,
+    /// Provided placeholder values that are declared in the schema but were not used by the
+    /// rendered layout.
+    pub unused_placeholders: Vec<String>,
+    /// Variables referenced by fragments that are not defined in the schema.
+    pub unresolved_variables: Vec<String>,
+    /// Font families referenced by fragments that are not loaded in the font database.
+    pub missing_fonts: Vec<String>,
+    /// The time spent in each stage of the render.
+    pub timings: RenderTimings,
+}
+
+impl RenderReport {
+    /// Returns whether the report contains any warnings.
+    pub fn has_warnings(&self) -> bool {
+        !(self.missing_placeholders.is_empty()
+            && self.unknown_placeholders.is_empty()
+            && self.unused_placeholders.is_empty()
+            && self.unresolved_variables.is_empty()
+            && self.missing_fonts.is_empty())
+    }
+
+    /// Returns whether the report contains any problems that fail a strict render. Unused
+    /// placeholders and missing fonts are not considered violations.
+    pub fn has_strict_violations(&self) -> bool {
+        !(self.missing_placeholders.is_empty()
+            && self.unknown_placeholders.is_empty()
+            && self.unresolved_variables.is_empty())
+    }
+
+    /// Returns a description of every problem that fails a strict render.
+    pub fn strict_violations(&self) -> Vec<String> {
+        let lists = [
+            ("Missing placeholders", &self.missing_placeholders),
+            ("Unknown placeholders", &self.unknown_placeholders),
+            ("Unresolved variables", &self.unresolved_variables),
+        ];
+
+        lists
+            .into_iter()
+            .filter(|(_, list)| !list.is_empty())
+            .map(|(label, list)| format!("{label}: {}.", list.join(", ")))
+            .collect()
+    }
+
+    /// Sorts and removes duplicate entries from every list in the report.
+    pub(crate) fn normalize(&mut self) {
+        for list in [
+            &mut self.missing_placeholders,
+            &mut self.defaulted_placeholders,
+            &mut self.unknown_placeholders,
+            &mut self.unused_placeholders,
+            &mut self.unresolved_variables,
+            &mut self.missing_fonts,
+        ] {
+            list.sort();
+            list.dedup();
+        }
+    }
+
+    /// Logs a warning for every non-empty list in the report.
+    pub fn log_warnings(&self) {
+        let lists = [
+            ("Missing placeholders", &self.missing_placeholders),
+            ("Placeholders not specified in the schema", &self.unknown_placeholders),
+            ("Unused placeholders", &self.unused_placeholders),
+            ("Unresolved variables", &self.unresolved_variables),
+            ("Missing fonts", &self.missing_fonts),
+        ];
+
+        for (label, list) in lists {
+            if !list.is_empty() {
+                warn!("{label}: {}", list.join(", "));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn normalizes_lists() {
+        let mut report = RenderReport {
+            missing_placeholders: names(&["title", "name", "title"]),
+            defaulted_placeholders: names(&["b", "a", "a"]),
+            unused_placeholders: names(&["rows[1].name", "rows[0].name"]),
+            ..Default::default()
+        };
+
+        report.normalize();
+
+        assert_eq!(report.missing_placeholders, ["name", "title"]);
+        assert_eq!(report.defaulted_placeholders, ["a", "b"]);
+        assert_eq!(report.unused_placeholders, ["rows[0].name", "rows[1].name"]);
+    }
+
+    #[test]
+    fn only_fails_strict_renders_on_violations() {
+        let report = RenderReport {
+            defaulted_placeholders: names(&["name"]),
+            unused_placeholders: names(&["title"]),
+            missing_fonts: names(&["Inter"]),
+            ..Default::default()
+        };
+
+        assert!(report.has_warnings());
+        assert!(!report.has_strict_violations());
+        assert!(report.strict_violations().is_empty());
+
+        let report = RenderReport {
+            missing_placeholders: names(&["name", "title"]),
+            unresolved_variables: names(&["color"]),
+            ..Default::default()
+        };
+
+        assert!(report.has_strict_violations());
+        assert_eq!(
+            report.strict_violations(),
+            ["Missing placeholders: name, title.", "Unresolved variables: color."]
+        );
+    }
+
+    #[test]
+    fn does_not_warn_about_defaulted_placeholders() {
+        let report = RenderReport { defaulted_placeholders: names(&["name"]), ..Default::default() };
+
+        assert!(!report.has_warnings());
+    }
+
+    #[test]
+    fn serializes_timings_as_milliseconds() {
+        let report = RenderReport {
+            missing_placeholders: names(&["name"]),
+            timings: RenderTimings { total: Duration::from_micros(1500), ..Default::default() },
+            ..Default::default()
+        };
+
+        let json = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(json["missing_placeholders"], serde_json::json!(["name"]));
+        assert_eq!(json["defaulted_placeholders"], serde_json::json!([]));
+        assert_eq!(json["timings"]["total"], 1.5);
+    }
+}
@@ -0,0 +1,260 @@
+use std::path::{Path, PathBuf};
+
+use log::warn;
+use resvg::usvg::ImageHrefResolver;
+
+use crate::render::RenderingError;
+
+#[derive(Debug, Clone, Default)]
+/// Determines which resources image placeholders, and any other image in a rendered fragment, may
+/// reference. By default only `data:` image URIs are allowed, so callers cannot make the renderer
+/// read files off the host.
+pub struct ResourcePolicy {
+    /// Optional: A directory that image placeholders may reference files in.
+    asset_dir: Option<PathBuf>,
+}
+
+impl ResourcePolicy {
+    /// Creates a policy that only allows `data:` image URIs.
+    pub fn new() -> Self {
+        ResourcePolicy::default()
+    }
+
+    /// Additionally allows image placeholders to reference files inside a directory. Relative
+    /// paths are resolved against the directory and symlinks are followed before checking that
+    /// the file is inside it.
+    ///
+    /// # Arguments
+    ///
+    /// - `asset_dir` - The directory to allow files from.
+    pub fn with_asset_dir(mut self, asset_dir: impl Into<PathBuf>) -> Self {
+        self.asset_dir = Some(asset_dir.into());
+        self
+    }
+
+    /// Returns the allowlisted asset directory, if any.
+    pub fn asset_dir(&self) -> Option<&Path> {
+        self.asset_dir.as_deref()
+    }
+
+    /// Checks an image placeholder value against the policy and returns the href to substitute
+    /// into the fragment. Files are returned as absolute paths, so they do not depend on the
+    /// resources directory of the usvg options.
+    ///
+    /// # Arguments
+    ///
+    /// - `placeholder` - The ID of the image placeholder, used in errors.
+    /// - `href` - The placeholder value.
+    pub fn resolve_href(&self, placeholder: &str, href: &str) -> Result<String, RenderingError> {
+        let reject = |reason: &str| {
+            RenderingError::ResourceRejected(format!(
+                "Image placeholder '{placeholder}' was rejected: {reason}"
+            ))
+        };
+
+        let href = href.trim();
+
+        // Hrefs are substituted into attributes, so they must not be able to close them.
+        if href.contains(['"', '\'', '<', '>', '&']) {
+            return Err(reject("it contains characters that are not allowed in hrefs."));
+        }
+
+        if href
+            .get(..5)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("data:"))
+        {
+            return match href[5..].get(..6) {
+                Some(media_type) if media_type.eq_ignore_ascii_case("image/") => {
+                    Ok(href.to_string())
+                }
+                _ => Err(reject("data URIs must contain images.")),
+            };
+        }
+
+        let Some(asset_dir) = &self.asset_dir else {
+            return Err(reject("only data URIs are allowed."));
+        };
+
+        let path = match href.strip_prefix("file://") {
+            Some(path) => path,
+            None if href.contains("://") => {
+                return Err(reject("only data URIs and local asset files are allowed."));
+            }
+            None => href,
+        };
+
+        let path = file_inside(asset_dir, Path::new(path))
+            .ok_or_else(|| reject("the file is not inside the allowed asset directory."))?;
+
+        path.to_str()
+            .map(str::to_string)
+            .ok_or_else(|| reject("the file path is not valid UTF-8."))
+    }
+
+    /// Wraps an image href resolver so that hrefs other than data URIs are only resolved if they
+    /// reference files inside the asset directory or the resources directory of the usvg options.
+    /// Parsing with it applies the policy to every image of the final tree, including images of
+    /// fragment sources and nested SVG images, rather than only to image placeholder values.
+    ///
+    /// # Arguments
+    ///
+    /// - `resolver` - The resolver to pass allowed hrefs on to.
+    pub fn image_href_resolver<'a>(&'a self, resolver: &'a ImageHrefResolver) -> ImageHrefResolver<'a> {
+        ImageHrefResolver {
+            resolve_data: Box::new(|mime, data, options| (resolver.resolve_data)(mime, data, options)),
+            resolve_string: Box::new(move |href, options| {
+                let path = options.get_abs_path(Path::new(href));
+                let allowed = [self.asset_dir.as_deref(), options.resources_dir.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .find_map(|dir| file_inside(dir, &path));
+
+                match allowed.as_deref().and_then(Path::to_str) {
+                    Some(path) => (resolver.resolve_string)(path, options),
+                    None => {
+                        warn!("Image '{href}' was not loaded as the resource policy does not allow it.");
+                        None
+                    }
+                }
+            }),
+        }
+    }
+}
+
+/// Returns the canonical path of a file if it is inside a directory after following symlinks.
+///
+/// # Arguments
+///
+/// - `dir` - The directory the file must be inside.
+/// - `path` - The path of the file, relative to the directory or absolute.
+fn file_inside(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let dir = dir.canonicalize().ok()?;
+    let path = dir.join(path).canonicalize().ok()?;
+
+    (path.starts_with(&dir) && path.is_file()).then_some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use resvg::usvg::Options;
+
+    use super::*;
+    use crate::test_support::TempDir;
+
+    /// A directory with an `assets` directory containing an image and an image next to it.
+    fn asset_dir(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("resources-{name}"));
+        dir.image("assets/icons/a.png", 1, 1, [255, 0, 0, 255]);
+        dir.image("outside/secret.png", 1, 1, [255, 0, 0, 255]);
+        dir
+    }
+
+    fn policy(dir: &TempDir) -> ResourcePolicy {
+        ResourcePolicy::new().with_asset_dir(dir.path("assets"))
+    }
+
+    fn is_rejected(result: Result<String, RenderingError>) -> bool {
+        matches!(result, Err(RenderingError::ResourceRejected(_)))
+    }
+
+    #[test]
+    fn accepts_data_image_uris() {
+        let href = "data:image/png;base64,iVBORw0KGgo=";
+        assert_eq!(ResourcePolicy::new().resolve_href("img", href).unwrap(), href);
+        assert!(ResourcePolicy::new().resolve_href("img", "DATA:IMAGE/png;base64,AA==").is_ok());
+    }
+
+    #[test]
+    fn rejects_data_uris_of_other_media_types() {
+        assert!(is_rejected(ResourcePolicy::new().resolve_href("img", "data:text/html,hi")));
+        assert!(is_rejected(ResourcePolicy::new().resolve_href("img", "data:,hi")));
+    }
+
+    #[test]
+    fn rejects_attribute_breaking_characters() {
+        for href in [
+            "data:image/png;base64,AA==\" onload=\"x",
+            "data:image/png;base64,<script>",
+            "data:image/png;base64,AA==&amp;",
+        ] {
+            assert!(is_rejected(ResourcePolicy::new().resolve_href("img", href)), "{href}");
+        }
+    }
+
+    #[test]
+    fn rejects_remote_urls() {
+        let dir = asset_dir("remote");
+
+        for href in ["http://example.com/a.png", "https://example.com/a.png"] {
+            assert!(is_rejected(ResourcePolicy::new().resolve_href("img", href)), "{href}");
+            assert!(is_rejected(policy(&dir).resolve_href("img", href)), "{href}");
+        }
+    }
+
+    #[test]
+    fn rejects_files_without_asset_dir() {
+        let dir = asset_dir("no-dir");
+        let href = dir.path("assets/icons/a.png");
+
+        assert!(is_rejected(ResourcePolicy::new().resolve_href("img", href.to_str().unwrap())));
+    }
+
+    #[test]
+    fn accepts_files_inside_asset_dir() {
+        let dir = asset_dir("inside");
+        let expected = dir.path("assets/icons/a.png").canonicalize().unwrap();
+        let expected = expected.to_str().unwrap();
+
+        let file_url = format!("file://{expected}");
+        for href in ["icons/a.png", "./icons/../icons/a.png", &file_url] {
+            assert_eq!(policy(&dir).resolve_href("img", href).unwrap(), expected, "{href}");
+        }
+    }
+
+    #[test]
+    fn rejects_files_outside_asset_dir() {
+        let dir = asset_dir("outside");
+        let secret = dir.path("outside/secret.png");
+
+        let file_url = format!("file://{}", secret.to_str().unwrap());
+        for href in ["../outside/secret.png", "icons/../../outside/secret.png", &file_url, "icons/missing.png"] {
+            assert!(is_rejected(policy(&dir).resolve_href("img", href)), "{href}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlinks_outside_asset_dir() {
+        let dir = asset_dir("symlink");
+        std::os::unix::fs::symlink(dir.path("outside"), dir.path("assets/linked")).unwrap();
+        std::os::unix::fs::symlink(dir.path("outside/secret.png"), dir.path("assets/secret.png"))
+            .unwrap();
+
+        for href in ["linked/secret.png", "secret.png"] {
+            assert!(is_rejected(policy(&dir).resolve_href("img", href)), "{href}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolver_only_loads_allowed_files() {
+        let dir = asset_dir("resolver");
+        std::os::unix::fs::symlink(dir.path("outside/secret.png"), dir.path("assets/secret.png"))
+            .unwrap();
+
+        let policy = policy(&dir);
+        let default_resolver = ImageHrefResolver::default();
+        let resolver = policy.image_href_resolver(&default_resolver);
+        let options = Options::default();
+
+        let inside = dir.path("assets/icons/a.png");
+        assert!((resolver.resolve_string)(inside.to_str().unwrap(), &options).is_some());
+
+        let outside = dir.path("outside/secret.png");
+        let traversal = dir.path("assets/icons/../../outside/secret.png");
+        let symlink = dir.path("assets/secret.png");
+        for path in [outside, traversal, symlink] {
+            assert!((resolver.resolve_string)(path.to_str().unwrap(), &options).is_none(), "{path:?}");
+        }
+    }
+}
@@ -1,8 +1,16 @@
-// For future schema versions, a migration system shall be implemented.
+// Schemas are loaded into the model of their version and migrated to the latest version by
+// applying each version's migration in sequence. See `migration`.
 
+mod migration;
 mod v1;
 mod v2;
-pub use v1::Fragment;
+mod v3;
+mod validation;
+pub use migration::LATEST_SCHEMA_VERSION;
+pub use v3::Fragment;
+pub use validation::{Severity, ValidationFinding};
+
+use std::fmt::Display;
 
 use serde_json::Value;
 
@@ -19,38 +27,109 @@ pub enum SchemaError {
     MalformedSchema(String),
     /// Unknown schema version.
     UnknownVersion(u64),
+    /// An asset path escapes the template directory.
+    AssetOutsideTemplate(String),
+    /// The schema has validation errors. Contains every finding, including warnings.
+    ValidationFailed(Vec<ValidationFinding>),
     MissingDefaultLayout,
 }
 
-pub type Schema = v2::Schema;
-pub type SchemaLayout = v2::Layout;
-pub type SchemaTextFragment = v2::TextFragment;
-pub type SchemaImageFragment = v2::ImageFragment;
-pub type SchemaShapeFragment = v2::ShapeFragment;
-pub type SchemaFragmentType = v2::FragmentType;
+impl Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::FileNotFoundError => write!(f, "The schema file was not found."),
+            SchemaError::FileReadError(e) => write!(f, "Failed to read the schema file: {e}"),
+            SchemaError::SchemaDecodeError(e) => write!(f, "Failed to decode the schema: {e}"),
+            SchemaError::MalformedSchema(message) => write!(f, "Malformed schema: {message}"),
+            SchemaError::UnknownVersion(version) => write!(f, "Unknown schema version {version}."),
+            SchemaError::AssetOutsideTemplate(path) => {
+                write!(f, "The asset '{path}' is outside of the template directory.")
+            }
+            SchemaError::ValidationFailed(findings) => {
+                let errors: Vec<String> = findings
+                    .iter()
+                    .filter(|finding| finding.is_error())
+                    .map(ValidationFinding::to_string)
+                    .collect();
+
+                write!(f, "The schema failed validation: {}", errors.join("; "))
+            }
+            SchemaError::MissingDefaultLayout => write!(f, "The schema has no default layout."),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+#[derive(Debug)]
+/** Errors resolving the path of an asset specified by a schema. */
+pub enum AssetPathError {
+    /// The asset is outside of the template directory, either directly or through symlinks.
+    OutsideTemplate(String),
+    /// The asset path could not be resolved or the asset could not be read.
+    Io(std::io::Error),
+}
+
+impl Display for AssetPathError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AssetPathError::OutsideTemplate(path) => {
+                write!(f, "The asset '{path}' is outside of the template directory.")
+            }
+            AssetPathError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for AssetPathError {}
+
+impl From<std::io::Error> for AssetPathError {
+    fn from(value: std::io::Error) -> Self {
+        AssetPathError::Io(value)
+    }
+}
+
+pub type Schema = v3::Schema;
+pub type SchemaLayout = v3::Layout;
+pub type SchemaTextFragment = v3::TextFragment;
+pub type SchemaImageFragment = v3::ImageFragment;
+pub type SchemaShapeFragment = v3::ShapeFragment;
+pub type SchemaFragmentType = v3::FragmentType;
+pub type SchemaVisibilityRule = v3::VisibilityRule;
+pub type SchemaRepeater = v3::Repeater;
+pub type SchemaDynamicFragments = v3::DynamicFragments;
+pub type SchemaTextBounds = v3::TextBounds;
+pub type SchemaTextOverflow = v3::TextOverflow;
+pub type SchemaTextAlign = v3::TextAlign;
+pub type SchemaValueFormat = v3::ValueFormat;
+pub type SchemaComputedPlaceholder = v3::ComputedPlaceholder;
 
-/// Load a schema from a file.
+/// Load a schema from a file. The schema is validated, warnings are logged and any validation
+/// errors fail the load.
 ///
 /// # Parameters
 /// - `schema_fp` - The path to the schema file.
 pub fn load_schema_from_file(schema_fp: &str) -> Result<Schema, SchemaError> {
-    if !std::fs::exists(schema_fp).map_err(|e| SchemaError::FileReadError(e))? {
+    if !std::fs::exists(schema_fp).map_err(SchemaError::FileReadError)? {
         return Err(SchemaError::FileNotFoundError);
     }
 
-    let content = std::fs::read_to_string(schema_fp).map_err(|e| SchemaError::FileReadError(e))?;
+    let content = std::fs::read_to_string(schema_fp).map_err(SchemaError::FileReadError)?;
     let json: Value =
-        serde_json::from_str(&content).map_err(|e| SchemaError::SchemaDecodeError(e))?;
-
-    let schema_version = json["schemaVersion"]
-        .as_u64()
-        .ok_or(SchemaError::MalformedSchema(
-            "Missing 'schemaVersion".to_string(),
-        ))?;
-
-    match schema_version {
-        1 => Ok(v1::load_schema_v1(schema_fp, json)?.migrate()),
-        2 => Ok(v2::load_schema_v2(schema_fp, json)?),
-        _ => Err(SchemaError::UnknownVersion(schema_version)),
+        serde_json::from_str(&content).map_err(SchemaError::SchemaDecodeError)?;
+
+    let schema = migration::VersionedSchema::load(schema_fp, json)?.into_latest();
+
+    schema.check_asset_paths()?;
+
+    let findings = schema.validate();
+    for finding in findings.iter().filter(|finding| !finding.is_error()) {
+        log::warn!("{schema_fp}: {finding}");
     }
+
+    if findings.iter().any(ValidationFinding::is_error) {
+        return Err(SchemaError::ValidationFailed(findings));
+    }
+
+    Ok(schema)
 }
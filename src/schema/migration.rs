@@ -0,0 +1,249 @@
+/// Schema migrations
+use serde_json::Value;
+
+use super::{SchemaError, v1, v2, v3};
+
+/** The latest schema version. Schemas of older versions are migrated to it when loaded. */
+pub const LATEST_SCHEMA_VERSION: u64 = 3;
+
+/** A schema model that can be migrated to the next schema version. */
+pub trait Migrate {
+    /** The schema model of the next version. */
+    type Next;
+
+    /** Migrate the schema to the next version. */
+    fn migrate(self) -> Self::Next;
+}
+
+#[derive(Debug)]
+/** A schema of any supported version. */
+pub enum VersionedSchema {
+    V1(v1::Schema),
+    V2(v2::Schema),
+    V3(v3::Schema),
+}
+
+impl VersionedSchema {
+    /**
+    Load json data into the schema model of its version.
+
+    # Parameters
+
+    - `schema_fp`: The filepath of the schema file.
+    - `schema_json`: The serde_json object loaded from the schema file.
+    */
+    pub fn load(schema_fp: &str, schema_json: Value) -> Result<Self, SchemaError> {
+        let schema_version = schema_json["schemaVersion"]
+            .as_u64()
+            .ok_or(SchemaError::MalformedSchema(
+                "Missing 'schemaVersion".to_string(),
+            ))?;
+
+        match schema_version {
+            1 => Ok(VersionedSchema::V1(v1::load_schema_v1(schema_fp, schema_json)?)),
+            2 => Ok(VersionedSchema::V2(v2::load_schema_v2(schema_fp, schema_json)?)),
+            3 => Ok(VersionedSchema::V3(v3::load_schema_v3(schema_fp, schema_json)?)),
+            _ => Err(SchemaError::UnknownVersion(schema_version)),
+        }
+    }
+
+    /** The version of the schema. */
+    pub fn version(&self) -> u64 {
+        match self {
+            VersionedSchema::V1(_) => 1,
+            VersionedSchema::V2(_) => 2,
+            VersionedSchema::V3(_) => 3,
+        }
+    }
+
+    /** Migrate the schema by a single version. Schemas of the latest version are returned unchanged. */
+    pub fn migrate_step(self) -> Self {
+        match self {
+            VersionedSchema::V1(schema) => VersionedSchema::V2(schema.migrate()),
+            VersionedSchema::V2(schema) => VersionedSchema::V3(schema.migrate()),
+            VersionedSchema::V3(schema) => VersionedSchema::V3(schema),
+        }
+    }
+
+    /** Migrate the schema to the latest version by applying every migration in sequence. */
+    pub fn into_latest(mut self) -> v3::Schema {
+        loop {
+            match self {
+                VersionedSchema::V3(schema) => return schema,
+                schema => {
+                    let version = schema.version();
+                    self = schema.migrate_step();
+                    log::debug!("Migrated schema from v{version} to v{}", self.version());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::test_support::TempDir;
+
+    /** A template directory with the base images and fragments used by the schemas below. */
+    fn template(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("migration-{name}"));
+
+        for image in ["opaque.png", "translucent.png", "mask.png"] {
+            dir.image(image, 10, 10, [255, 255, 255, 255]);
+        }
+        for fragment in ["name", "title", "avatar", "bar", "badge"] {
+            dir.write(&format!("fragments/{fragment}.svg"), "<svg/>");
+        }
+
+        dir
+    }
+
+    fn fragment(name: &str, x: i32) -> Value {
+        json!({"src": format!("fragments/{name}.svg"), "position": {"x": x, "y": 0}, "placeholders": []})
+    }
+
+    fn shape(name: &str, x: i32) -> Value {
+        let mut shape = fragment(name, x);
+        shape["widthMode"] = json!("dynamic");
+        shape["heightMode"] = json!("fixed");
+        shape["colorMode"] = json!("fixed");
+        shape
+    }
+
+    /** The fragments in both v1 and v2 schemas, which are rendered as text, images and then shapes. */
+    fn fragments() -> Value {
+        json!({
+            "text": [fragment("name", 1), fragment("title", 2)],
+            "images": [fragment("avatar", 3)],
+            "shapes": [shape("bar", 4), shape("badge", 5)]
+        })
+    }
+
+    fn v1_schema() -> Value {
+        json!({
+            "schemaVersion": 1,
+            "id": "card",
+            "name": "Card",
+            "contentBox": {"width": 10, "height": 10, "rasterX": 0, "rasterY": 0},
+            "rasterSize": {"width": 10, "height": 10},
+            "staticBase": {"opaque": "opaque.png", "translucent": "translucent.png", "mask": "mask.png"},
+            "fragments": fragments()
+        })
+    }
+
+    fn v2_layout(id: u32, name: &str, is_default: bool) -> Value {
+        json!({
+            "id": id,
+            "scale": {"id": id, "name": name, "scale": 1, "isDefault": is_default},
+            "contentBox": {"width": 10, "height": 10, "rasterX": 0, "rasterY": 0},
+            "rasterSize": {"width": 10, "height": 10},
+            "staticBase": {"default": "opaque.png"},
+            "fragments": fragments()
+        })
+    }
+
+    fn v2_schema() -> Value {
+        json!({
+            "schemaVersion": 2,
+            "id": "card",
+            "name": "Card",
+            "layouts": [v2_layout(1, "regular", true), v2_layout(2, "compact", false)],
+            "variables": [{"name": "colors/primary", "value": "#ff0000"}]
+        })
+    }
+
+    /** The sources of the fragments of a layout in render order with their IDs and z-indices. */
+    fn render_order(layout: &v3::Layout) -> Vec<(String, String, i32)> {
+        layout
+            .fragments
+            .ordered()
+            .into_iter()
+            .map(|f| (f.src().clone(), f.id().clone(), f.z_index()))
+            .collect()
+    }
+
+    fn expected_render_order() -> Vec<(String, String, i32)> {
+        [
+            ("name", "text-0"),
+            ("title", "text-1"),
+            ("avatar", "image-0"),
+            ("bar", "shape-0"),
+            ("badge", "shape-1"),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(z_index, (name, id))| (format!("fragments/{name}.svg"), id.to_string(), z_index as i32))
+        .collect()
+    }
+
+    #[test]
+    fn migrates_one_version_at_a_time() {
+        let schema = VersionedSchema::load("schema.json", v1_schema()).unwrap();
+        assert_eq!(schema.version(), 1);
+
+        let schema = schema.migrate_step();
+        assert_eq!(schema.version(), 2);
+
+        let schema = schema.migrate_step();
+        assert_eq!(schema.version(), 3);
+        assert_eq!(schema.migrate_step().version(), 3);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let schema = json!({"schemaVersion": LATEST_SCHEMA_VERSION + 1});
+
+        assert!(matches!(
+            VersionedSchema::load("schema.json", schema),
+            Err(SchemaError::UnknownVersion(version)) if version == LATEST_SCHEMA_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn migrates_v1_schemas_to_latest() {
+        let dir = template("v1");
+        let schema = dir.load("schema.json", &v1_schema()).unwrap();
+
+        assert_eq!(schema.schema_version, LATEST_SCHEMA_VERSION);
+        assert_eq!(schema.id, "card");
+        assert!(schema.variables.is_empty());
+        assert_eq!(schema.layouts.len(), 1);
+
+        let layout = &schema.layouts[0];
+        assert_eq!(layout.id, 0);
+        assert_eq!(layout.scale.name, "regular");
+        assert!(layout.scale.is_default);
+        assert_eq!(layout.static_base.default, "opaque.png");
+
+        let background = layout.static_base.background.as_ref().unwrap();
+        assert_eq!(background.translucent, "translucent.png");
+        assert_eq!(background.mask, "mask.png");
+
+        assert_eq!(render_order(layout), expected_render_order());
+        assert!(layout.repeaters.is_empty());
+    }
+
+    #[test]
+    fn migrates_v2_schemas_to_latest() {
+        let dir = template("v2");
+        let schema = dir.load("schema.json", &v2_schema()).unwrap();
+
+        assert_eq!(schema.schema_version, LATEST_SCHEMA_VERSION);
+        assert_eq!(schema.variables.len(), 1);
+        assert_eq!(schema.variables[0].name, "colors/primary");
+        assert!(schema.computed.is_empty());
+
+        let scale_names: Vec<&str> = schema.layouts.iter().map(|l| l.scale.name.as_str()).collect();
+        assert_eq!(scale_names, ["regular", "compact"]);
+        assert_eq!(schema.default_layout().unwrap().id, 1);
+
+        for layout in &schema.layouts {
+            assert_eq!(render_order(layout), expected_render_order());
+            assert!(matches!(layout.fragments.shapes[0].width_mode, v3::Mode::Dynamic));
+            assert_eq!(layout.fragments.images[0].position.as_tuple(), (3, 0));
+        }
+    }
+}
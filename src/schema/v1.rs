@@ -2,6 +2,7 @@
 use serde::Deserialize;
 use serde_json::Value;
 
+use super::migration::Migrate;
 use super::v2;
 use super::SchemaError;
 
@@ -55,18 +56,6 @@ impl Position {
     }
 }
 
-/** Common methods for fragments */
-pub trait Fragment {
-    /** The path for the fragment SVG file. */
-    fn src(&self) -> &String;
-    /** The position within the content box to render the fragment. */
-    fn position(&self) -> &Position;
-    /** The placeholders within the fragment that are expected to be replaced. */
-    fn placeholders(&self) -> &Vec<String>;
-    /** The type of fragment. */
-    fn fragment_type(&self) -> FragmentType;
-}
-
 #[derive(Deserialize, Debug, Clone)]
 /** The associated data for an image fragment. */
 pub struct ImageFragment {
@@ -78,21 +67,6 @@ pub struct ImageFragment {
     pub placeholders: Vec<String>,
 }
 
-impl Fragment for ImageFragment {
-    fn src(&self) -> &String {
-        &self.src
-    }
-    fn position(&self) -> &Position {
-        &self.position
-    }
-    fn placeholders(&self) -> &Vec<String> {
-        &self.placeholders
-    }
-    fn fragment_type(&self) -> FragmentType {
-        FragmentType::Image
-    }
-}
-
 #[derive(Deserialize, Debug, Clone)]
 /** The associated data for a text fragment. */
 pub struct TextFragment {
@@ -104,21 +78,6 @@ pub struct TextFragment {
     pub placeholders: Vec<String>,
 }
 
-impl Fragment for TextFragment {
-    fn src(&self) -> &String {
-        &self.src
-    }
-    fn position(&self) -> &Position {
-        &self.position
-    }
-    fn placeholders(&self) -> &Vec<String> {
-        &self.placeholders
-    }
-    fn fragment_type(&self) -> FragmentType {
-        FragmentType::Text
-    }
-}
-
 #[derive(Deserialize, Debug, Clone)]
 #[serde(rename_all = "lowercase")]
 /** Whether a attribute is fixed or dynamic */
@@ -129,7 +88,7 @@ pub enum Mode {
     Dynamic,
 }
 
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 /** The type of fragment */
 pub enum FragmentType {
@@ -156,21 +115,6 @@ pub struct ShapeFragment {
     pub color_mode: Mode,
 }
 
-impl Fragment for ShapeFragment {
-    fn src(&self) -> &String {
-        &self.src
-    }
-    fn position(&self) -> &Position {
-        &self.position
-    }
-    fn placeholders(&self) -> &Vec<String> {
-        &self.placeholders
-    }
-    fn fragment_type(&self) -> FragmentType {
-        FragmentType::Shape
-    }
-}
-
 #[derive(Deserialize, Debug, Clone)]
 /** All of the fragments for the render. */
 pub struct DynamicFragments {
@@ -206,11 +150,13 @@ pub struct Schema {
     pub fragments: DynamicFragments,
 }
 
-impl Schema {
-    pub fn migrate(self) -> v2::Schema {
+impl Migrate for Schema {
+    type Next = v2::Schema;
+
+    /** Moves the schema into a single default layout without variables. */
+    fn migrate(self) -> v2::Schema {
         v2::Schema {
             schema_file: self.schema_file,
-            schema_version: 2,
             id: self.id,
             name: self.name,
             variables: vec![],
@@ -248,7 +194,7 @@ Load json data into the schema v1 model.
 pub fn load_schema_v1(schema_fp: &str, mut schema_json: Value) -> Result<Schema, SchemaError> {
     schema_json["schemaFile"] = Value::String(schema_fp.to_string());
     let schema: Schema =
-        serde_json::from_value(schema_json).map_err(|e| SchemaError::SchemaDecodeError(e))?;
+        serde_json::from_value(schema_json).map_err(SchemaError::SchemaDecodeError)?;
 
     Ok(schema)
 }
@@ -1,14 +1,15 @@
 /// Schema v2 model
-
-use std::path::PathBuf;
-use std::path::absolute;
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
 use serde::Deserialize;
 use serde_json::Value;
 
+use super::migration::Migrate;
+use super::v3;
 use super::SchemaError;
 #[allow(unused)]
-pub use super::v1::{ContentBox, RasterSize, Fragment, FragmentType, Position, DynamicFragments, ImageFragment, TextFragment, ShapeFragment, Mode};
+pub use super::v1::{ContentBox, RasterSize, FragmentType, Position, DynamicFragments, ImageFragment, TextFragment, ShapeFragment, Mode};
 
 
 #[derive(Deserialize, Debug, Clone)]
@@ -79,8 +80,6 @@ pub struct Variable {
 pub struct Schema {
     /** The path to the schema file. */
     pub schema_file: String,
-    /** The version of the schema. */
-    pub schema_version: u64,
     /** The ID of the schema. */
     pub id: String,
     /** The name of the schema. */
@@ -91,42 +90,78 @@ pub struct Schema {
     pub variables: Vec<Variable>
 }
 
-impl Schema {
-    /** Get the absolute path for an asset specified by the schema. */
-    pub fn absolute_asset_path(&self, specified_fp: &str) -> Result<PathBuf, std::io::Error> {
-        let path = absolute(&format!("{}/../{}", self.schema_file, specified_fp))?;
-
-        let mut result = PathBuf::new();
-
-        for component in path.components() {
-            match component {
-                std::path::Component::ParentDir => {
-                    result.pop();
-                }
-                std::path::Component::CurDir => {}
-                other => result.push(other),
-            }
+impl Migrate for Schema {
+    type Next = v3::Schema;
+
+    /** Assigns fragment IDs and z-indices that preserve the v2 render order of text, images and then shapes. */
+    fn migrate(self) -> v3::Schema {
+        v3::Schema {
+            schema_file: self.schema_file,
+            schema_version: 3,
+            id: self.id,
+            name: self.name,
+            variables: self.variables,
+            computed: vec![],
+            layouts: self.layouts.into_iter().map(Layout::migrate).collect(),
+            canonical_root: OnceLock::new(),
         }
-
-        Ok(result)
-    }
-
-    /** Read the contents of an asset file specified by the schema. */
-    pub fn read_schema_asset_file(&self, specified_fp: &str) -> Result<Vec<u8>, std::io::Error> {
-        let path = self.absolute_asset_path(specified_fp)?;
-        std::fs::read(path)
-    }
-
-    pub fn default_layout(&self) -> Option<&Layout> {
-        self.layouts.iter().find(|l| l.scale.is_default)
-    }
-
-    pub fn layout_by_scale_name(&self, name: &str) -> Option<&Layout> {
-        self.layouts.iter().find(|l| l.scale.name == name)
     }
+}
 
-    pub fn get_variable(&self, name: &str) -> Option<Variable> {
-        self.variables.iter().find(|v| v.name == name).cloned()
+impl Layout {
+    /** Migrates the layout and its fragments to v3. */
+    fn migrate(self) -> v3::Layout {
+        let fragments = self.fragments;
+        let mut z_index = 0;
+        let mut next_z_index = || {
+            z_index += 1;
+            z_index - 1
+        };
+
+        let text = fragments.text.into_iter().enumerate().map(|(i, f)| v3::TextFragment {
+            id: format!("text-{i}"),
+            src: f.src,
+            position: f.position,
+            placeholders: f.placeholders,
+            z_index: next_z_index(),
+            defaults: HashMap::new(),
+            visible_when: None,
+            bounds: None,
+            formats: HashMap::new(),
+        }).collect();
+
+        let images = fragments.images.into_iter().enumerate().map(|(i, f)| v3::ImageFragment {
+            id: format!("image-{i}"),
+            src: f.src,
+            position: f.position,
+            placeholders: f.placeholders,
+            z_index: next_z_index(),
+            defaults: HashMap::new(),
+            visible_when: None,
+        }).collect();
+
+        let shapes = fragments.shapes.into_iter().enumerate().map(|(i, f)| v3::ShapeFragment {
+            id: format!("shape-{i}"),
+            src: f.src,
+            position: f.position,
+            placeholders: f.placeholders,
+            z_index: next_z_index(),
+            defaults: HashMap::new(),
+            visible_when: None,
+            width_mode: f.width_mode,
+            height_mode: f.height_mode,
+            color_mode: f.color_mode,
+        }).collect();
+
+        v3::Layout {
+            id: self.id,
+            scale: self.scale,
+            content_box: self.content_box,
+            raster_size: self.raster_size,
+            static_base: self.static_base,
+            fragments: v3::DynamicFragments { images, text, shapes },
+            repeaters: vec![],
+        }
     }
 }
 
@@ -141,7 +176,7 @@ Load json data into the schema v2 model.
 pub fn load_schema_v2(schema_fp: &str, mut schema_json: Value) -> Result<Schema, SchemaError> {
     schema_json["schemaFile"] = Value::String(schema_fp.to_string());
     let schema: Schema =
-        serde_json::from_value(schema_json).map_err(|e| SchemaError::SchemaDecodeError(e))?;
+        serde_json::from_value(schema_json).map_err(SchemaError::SchemaDecodeError)?;
 
     if schema.layouts.iter().find(|l| l.scale.is_default).is_none() {
         return Err(SchemaError::MissingDefaultLayout)
@@ -0,0 +1,781 @@
+/// Schema v3 model
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::path::absolute;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use super::{AssetPathError, SchemaError};
+use crate::expressions::{Expression, ExpressionError};
+#[allow(unused)]
+pub use super::v1::{ContentBox, RasterSize, FragmentType, Position, Mode};
+#[allow(unused)]
+pub use super::v2::{BackgroundBase, StaticBase, LayoutScale, Variable};
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** A rule that determines whether a fragment is rendered, based on the placeholder values. */
+pub enum VisibilityRule {
+    /** Visible when the named flag is set to true. */
+    Flag(String),
+    /** Visible when a value is provided for the named placeholder. */
+    Present(String),
+    /** Visible when the value provided for the named placeholder is not empty or whitespace. */
+    NotEmpty(String),
+    /** Visible when the text of the value provided for the placeholder is the value. */
+    Equals { placeholder: String, value: String },
+    /** Visible when no value is provided for the placeholder or its text is not the value. */
+    NotEquals { placeholder: String, value: String },
+    /** Visible when the inner rule is not. */
+    Not(Box<VisibilityRule>),
+    /** Visible when all of the inner rules are. */
+    All(Vec<VisibilityRule>),
+    /** Visible when any of the inner rules are. */
+    Any(Vec<VisibilityRule>),
+}
+
+impl VisibilityRule {
+    /** All flags referenced by the rule. */
+    pub fn flags(&self) -> Vec<&String> {
+        match self {
+            VisibilityRule::Flag(flag) => vec![flag],
+            VisibilityRule::Present(_)
+            | VisibilityRule::NotEmpty(_)
+            | VisibilityRule::Equals { .. }
+            | VisibilityRule::NotEquals { .. } => vec![],
+            VisibilityRule::Not(rule) => rule.flags(),
+            VisibilityRule::All(rules) | VisibilityRule::Any(rules) => {
+                rules.iter().flat_map(VisibilityRule::flags).collect()
+            }
+        }
+    }
+
+    /** All placeholders referenced by the rule. */
+    pub fn placeholders(&self) -> Vec<&String> {
+        match self {
+            VisibilityRule::Flag(_) => vec![],
+            VisibilityRule::Present(name) | VisibilityRule::NotEmpty(name) => vec![name],
+            VisibilityRule::Equals { placeholder, .. } | VisibilityRule::NotEquals { placeholder, .. } => {
+                vec![placeholder]
+            }
+            VisibilityRule::Not(rule) => rule.placeholders(),
+            VisibilityRule::All(rules) | VisibilityRule::Any(rules) => {
+                rules.iter().flat_map(VisibilityRule::placeholders).collect()
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "mode", rename_all = "camelCase", rename_all_fields = "camelCase")]
+/** How text that does not fit into the bounds of its fragment is handled. */
+pub enum TextOverflow {
+    /** The text is scaled down until it fits. Text that does not fit at a tenth of its size is also truncated with an ellipsis. */
+    Shrink,
+    /** The end of the text is replaced with an ellipsis. */
+    Ellipsis,
+    /**
+     * The text is always laid out in lines that are wrapped to the width of the bounds. If the
+     * lines do not fit, the last one ends with an ellipsis.
+     */
+    Wrap {
+        /** Optional: The maximum number of lines. Without one, lines are only limited by the height of the bounds. */
+        #[serde(default)]
+        max_lines: Option<u32>,
+        /** Optional: The distance between the baselines of lines. Defaults to 1.2 times the font size. */
+        #[serde(default)]
+        line_height: Option<f32>,
+        /** The horizontal alignment of lines within the bounds. */
+        #[serde(default)]
+        align: TextAlign,
+    },
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/** The horizontal alignment of wrapped lines within the bounds of a text fragment. */
+pub enum TextAlign {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** A text placeholder whose value is computed from the numeric values of other placeholders. */
+pub struct ComputedPlaceholder {
+    /** The name of the placeholder. */
+    pub name: String,
+    /** The arithmetic expression that computes the value, such as `{kills#text} / {deaths#text}`. */
+    pub expression: String,
+}
+
+impl ComputedPlaceholder {
+    /** Parses the expression of the placeholder. */
+    pub fn parse(&self) -> Result<Expression, ExpressionError> {
+        Expression::parse(&self.expression)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+/** How a typed placeholder value, such as a number or timestamp, is converted to text. */
+pub struct ValueFormat {
+    /** Optional: The number of decimal places. Defaults to 0 for integers, 2 for ratios, 1 for compact numbers and up to 6 without trailing zeros for floats. */
+    #[serde(default)]
+    pub decimals: Option<u32>,
+    /** Optional: The separator between groups of thousands, such as ",". Without one, thousands are not separated. */
+    #[serde(default)]
+    pub thousands_separator: Option<String>,
+    /** Optional: The separator before the decimal places. Defaults to ".". */
+    #[serde(default)]
+    pub decimal_separator: Option<String>,
+    /** Whether large numbers are abbreviated with a suffix, such as 1.2K. */
+    #[serde(default)]
+    pub compact: bool,
+    /** Whether numbers are multiplied by 100 and followed by a percent sign. */
+    #[serde(default)]
+    pub percent: bool,
+    /** Whether timestamps are shown relative to the time of the render, such as "3 days ago". Otherwise they are shown as dates. */
+    #[serde(default)]
+    pub relative: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** The box that the text of a fragment must fit into, relative to the fragment. */
+pub struct TextBounds {
+    /** The X position of the box, where lines start. */
+    #[serde(default)]
+    pub x: f32,
+    /** The Y position of the box. */
+    #[serde(default)]
+    pub y: f32,
+    /** The width of the box. */
+    pub width: f32,
+    /** Optional: The height of the box. Without a height, only the width of the text is limited. */
+    pub height: Option<f32>,
+    /** How text that does not fit is handled. */
+    pub overflow: TextOverflow,
+}
+
+/** Common methods for fragments */
+pub trait Fragment {
+    /** The ID of the fragment, unique within its layout. */
+    fn id(&self) -> &String;
+    /** The path for the fragment SVG file. */
+    fn src(&self) -> &String;
+    /** The position within the content box to render the fragment. */
+    fn position(&self) -> &Position;
+    /** The placeholders within the fragment that are expected to be replaced. */
+    fn placeholders(&self) -> &Vec<String>;
+    /** The stacking order of the fragment. Fragments with higher z-indices are rendered on top. */
+    fn z_index(&self) -> i32;
+    /** The values to use for placeholders that no value is provided for. */
+    fn defaults(&self) -> &HashMap<String, String>;
+    /** The rule that determines whether the fragment is rendered. Fragments without one are always rendered. */
+    fn visible_when(&self) -> Option<&VisibilityRule>;
+    /** The bounds that the text of the fragment must fit into. Only text fragments have bounds. */
+    fn text_bounds(&self) -> Option<&TextBounds>;
+    /** The formats of typed placeholder values. Only text fragments have formats. */
+    fn formats(&self) -> Option<&HashMap<String, ValueFormat>>;
+    /** The type of fragment. */
+    fn fragment_type(&self) -> FragmentType;
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** The associated data for an image fragment. */
+pub struct ImageFragment {
+    /** The ID of the fragment, unique within its layout. */
+    pub id: String,
+    /** The path for the fragment SVG file. */
+    pub src: String,
+    /** The position within the content box to render the fragment. */
+    pub position: Position,
+    /** The placeholders within the fragment that are expected to be replaced. */
+    pub placeholders: Vec<String>,
+    /** The stacking order of the fragment across all fragment types. */
+    pub z_index: i32,
+    /** The values to use for placeholders that no value is provided for. */
+    #[serde(default)]
+    pub defaults: HashMap<String, String>,
+    /** Optional: The rule that determines whether the fragment is rendered. */
+    #[serde(default)]
+    pub visible_when: Option<VisibilityRule>,
+}
+
+impl Fragment for ImageFragment {
+    fn id(&self) -> &String {
+        &self.id
+    }
+    fn src(&self) -> &String {
+        &self.src
+    }
+    fn position(&self) -> &Position {
+        &self.position
+    }
+    fn placeholders(&self) -> &Vec<String> {
+        &self.placeholders
+    }
+    fn z_index(&self) -> i32 {
+        self.z_index
+    }
+    fn defaults(&self) -> &HashMap<String, String> {
+        &self.defaults
+    }
+    fn visible_when(&self) -> Option<&VisibilityRule> {
+        self.visible_when.as_ref()
+    }
+    fn text_bounds(&self) -> Option<&TextBounds> {
+        None
+    }
+    fn formats(&self) -> Option<&HashMap<String, ValueFormat>> {
+        None
+    }
+    fn fragment_type(&self) -> FragmentType {
+        FragmentType::Image
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** The associated data for a text fragment. */
+pub struct TextFragment {
+    /** The ID of the fragment, unique within its layout. */
+    pub id: String,
+    /** The path for the fragment SVG file. */
+    pub src: String,
+    /** The position within the content box to render the fragment. */
+    pub position: Position,
+    /** The placeholders within the fragment that are expected to be replaced. */
+    pub placeholders: Vec<String>,
+    /** The stacking order of the fragment across all fragment types. */
+    pub z_index: i32,
+    /** The values to use for placeholders that no value is provided for. */
+    #[serde(default)]
+    pub defaults: HashMap<String, String>,
+    /** Optional: The rule that determines whether the fragment is rendered. */
+    #[serde(default)]
+    pub visible_when: Option<VisibilityRule>,
+    /** Optional: The bounds that the text must fit into. Without bounds, text is not fitted. */
+    #[serde(default)]
+    pub bounds: Option<TextBounds>,
+    /** The formats of typed placeholder values, keyed by placeholder. */
+    #[serde(default)]
+    pub formats: HashMap<String, ValueFormat>,
+}
+
+impl Fragment for TextFragment {
+    fn id(&self) -> &String {
+        &self.id
+    }
+    fn src(&self) -> &String {
+        &self.src
+    }
+    fn position(&self) -> &Position {
+        &self.position
+    }
+    fn placeholders(&self) -> &Vec<String> {
+        &self.placeholders
+    }
+    fn z_index(&self) -> i32 {
+        self.z_index
+    }
+    fn defaults(&self) -> &HashMap<String, String> {
+        &self.defaults
+    }
+    fn visible_when(&self) -> Option<&VisibilityRule> {
+        self.visible_when.as_ref()
+    }
+    fn text_bounds(&self) -> Option<&TextBounds> {
+        self.bounds.as_ref()
+    }
+    fn formats(&self) -> Option<&HashMap<String, ValueFormat>> {
+        Some(&self.formats)
+    }
+    fn fragment_type(&self) -> FragmentType {
+        FragmentType::Text
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** The associated data for a shape fragment. */
+pub struct ShapeFragment {
+    /** The ID of the fragment, unique within its layout. */
+    pub id: String,
+    /** The path for the fragment SVG file. */
+    pub src: String,
+    /** The position within the content box to render the fragment. */
+    pub position: Position,
+    /** The placeholders within the fragment that are expected to be replaced. */
+    pub placeholders: Vec<String>,
+    /** The stacking order of the fragment across all fragment types. */
+    pub z_index: i32,
+    /** The values to use for placeholders that no value is provided for. */
+    #[serde(default)]
+    pub defaults: HashMap<String, String>,
+    /** Optional: The rule that determines whether the fragment is rendered. */
+    #[serde(default)]
+    pub visible_when: Option<VisibilityRule>,
+    /** Whether the width of the shape is fixed or dynamic. */
+    pub width_mode: Mode,
+    /** Whether the height of the shape is fixed or dynamic. */
+    pub height_mode: Mode,
+    /** Whether the color of the shape is fixed or dynamic. */
+    pub color_mode: Mode,
+}
+
+impl Fragment for ShapeFragment {
+    fn id(&self) -> &String {
+        &self.id
+    }
+    fn src(&self) -> &String {
+        &self.src
+    }
+    fn position(&self) -> &Position {
+        &self.position
+    }
+    fn placeholders(&self) -> &Vec<String> {
+        &self.placeholders
+    }
+    fn z_index(&self) -> i32 {
+        self.z_index
+    }
+    fn defaults(&self) -> &HashMap<String, String> {
+        &self.defaults
+    }
+    fn visible_when(&self) -> Option<&VisibilityRule> {
+        self.visible_when.as_ref()
+    }
+    fn text_bounds(&self) -> Option<&TextBounds> {
+        None
+    }
+    fn formats(&self) -> Option<&HashMap<String, ValueFormat>> {
+        None
+    }
+    fn fragment_type(&self) -> FragmentType {
+        FragmentType::Shape
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+/** All of the fragments for the render. */
+pub struct DynamicFragments {
+    /** All of the image fragments for the render. */
+    pub images: Vec<ImageFragment>,
+    /** All of the text fragments for the render. */
+    pub text: Vec<TextFragment>,
+    /** All of the shape fragments for the render. */
+    pub shapes: Vec<ShapeFragment>,
+}
+
+impl DynamicFragments {
+    /** All fragments of a type as trait objects. */
+    fn of_type(&self, fragment_type: FragmentType) -> Vec<&dyn Fragment> {
+        match fragment_type {
+            FragmentType::Text => self.text.iter().map(|f| f as &dyn Fragment).collect(),
+            FragmentType::Image => self.images.iter().map(|f| f as &dyn Fragment).collect(),
+            FragmentType::Shape => self.shapes.iter().map(|f| f as &dyn Fragment).collect(),
+        }
+    }
+
+    /**
+     * All fragments in render order, sorted by z-index from bottom to top. Fragments with equal
+     * z-indices are rendered in the order text, images and then shapes.
+     */
+    pub fn ordered(&self) -> Vec<&dyn Fragment> {
+        let mut fragments: Vec<&dyn Fragment> = [FragmentType::Text, FragmentType::Image, FragmentType::Shape]
+            .into_iter()
+            .flat_map(|fragment_type| self.of_type(fragment_type))
+            .collect();
+
+        fragments.sort_by_key(|f| f.z_index());
+        fragments
+    }
+
+    /** All placeholders expected by the fragments of a type, sorted and without duplicates. */
+    pub fn placeholders(&self, fragment_type: FragmentType) -> Vec<String> {
+        let mut placeholders: Vec<String> = self
+            .of_type(fragment_type)
+            .into_iter()
+            .flat_map(|f| f.placeholders().clone())
+            .collect();
+
+        placeholders.sort();
+        placeholders.dedup();
+        placeholders
+    }
+
+    /** All flags referenced by the visibility rules of the fragments, sorted and without duplicates. */
+    pub fn flags(&self) -> Vec<String> {
+        let mut flags: Vec<String> = self
+            .ordered()
+            .into_iter()
+            .filter_map(|f| f.visible_when())
+            .flat_map(|rule| rule.flags().into_iter().cloned().collect::<Vec<_>>())
+            .collect();
+
+        flags.sort();
+        flags.dedup();
+        flags
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** A group of fragments that is rendered once for every row of a placeholder value list. */
+pub struct Repeater {
+    /** The ID of the repeater. Its rows are filled from the placeholder value list with the same name. */
+    pub id: String,
+    /** The fragments of a row. Their positions are for the first row. */
+    pub fragments: DynamicFragments,
+    /** The offset of every row from the previous one. */
+    pub row_offset: Position,
+    /** The maximum number of rows to render. Additional rows are ignored. */
+    pub max_rows: u32,
+}
+
+impl Repeater {
+    /** The offset of a row from the first row. Offsets beyond the range of `i32` saturate. */
+    pub fn offset(&self, row: usize) -> (i32, i32) {
+        let row = i32::try_from(row).unwrap_or(i32::MAX);
+        (self.row_offset.x.saturating_mul(row), self.row_offset.y.saturating_mul(row))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** A layout configuration for the render. */
+pub struct Layout {
+    /** The ID of the layout. */
+    pub id: u32,
+    /** The scale settings for the layout. */
+    pub scale: LayoutScale,
+    /** The content box of the schema. This is what fragments are positioned relative to. */
+    pub content_box: ContentBox,
+    /** The actual size of the raster. */
+    pub raster_size: RasterSize,
+    /** The paths to the static base images. */
+    pub static_base: StaticBase,
+    /** All of the fragments for the render. */
+    pub fragments: DynamicFragments,
+    /** Optional: Groups of fragments that are repeated for every row of a placeholder value list. */
+    #[serde(default)]
+    pub repeaters: Vec<Repeater>,
+}
+
+impl Layout {
+    /** The fragments of the layout followed by the fragments of every repeater. */
+    pub fn all_fragments(&self) -> Vec<&DynamicFragments> {
+        std::iter::once(&self.fragments)
+            .chain(self.repeaters.iter().map(|r| &r.fragments))
+            .collect()
+    }
+
+    /** Get a repeater by its ID. */
+    pub fn repeater(&self, id: &str) -> Option<&Repeater> {
+        self.repeaters.iter().find(|r| r.id == id)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/** v3: The schema that determines the layout of the render and all of its elements. */
+pub struct Schema {
+    /** The path to the schema file. */
+    pub schema_file: String,
+    /** The version of the schema. */
+    pub schema_version: u64,
+    /** The ID of the schema. */
+    pub id: String,
+    /** The name of the schema. */
+    pub name: String,
+    /** All of the layouts for the render. */
+    pub layouts: Vec<Layout>,
+    /** All of the variables for the render. */
+    pub variables: Vec<Variable>,
+    /** Text placeholders whose values are computed from other placeholder values, in the order they are computed. */
+    #[serde(default)]
+    pub computed: Vec<ComputedPlaceholder>,
+    /** The canonical template directory, resolved when the asset paths are first checked. */
+    #[serde(skip)]
+    pub(crate) canonical_root: OnceLock<PathBuf>,
+}
+
+impl Schema {
+    /** Get the template directory containing the schema file. All assets must be inside it. */
+    pub fn template_root(&self) -> Result<PathBuf, io::Error> {
+        let schema_file = normalize_path(&absolute(&self.schema_file)?);
+
+        schema_file
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "The schema file has no parent directory."))
+    }
+
+    /**
+     * Get the template directory with symlinks resolved. It is resolved once, when the asset paths
+     * are checked while loading the schema, so renders do not resolve it again.
+     */
+    fn canonical_root(&self) -> Result<&Path, io::Error> {
+        if let Some(root) = self.canonical_root.get() {
+            return Ok(root);
+        }
+
+        let root = self.template_root()?.canonicalize()?;
+        Ok(self.canonical_root.get_or_init(|| root))
+    }
+
+    /**
+     * Get the absolute path for an asset specified by the schema. Paths that escape the template
+     * directory, either directly or through symlinks, are rejected with
+     * `AssetPathError::OutsideTemplate`.
+     */
+    pub fn absolute_asset_path(&self, specified_fp: &str) -> Result<PathBuf, AssetPathError> {
+        let root = self.template_root()?;
+        let path = normalize_path(&root.join(specified_fp));
+
+        let outside_root = || AssetPathError::OutsideTemplate(specified_fp.to_string());
+
+        if !path.starts_with(&root) {
+            return Err(outside_root());
+        }
+
+        // The deepest existing ancestor is resolved to catch symlinks pointing out of the template
+        // directory, including symlinked directories of paths that do not exist yet.
+        for ancestor in path.ancestors().take_while(|ancestor| ancestor.starts_with(&root)) {
+            if ancestor.try_exists()? {
+                if !ancestor.canonicalize()?.starts_with(self.canonical_root()?) {
+                    return Err(outside_root());
+                }
+                break;
+            }
+        }
+
+        Ok(path)
+    }
+
+    /** Get the paths of all assets referenced by the schema. */
+    pub fn asset_paths(&self) -> Vec<&str> {
+        let mut paths = vec![];
+
+        for layout in &self.layouts {
+            paths.push(layout.static_base.default.as_str());
+
+            if let Some(background) = &layout.static_base.background {
+                paths.push(background.translucent.as_str());
+                paths.push(background.mask.as_str());
+            }
+
+            for fragments in layout.all_fragments() {
+                paths.extend(fragments.images.iter().map(|f| f.src().as_str()));
+                paths.extend(fragments.text.iter().map(|f| f.src().as_str()));
+                paths.extend(fragments.shapes.iter().map(|f| f.src().as_str()));
+            }
+        }
+
+        paths
+    }
+
+    /**
+     * Check that every asset referenced by the schema is inside the template directory. This also
+     * resolves the canonical template directory that later asset paths are checked against.
+     */
+    pub fn check_asset_paths(&self) -> Result<(), SchemaError> {
+        self.canonical_root().map_err(SchemaError::FileReadError)?;
+
+        for path in self.asset_paths() {
+            match self.absolute_asset_path(path) {
+                Ok(_) => {}
+                Err(AssetPathError::OutsideTemplate(_)) => {
+                    return Err(SchemaError::AssetOutsideTemplate(path.to_string()));
+                }
+                Err(AssetPathError::Io(e)) => return Err(SchemaError::FileReadError(e)),
+            }
+        }
+
+        Ok(())
+    }
+
+    /** Read the contents of an asset file specified by the schema. */
+    pub fn read_schema_asset_file(&self, specified_fp: &str) -> Result<Vec<u8>, AssetPathError> {
+        let path = self.absolute_asset_path(specified_fp)?;
+        Ok(std::fs::read(path)?)
+    }
+
+    pub fn default_layout(&self) -> Option<&Layout> {
+        self.layouts.iter().find(|l| l.scale.is_default)
+    }
+
+    pub fn layout_by_scale_name(&self, name: &str) -> Option<&Layout> {
+        self.layouts.iter().find(|l| l.scale.name == name)
+    }
+
+    /// Returns the best layout to render at the specified scale. This is the smallest layout
+    /// exported at or above the scale, or the largest layout if none are.
+    pub fn layout_for_scale(&self, scale: f32) -> Option<&Layout> {
+        let by_scale = |a: &&Layout, b: &&Layout| a.scale.scale.total_cmp(&b.scale.scale);
+
+        self.layouts
+            .iter()
+            .filter(|l| l.scale.scale >= scale)
+            .min_by(by_scale)
+            .or_else(|| self.layouts.iter().max_by(by_scale))
+    }
+
+    pub fn get_variable(&self, name: &str) -> Option<Variable> {
+        self.variables.iter().find(|v| v.name == name).cloned()
+    }
+
+    /** Get the names of the computed placeholders and of the placeholders their expressions reference. */
+    pub fn computed_placeholders(&self) -> Vec<String> {
+        let mut names: Vec<String> = vec![];
+
+        for placeholder in &self.computed {
+            names.push(placeholder.name.clone());
+
+            if let Ok(expression) = placeholder.parse() {
+                names.extend(expression.placeholders().into_iter().map(String::from));
+            }
+        }
+
+        names.sort();
+        names.dedup();
+        names
+    }
+}
+
+/** Lexically resolves `.` and `..` components of a path without touching the file system. */
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::CurDir => {}
+            other => result.push(other),
+        }
+    }
+
+    result
+}
+
+/**
+Load json data into the schema v3 model.
+
+# Parameters
+
+- `schema_fp`: The filepath of the schema file.
+- `schema_json`: The serde_json object loaded from the schema file.
+*/
+pub fn load_schema_v3(schema_fp: &str, mut schema_json: Value) -> Result<Schema, SchemaError> {
+    schema_json["schemaFile"] = Value::String(schema_fp.to_string());
+    let schema: Schema =
+        serde_json::from_value(schema_json).map_err(SchemaError::SchemaDecodeError)?;
+
+    if schema.default_layout().is_none() {
+        return Err(SchemaError::MissingDefaultLayout)
+    }
+
+    Ok(schema)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempDir;
+
+    /** A template directory with a fragment and a file next to it, and a schema in it. */
+    fn template(name: &str) -> (TempDir, Schema) {
+        let dir = TempDir::new(&format!("assets-{name}"));
+        dir.write("template/fragments/a.svg", "<svg/>");
+        dir.write("outside/secret.png", "secret");
+
+        let schema = Schema {
+            schema_file: dir.path("template/schema.json").to_string_lossy().to_string(),
+            schema_version: 3,
+            id: name.to_string(),
+            name: name.to_string(),
+            layouts: vec![],
+            variables: vec![],
+            computed: vec![],
+            canonical_root: OnceLock::new(),
+        };
+
+        (dir, schema)
+    }
+
+    #[test]
+    fn resolves_assets_inside_template() {
+        let (dir, schema) = template("inside");
+
+        let path = schema.absolute_asset_path("fragments/../fragments/./a.svg").unwrap();
+        assert_eq!(path, dir.path("template/fragments/a.svg"));
+        assert!(schema.absolute_asset_path("fragments/missing.svg").is_ok());
+        assert!(schema.absolute_asset_path("missing/nested/a.svg").is_ok());
+    }
+
+    #[test]
+    fn rejects_parent_directory_escapes() {
+        let (_dir, schema) = template("parent");
+
+        for path in ["../outside/secret.png", "fragments/../../outside/secret.png", "/etc/passwd"] {
+            assert!(
+                matches!(schema.absolute_asset_path(path), Err(AssetPathError::OutsideTemplate(p)) if p == path),
+                "{path} is outside of the template directory"
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_symlink_escapes() {
+        let (dir, schema) = template("symlink");
+        std::os::unix::fs::symlink(dir.path("outside"), dir.path("template/linked")).unwrap();
+        std::os::unix::fs::symlink(dir.path("outside/secret.png"), dir.path("template/secret.png")).unwrap();
+
+        for path in ["linked/secret.png", "secret.png", "linked/missing.png", "linked/nested/missing.png"] {
+            assert!(
+                matches!(schema.absolute_asset_path(path), Err(AssetPathError::OutsideTemplate(p)) if p == path),
+                "{path} links outside of the template directory"
+            );
+        }
+    }
+
+    #[test]
+    fn saturates_row_offsets() {
+        let repeater = Repeater {
+            id: "rows".to_string(),
+            fragments: DynamicFragments { images: vec![], text: vec![], shapes: vec![] },
+            row_offset: Position { x: -3, y: 1_000_000 },
+            max_rows: u32::MAX,
+        };
+
+        assert_eq!(repeater.offset(0), (0, 0));
+        assert_eq!(repeater.offset(2), (-6, 2_000_000));
+        assert_eq!(repeater.offset(10_000), (-30_000, i32::MAX));
+        assert_eq!(repeater.offset(usize::MAX), (i32::MIN, i32::MAX));
+    }
+
+    #[test]
+    fn resolves_template_root_once() {
+        let (dir, schema) = template("root-once");
+        assert!(schema.canonical_root.get().is_none());
+
+        schema.check_asset_paths().unwrap();
+        let root = dir.path("template").canonicalize().unwrap();
+        assert_eq!(schema.canonical_root.get(), Some(&root));
+
+        // The cached root is used even once the directory can no longer be resolved.
+        std::fs::rename(dir.path("template"), dir.path("moved")).unwrap();
+        assert!(schema.canonical_root().is_ok());
+    }
+}
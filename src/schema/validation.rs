@@ -0,0 +1,553 @@
+/// Schema validation and linting
+use std::{collections::HashMap, fmt::Display, io};
+
+use regex::Regex;
+use serde::Serialize;
+
+use super::AssetPathError;
+use super::v3::{DynamicFragments, Fragment, FragmentType, Layout, Schema, TextOverflow};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+/** The severity of a validation finding. */
+pub enum Severity {
+    /** The schema renders, but probably not as intended. */
+    Warning,
+    /** The schema cannot be rendered correctly. */
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/** A problem found while validating a schema. */
+pub struct ValidationFinding {
+    /** The severity of the finding. */
+    pub severity: Severity,
+    /** Optional: The ID of the layout the finding belongs to. */
+    pub layout_id: Option<u32>,
+    /** Optional: The asset path the finding belongs to, as specified by the schema. */
+    pub asset: Option<String>,
+    /** A human readable description of the finding. */
+    pub message: String,
+}
+
+impl ValidationFinding {
+    /** Whether the finding is an error. */
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+impl Display for ValidationFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.severity {
+            Severity::Warning => write!(f, "[warning]")?,
+            Severity::Error => write!(f, "[error]")?,
+        }
+
+        if let Some(layout_id) = self.layout_id {
+            write!(f, " layout {layout_id}")?;
+        }
+
+        if let Some(asset) = &self.asset {
+            write!(f, " '{asset}'")?;
+        }
+
+        write!(f, ": {}", self.message)
+    }
+}
+
+/** Collects findings for a single layout. */
+struct LayoutValidator<'a> {
+    schema: &'a Schema,
+    layout: &'a Layout,
+    variable_re: &'a Regex,
+    findings: &'a mut Vec<ValidationFinding>,
+}
+
+impl LayoutValidator<'_> {
+    fn push(&mut self, severity: Severity, asset: &str, message: String) {
+        self.findings.push(ValidationFinding {
+            severity,
+            layout_id: Some(self.layout.id),
+            asset: Some(asset.to_string()),
+            message,
+        });
+    }
+
+    /** Describes an error reading an asset. */
+    fn read_error_message(e: &AssetPathError) -> String {
+        match e {
+            AssetPathError::OutsideTemplate(_) => {
+                "The file is outside of the template directory.".to_string()
+            }
+            AssetPathError::Io(e) => match e.kind() {
+                io::ErrorKind::NotFound => "The file does not exist.".to_string(),
+                io::ErrorKind::PermissionDenied => "The file is not readable.".to_string(),
+                _ => format!("The file could not be read: {e}"),
+            },
+        }
+    }
+
+    /** Checks that an image asset exists and returns its dimensions if it can be decoded. */
+    fn image_dimensions(&mut self, asset: &str) -> Option<(u32, u32)> {
+        let path = match self.schema.absolute_asset_path(asset) {
+            Ok(path) => path,
+            Err(e) => {
+                self.push(Severity::Error, asset, Self::read_error_message(&e));
+                return None;
+            }
+        };
+
+        match image::image_dimensions(&path) {
+            Ok(dimensions) => Some(dimensions),
+            Err(image::ImageError::IoError(e)) => {
+                self.push(Severity::Error, asset, Self::read_error_message(&AssetPathError::Io(e)));
+                None
+            }
+            Err(e) => {
+                self.push(Severity::Error, asset, format!("The image could not be decoded: {e}"));
+                None
+            }
+        }
+    }
+
+    /** Checks the static base images of the layout. */
+    fn validate_static_base(&mut self) {
+        let layout = self.layout;
+        let raster_size = (layout.raster_size.width, layout.raster_size.height);
+        let static_base = &layout.static_base;
+
+        if let Some(size) = self.image_dimensions(&static_base.default)
+            && size != raster_size
+        {
+            self.push(
+                Severity::Warning,
+                &static_base.default,
+                format!(
+                    "The base image is {}x{}px, but the raster size is {}x{}px.",
+                    size.0, size.1, raster_size.0, raster_size.1
+                ),
+            );
+        }
+
+        let Some(background) = &static_base.background else {
+            return;
+        };
+
+        let translucent_size = self.image_dimensions(&background.translucent);
+        let mask_size = self.image_dimensions(&background.mask);
+
+        if let (Some(translucent_size), Some(mask_size)) = (translucent_size, mask_size)
+            && translucent_size != mask_size
+        {
+            self.push(
+                Severity::Error,
+                &background.mask,
+                format!(
+                    "The mask image is {}x{}px, but the translucent base image is {}x{}px.",
+                    mask_size.0, mask_size.1, translucent_size.0, translucent_size.1
+                ),
+            );
+        }
+
+        if let Some(size) = translucent_size
+            && size != raster_size
+        {
+            self.push(
+                Severity::Warning,
+                &background.translucent,
+                format!(
+                    "The translucent base image is {}x{}px, but the raster size is {}x{}px.",
+                    size.0, size.1, raster_size.0, raster_size.1
+                ),
+            );
+        }
+    }
+
+    /** Checks that fragment IDs are unique within the layout, including the fragments of repeaters. */
+    fn validate_fragment_ids(&mut self) {
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+
+        for fragments in self.layout.all_fragments() {
+            let all_ids = fragments.images.iter().map(|f| f.id())
+                .chain(fragments.text.iter().map(|f| f.id()))
+                .chain(fragments.shapes.iter().map(|f| f.id()));
+
+            for id in all_ids {
+                *ids.entry(id).or_default() += 1;
+            }
+        }
+
+        let mut duplicates: Vec<_> = ids.into_iter().filter(|(_, n)| *n > 1).collect();
+        duplicates.sort();
+        for (id, count) in duplicates {
+            self.findings.push(ValidationFinding {
+                severity: Severity::Error,
+                layout_id: Some(self.layout.id),
+                asset: None,
+                message: format!("The fragment ID '{id}' is used by {count} fragments."),
+            });
+        }
+    }
+
+    /** Checks that repeater IDs are unique within the layout and repeaters can render rows. */
+    fn validate_repeaters(&mut self) {
+        let mut ids: HashMap<&str, usize> = HashMap::new();
+
+        for repeater in &self.layout.repeaters {
+            *ids.entry(&repeater.id).or_default() += 1;
+
+            if repeater.max_rows == 0 {
+                self.findings.push(ValidationFinding {
+                    severity: Severity::Warning,
+                    layout_id: Some(self.layout.id),
+                    asset: None,
+                    message: format!("The repeater '{}' has a maximum of 0 rows and is never rendered.", repeater.id),
+                });
+            }
+        }
+
+        let mut duplicates: Vec<_> = ids.into_iter().filter(|(_, n)| *n > 1).collect();
+        duplicates.sort();
+        for (id, count) in duplicates {
+            self.findings.push(ValidationFinding {
+                severity: Severity::Error,
+                layout_id: Some(self.layout.id),
+                asset: None,
+                message: format!("The repeater ID '{id}' is used by {count} repeaters."),
+            });
+        }
+    }
+
+    /** Checks the fragments of the layout or of a repeater. */
+    fn validate_fragment_group(&mut self, group: &DynamicFragments) {
+        self.validate_fragments(&group.images, group);
+        self.validate_fragments(&group.text, group);
+        self.validate_fragments(&group.shapes, group);
+    }
+
+    /**
+     * Checks that fragment sources exist and use their declared placeholders and variables.
+     * Visibility rules may only reference placeholders declared by the group of the fragments.
+     */
+    fn validate_fragments<T: Fragment>(&mut self, fragments: &[T], group: &DynamicFragments) {
+        for fragment in fragments {
+            let src = fragment.src();
+
+            let svg_code = match self.schema.read_schema_asset_file(src).map(String::from_utf8) {
+                Ok(Ok(svg_code)) => svg_code,
+                Ok(Err(_)) => {
+                    self.push(Severity::Error, src, "The fragment is not valid UTF-8.".to_string());
+                    continue;
+                }
+                Err(e) => {
+                    self.push(Severity::Error, src, Self::read_error_message(&e));
+                    continue;
+                }
+            };
+
+            if let Some(rule) = fragment.visible_when() {
+                let declared: Vec<String> = [FragmentType::Text, FragmentType::Image, FragmentType::Shape]
+                    .into_iter()
+                    .flat_map(|fragment_type| group.placeholders(fragment_type))
+                    .collect();
+
+                for placeholder in rule.placeholders() {
+                    if !declared.contains(placeholder) {
+                        self.push(
+                            Severity::Warning,
+                            src,
+                            format!("The visibility rule references '{placeholder}', which is not declared by the layout."),
+                        );
+                    }
+                }
+            }
+
+            if let Some(bounds) = fragment.text_bounds() {
+                let positive = |size: f32| size.is_finite() && size > 0.0;
+
+                if !positive(bounds.width) || !bounds.height.is_none_or(positive) {
+                    self.push(Severity::Error, src, "The text bounds must have a positive size.".to_string());
+                }
+
+                if let TextOverflow::Wrap { max_lines, line_height, .. } = bounds.overflow {
+                    if max_lines == Some(0) {
+                        self.push(Severity::Error, src, "Wrapped text must allow at least one line.".to_string());
+                    }
+
+                    if !line_height.is_none_or(positive) {
+                        self.push(Severity::Error, src, "The line height must be positive.".to_string());
+                    }
+                }
+            }
+
+            let mut defaults: Vec<&String> = fragment.defaults().keys().collect();
+            defaults.sort();
+            for placeholder in defaults {
+                if !fragment.placeholders().contains(placeholder) {
+                    self.push(
+                        Severity::Warning,
+                        src,
+                        format!("The default for '{placeholder}' is not for a placeholder declared by the fragment."),
+                    );
+                }
+            }
+
+            let mut formats: Vec<&String> = fragment.formats().into_iter().flat_map(|formats| formats.keys()).collect();
+            formats.sort();
+            for placeholder in formats {
+                if !fragment.placeholders().contains(placeholder) {
+                    self.push(
+                        Severity::Warning,
+                        src,
+                        format!("The format for '{placeholder}' is not for a placeholder declared by the fragment."),
+                    );
+                }
+            }
+
+            for placeholder in fragment.placeholders() {
+                if !svg_code.contains(&format!("{{{placeholder}}}")) {
+                    self.push(
+                        Severity::Warning,
+                        src,
+                        format!("The placeholder '{placeholder}' is declared but not used by the fragment."),
+                    );
+                }
+            }
+
+            for caps in self.variable_re.captures_iter(&svg_code) {
+                if self.schema.get_variable(&caps[1]).is_none() {
+                    self.push(
+                        Severity::Warning,
+                        src,
+                        format!("The variable '{}' is not defined by the schema.", &caps[1]),
+                    );
+                }
+            }
+        }
+    }
+}
+
+impl Schema {
+    /**
+     * Validate the schema. Every layout's static base images and fragment sources, including those
+     * of repeaters, are checked along with the placeholders and variables the fragments use and
+     * the expressions of computed placeholders.
+     *
+     * Returns all findings. The schema can only be rendered correctly if none are errors.
+     */
+    pub fn validate(&self) -> Vec<ValidationFinding> {
+        let mut findings = vec![];
+
+        let mut scale_names: HashMap<&str, usize> = HashMap::new();
+        let mut layout_ids: HashMap<u32, usize> = HashMap::new();
+
+        for layout in &self.layouts {
+            *scale_names.entry(&layout.scale.name).or_default() += 1;
+            *layout_ids.entry(layout.id).or_default() += 1;
+        }
+
+        let mut duplicate_names: Vec<_> = scale_names.into_iter().filter(|(_, n)| *n > 1).collect();
+        duplicate_names.sort();
+        for (name, count) in duplicate_names {
+            findings.push(ValidationFinding {
+                severity: Severity::Error,
+                layout_id: None,
+                asset: None,
+                message: format!("The scale name '{name}' is used by {count} layouts."),
+            });
+        }
+
+        let mut duplicate_ids: Vec<_> = layout_ids.into_iter().filter(|(_, n)| *n > 1).collect();
+        duplicate_ids.sort();
+        for (id, count) in duplicate_ids {
+            findings.push(ValidationFinding {
+                severity: Severity::Error,
+                layout_id: Some(id),
+                asset: None,
+                message: format!("The layout ID {id} is used by {count} layouts."),
+            });
+        }
+
+        let default_layouts = self.layouts.iter().filter(|l| l.scale.is_default).count();
+        if default_layouts > 1 {
+            findings.push(ValidationFinding {
+                severity: Severity::Warning,
+                layout_id: None,
+                asset: None,
+                message: format!("{default_layouts} layouts are marked as default, only the first is used."),
+            });
+        }
+
+        let mut computed_names: HashMap<&str, usize> = HashMap::new();
+
+        for placeholder in &self.computed {
+            *computed_names.entry(&placeholder.name).or_default() += 1;
+
+            if let Err(e) = placeholder.parse() {
+                findings.push(ValidationFinding {
+                    severity: Severity::Error,
+                    layout_id: None,
+                    asset: None,
+                    message: format!("The expression of the computed placeholder '{}' is invalid: {e}", placeholder.name),
+                });
+            }
+        }
+
+        let mut duplicate_computed: Vec<_> = computed_names.into_iter().filter(|(_, n)| *n > 1).collect();
+        duplicate_computed.sort();
+        for (name, count) in duplicate_computed {
+            findings.push(ValidationFinding {
+                severity: Severity::Error,
+                layout_id: None,
+                asset: None,
+                message: format!("The computed placeholder '{name}' is declared {count} times."),
+            });
+        }
+
+        let variable_re = Regex::new(r"\{variable:([a-zA-Z0-9_.]+)\}").expect("The variable regex is valid.");
+
+        for layout in &self.layouts {
+            let mut validator = LayoutValidator {
+                schema: self,
+                layout,
+                variable_re: &variable_re,
+                findings: &mut findings,
+            };
+
+            validator.validate_static_base();
+            validator.validate_fragment_ids();
+            validator.validate_repeaters();
+
+            for fragments in layout.all_fragments() {
+                validator.validate_fragment_group(fragments);
+            }
+        }
+
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+
+    use super::*;
+    use crate::schema::SchemaError;
+    use crate::test_support::{self, TempDir, fragment};
+
+    /** A template directory with a 10x10px base image and a fragment. */
+    fn template(name: &str) -> TempDir {
+        let dir = TempDir::new(&format!("validation-{name}"));
+        dir.image("base.png", 10, 10, [0, 0, 0, 0]);
+        dir.write("fragments/name.svg", "<svg><text>{name}</text></svg>");
+        dir
+    }
+
+    /** A layout with the fragment `name`, which declares the placeholders. */
+    fn layout(id: u32, scale_name: &str, placeholders: &[&str]) -> Value {
+        let mut layout = test_support::layout(id, scale_name, 1.0);
+        layout["fragments"]["text"] = json!([fragment("name", placeholders, json!({}))]);
+        layout
+    }
+
+    /** Writes a schema with the layouts and loads it. */
+    fn load(dir: &TempDir, layouts: Vec<Value>) -> Result<Schema, SchemaError> {
+        dir.load("schema.json", &test_support::schema(layouts))
+    }
+
+    /** Loads a schema that must fail validation and returns its error findings. */
+    fn errors(dir: &TempDir, layouts: Vec<Value>) -> Vec<ValidationFinding> {
+        match load(dir, layouts) {
+            Err(SchemaError::ValidationFailed(findings)) => {
+                findings.into_iter().filter(ValidationFinding::is_error).collect()
+            }
+            other => panic!("Expected the schema to fail validation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn loads_valid_schema() {
+        let dir = template("valid");
+        let schema = load(&dir, vec![layout(1, "1x", &["name"])]).unwrap();
+
+        assert!(schema.validate().is_empty());
+    }
+
+    #[test]
+    fn reports_missing_fragment() {
+        let dir = template("missing-fragment");
+        let mut layout = layout(1, "1x", &["name"]);
+        layout["fragments"]["text"][0]["src"] = json!("fragments/missing.svg");
+
+        let errors = errors(&dir, vec![layout]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].asset.as_deref(), Some("fragments/missing.svg"));
+        assert_eq!(errors[0].message, "The file does not exist.");
+    }
+
+    #[test]
+    fn reports_undecodable_base_image() {
+        let dir = template("undecodable");
+        dir.write("base.png", "not an image");
+
+        let errors = errors(&dir, vec![layout(1, "1x", &["name"])]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].asset.as_deref(), Some("base.png"));
+        assert!(errors[0].message.starts_with("The image could not be decoded"), "{}", errors[0].message);
+    }
+
+    #[test]
+    fn reports_mismatched_mask_size() {
+        let dir = template("mask-size");
+        dir.image("translucent.png", 10, 10, [0, 0, 0, 0]);
+        dir.image("mask.png", 5, 5, [0, 0, 0, 0]);
+
+        let mut layout = layout(1, "1x", &["name"]);
+        layout["staticBase"]["background"] = json!({"translucent": "translucent.png", "mask": "mask.png"});
+
+        let errors = errors(&dir, vec![layout]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].layout_id, Some(1));
+        assert_eq!(errors[0].asset.as_deref(), Some("mask.png"));
+        assert_eq!(errors[0].message, "The mask image is 5x5px, but the translucent base image is 10x10px.");
+    }
+
+    #[test]
+    fn warns_about_translucent_base_size() {
+        let dir = template("translucent-size");
+        dir.image("translucent.png", 20, 20, [0, 0, 0, 0]);
+        dir.image("mask.png", 20, 20, [0, 0, 0, 0]);
+
+        let mut layout = layout(1, "1x", &["name"]);
+        layout["staticBase"]["background"] = json!({"translucent": "translucent.png", "mask": "mask.png"});
+
+        let findings = load(&dir, vec![layout]).unwrap().validate();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[0].asset.as_deref(), Some("translucent.png"));
+    }
+
+    #[test]
+    fn reports_duplicate_scale_names() {
+        let dir = template("scale-names");
+
+        let errors = errors(&dir, vec![layout(1, "1x", &["name"]), layout(2, "1x", &["name"])]);
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].layout_id, None);
+        assert_eq!(errors[0].message, "The scale name '1x' is used by 2 layouts.");
+    }
+
+    #[test]
+    fn warns_about_unused_placeholder() {
+        let dir = template("unused-placeholder");
+
+        let schema = load(&dir, vec![layout(1, "1x", &["name", "rank"])]).unwrap();
+        let findings = schema.validate();
+
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        assert_eq!(findings[0].asset.as_deref(), Some("fragments/name.svg"));
+        assert_eq!(findings[0].message, "The placeholder 'rank' is declared but not used by the fragment.");
+    }
+}
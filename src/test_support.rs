@@ -0,0 +1,144 @@
+//! Helpers shared by the tests of every module.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::{Value, json};
+
+use crate::schema::{Schema, SchemaError, load_schema_from_file};
+
+/// A directory in the system temp directory, removed when dropped.
+pub struct TempDir {
+    /// The path of the directory.
+    pub root: PathBuf,
+}
+
+impl TempDir {
+    /// Creates an empty directory. Tests run in parallel, so the name must be unique across all of
+    /// them.
+    ///
+    /// # Arguments
+    ///
+    /// - `name` - The name of the directory.
+    pub fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("fractyl-renderer-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+
+        TempDir { root }
+    }
+
+    /// Returns the path of a file in the directory.
+    pub fn path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    /// Writes a file and creates its parent directories. Returns the path of the file.
+    pub fn write(&self, path: &str, contents: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.path(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    /// Writes a PNG image filled with a color. Returns the path of the image.
+    pub fn image(&self, path: &str, width: u32, height: u32, color: [u8; 4]) -> PathBuf {
+        let path = self.write(path, "");
+        image::RgbaImage::from_pixel(width, height, image::Rgba(color)).save(&path).unwrap();
+        path
+    }
+
+    /// Writes a schema of any version as JSON and loads it.
+    ///
+    /// # Arguments
+    ///
+    /// - `path` - The path of the schema file.
+    /// - `schema` - The schema to write.
+    pub fn load(&self, path: &str, schema: &Value) -> Result<Schema, SchemaError> {
+        let schema_fp = self.write(path, schema.to_string());
+        load_schema_from_file(path_str(&schema_fp))
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// Returns a path as a string.
+pub fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+/// A schema of the latest version with layouts.
+pub fn schema(layouts: Vec<Value>) -> Value {
+    json!({
+        "schemaVersion": 3,
+        "id": "test",
+        "name": "Test",
+        "layouts": layouts,
+        "variables": []
+    })
+}
+
+/// A layout without fragments that renders a 10x10 content box onto `base.png`. The layout with
+/// ID 1 is the default layout.
+///
+/// # Arguments
+///
+/// - `id` - The ID of the layout and its scale.
+/// - `scale_name` - The name of the scale.
+/// - `scale` - The scale the layout was exported at. The raster size is scaled by it.
+pub fn layout(id: u32, scale_name: &str, scale: f32) -> Value {
+    let size = (10.0 * scale).round() as u32;
+
+    json!({
+        "id": id,
+        "scale": {"id": id, "name": scale_name, "scale": scale, "isDefault": id == 1},
+        "contentBox": {"width": 10, "height": 10, "rasterX": 0, "rasterY": 0},
+        "rasterSize": {"width": size, "height": size},
+        "staticBase": {"default": "base.png"},
+        "fragments": fragments(vec![], vec![], vec![]),
+        "repeaters": []
+    })
+}
+
+/// The fragments of a layout or repeater.
+pub fn fragments(text: Vec<Value>, images: Vec<Value>, shapes: Vec<Value>) -> Value {
+    json!({"text": text, "images": images, "shapes": shapes})
+}
+
+/// A text or image fragment at the origin, rendered from `fragments/{id}.svg`.
+///
+/// # Arguments
+///
+/// - `id` - The ID of the fragment.
+/// - `placeholders` - The placeholders of the fragment.
+/// - `properties` - Additional properties, which replace the defaults.
+pub fn fragment(id: &str, placeholders: &[&str], properties: Value) -> Value {
+    let mut fragment = json!({
+        "id": id,
+        "src": format!("fragments/{id}.svg"),
+        "position": {"x": 0, "y": 0},
+        "placeholders": placeholders,
+        "zIndex": 0
+    });
+
+    if let (Some(fragment), Value::Object(properties)) = (fragment.as_object_mut(), properties) {
+        fragment.extend(properties);
+    }
+
+    fragment
+}
+
+/// A shape fragment with a fixed size and color. See [`fragment`].
+pub fn shape_fragment(id: &str, placeholders: &[&str], properties: Value) -> Value {
+    let mut shape = json!({"widthMode": "fixed", "heightMode": "fixed", "colorMode": "fixed"});
+
+    if let (Some(shape), Value::Object(properties)) = (shape.as_object_mut(), properties) {
+        shape.extend(properties);
+    }
+
+    fragment(id, placeholders, shape)
+}
@@ -0,0 +1,822 @@
+use resvg::usvg::{self, Rect};
+
+use crate::placeholders::{PlaceholderValueMap, TextSpan, spans_to_tspans};
+use crate::render::RenderingError;
+use crate::schema::{SchemaTextAlign, SchemaTextBounds, SchemaTextOverflow};
+
+/// The character that replaces the end of truncated text.
+pub const ELLIPSIS: char = '…';
+
+/// The default distance between the baselines of wrapped lines, relative to the font size.
+const DEFAULT_LINE_HEIGHT: f32 = 1.2;
+
+/// Allowed error when comparing the size of text with its bounds.
+const TOLERANCE: f32 = 0.01;
+
+/// The smallest factor that shrunk text is scaled by.
+const MIN_SHRINK_SCALE: f32 = 0.1;
+
+/// The number of steps of the search for the factor to shrink text by.
+const SHRINK_STEPS: usize = 8;
+
+/// A word of wrapped text. Words can span multiple text spans, so every piece of the word
+/// keeps the index of the span it came from.
+type Word = Vec<(usize, String)>;
+
+/// Returns the union of the bounding boxes of all text in a tree, in canvas coordinates.
+///
+/// # Arguments
+///
+/// - `tree` - The parsed fragment.
+pub fn text_bounding_box(tree: &usvg::Tree) -> Option<Rect> {
+    fn collect(group: &usvg::Group, bbox: &mut Option<Rect>) {
+        for node in group.children() {
+            match node {
+                usvg::Node::Text(text) => {
+                    let rect = text.abs_bounding_box();
+                    *bbox = match bbox {
+                        Some(b) => Rect::from_ltrb(
+                            b.left().min(rect.left()),
+                            b.top().min(rect.top()),
+                            b.right().max(rect.right()),
+                            b.bottom().max(rect.bottom()),
+                        ),
+                        None => Some(rect),
+                    };
+                }
+                usvg::Node::Group(group) => collect(group, bbox),
+                _ => {}
+            }
+        }
+    }
+
+    let mut bbox = None;
+    collect(tree.root(), &mut bbox);
+    bbox
+}
+
+/// Fits the text of a fragment into its bounds according to the overflow policy of the bounds.
+/// Text is measured by rendering the fragment with candidate placeholder values, so the
+/// measurement uses the same fonts and styles as the render.
+pub struct TextFitter<'a, M>
+where
+    M: Fn(&PlaceholderValueMap) -> Result<Option<Rect>, RenderingError>,
+{
+    /// The bounds to fit the text into.
+    bounds: &'a SchemaTextBounds,
+    /// Measures the text of the fragment rendered with placeholder values.
+    measure: M,
+}
+
+impl<'a, M> TextFitter<'a, M>
+where
+    M: Fn(&PlaceholderValueMap) -> Result<Option<Rect>, RenderingError>,
+{
+    /// Creates a new text fitter.
+    ///
+    /// # Arguments
+    ///
+    /// - `bounds` - The bounds to fit the text into.
+    /// - `measure` - Returns the text bounding box of the fragment rendered with placeholder
+    ///   values, or `None` if it contains no text.
+    pub fn new(bounds: &'a SchemaTextBounds, measure: M) -> Self {
+        TextFitter { bounds, measure }
+    }
+
+    /// Returns whether a text bounding box fits the width of the bounds.
+    fn fits_width(&self, bbox: Option<Rect>) -> bool {
+        bbox.is_none_or(|bbox| bbox.width() <= self.bounds.width + TOLERANCE)
+    }
+
+    /// Returns whether a text bounding box fits the height of the bounds, if it has one.
+    fn fits_height(&self, bbox: Option<Rect>) -> bool {
+        match (bbox, self.bounds.height) {
+            (Some(bbox), Some(height)) => bbox.height() <= height + TOLERANCE,
+            _ => true,
+        }
+    }
+
+    /// Returns whether a text bounding box fits the bounds.
+    fn fits(&self, bbox: Option<Rect>) -> bool {
+        self.fits_width(bbox) && self.fits_height(bbox)
+    }
+
+    /// Measures the text with a different value for a placeholder.
+    ///
+    /// # Arguments
+    ///
+    /// - `values` - The placeholder values of the fragment.
+    /// - `placeholder` - The placeholder to replace the value of.
+    /// - `value` - The value to measure the text with.
+    fn measure_with(
+        &self,
+        values: &mut PlaceholderValueMap,
+        placeholder: &str,
+        value: String,
+    ) -> Result<Option<Rect>, RenderingError> {
+        values.insert(placeholder.to_string(), value);
+        (self.measure)(values)
+    }
+
+    /// Fits the text into the bounds by changing the value of a placeholder. Unless the text is
+    /// wrapped, text that already fits is left unchanged.
+    ///
+    /// # Arguments
+    ///
+    /// - `values` - The placeholder values of the fragment.
+    /// - `placeholder` - The placeholder to change the value of.
+    /// - `spans` - The text spans of the placeholder value.
+    ///
+    /// Returns the placeholder values to render the fragment with.
+    pub fn fit(
+        &self,
+        mut values: PlaceholderValueMap,
+        placeholder: &str,
+        spans: &[TextSpan],
+    ) -> Result<PlaceholderValueMap, RenderingError> {
+        // Wrapped text is always laid out, so line breaks and the alignment are applied.
+        if let SchemaTextOverflow::Wrap { max_lines, .. } = self.bounds.overflow {
+            let value = self.wrap(&mut values, placeholder, spans, max_lines)?;
+            values.insert(placeholder.to_string(), value);
+            return Ok(values);
+        }
+
+        let Some(bbox) = (self.measure)(&values)? else {
+            return Ok(values);
+        };
+
+        if self.fits(Some(bbox)) {
+            return Ok(values);
+        }
+
+        let value = match self.bounds.overflow {
+            SchemaTextOverflow::Shrink => self.shrink(&mut values, placeholder, spans, bbox)?,
+            _ => {
+                let length = text_length(spans).saturating_sub(1);
+                self.truncate(&mut values, placeholder, length, |length| {
+                    spans_to_tspans(&ellipsize(spans, length))
+                })?
+            }
+        };
+
+        values.insert(placeholder.to_string(), value);
+        Ok(values)
+    }
+
+    /// Returns the value with its text spans scaled down to the largest font size that fits the
+    /// bounds. The scale is first estimated from the size of the text, which is exact unless the
+    /// fragment contains other text. Text that does not fit at the smallest scale is also
+    /// truncated with an ellipsis.
+    ///
+    /// # Arguments
+    ///
+    /// - `values` - The placeholder values of the fragment.
+    /// - `placeholder` - The placeholder to shrink the value of.
+    /// - `spans` - The text spans to shrink.
+    /// - `bbox` - The bounding box of the text before shrinking.
+    fn shrink(
+        &self,
+        values: &mut PlaceholderValueMap,
+        placeholder: &str,
+        spans: &[TextSpan],
+        bbox: Rect,
+    ) -> Result<String, RenderingError> {
+        let value = |scale: f32| scaled_tspans(spans, scale);
+
+        let mut estimate = self.bounds.width / bbox.width();
+        if let Some(height) = self.bounds.height {
+            estimate = estimate.min(height / bbox.height());
+        }
+
+        let (mut low, mut high) = (MIN_SHRINK_SCALE, estimate.clamp(MIN_SHRINK_SCALE, 1.0));
+        if self.fits(self.measure_with(values, placeholder, value(high))?) {
+            return Ok(value(high));
+        }
+
+        for _ in 0..SHRINK_STEPS {
+            let mid = (low + high) / 2.0;
+
+            if self.fits(self.measure_with(values, placeholder, value(mid))?) {
+                low = mid;
+            } else {
+                high = mid;
+            }
+        }
+
+        if low > MIN_SHRINK_SCALE || self.fits(self.measure_with(values, placeholder, value(low))?) {
+            return Ok(value(low));
+        }
+
+        let length = text_length(spans).saturating_sub(1);
+        self.truncate(values, placeholder, length, |length| {
+            scaled_tspans(&ellipsize(spans, length), MIN_SHRINK_SCALE)
+        })
+    }
+
+    /// Returns the value with the longest prefix of its text that fits the bounds.
+    ///
+    /// # Arguments
+    ///
+    /// - `values` - The placeholder values of the fragment.
+    /// - `placeholder` - The placeholder to truncate the value of.
+    /// - `max_length` - The maximum number of characters to keep.
+    /// - `value` - Returns the value with its text truncated to a number of characters and
+    ///   followed by an ellipsis.
+    fn truncate(
+        &self,
+        values: &mut PlaceholderValueMap,
+        placeholder: &str,
+        max_length: usize,
+        value: impl Fn(usize) -> String,
+    ) -> Result<String, RenderingError> {
+        // Binary search for the longest prefix that fits, assuming longer text is never smaller.
+        let (mut low, mut high) = (0, max_length);
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+
+            if self.fits(self.measure_with(values, placeholder, value(mid))?) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+
+        Ok(value(low))
+    }
+
+    /// Wraps text spans onto lines that fit the width of the bounds. Line breaks in the text
+    /// start new lines. If there are more lines than allowed or they do not fit the height of the
+    /// bounds, the last line that fits is truncated with an ellipsis. Candidate lines are measured
+    /// on their own, and lines stop being added once the bounds are full.
+    ///
+    /// # Arguments
+    ///
+    /// - `values` - The placeholder values of the fragment.
+    /// - `placeholder` - The placeholder to wrap the value of.
+    /// - `spans` - The text spans to wrap.
+    /// - `max_lines` - Optional: The maximum number of lines.
+    fn wrap(
+        &self,
+        values: &mut PlaceholderValueMap,
+        placeholder: &str,
+        spans: &[TextSpan],
+        max_lines: Option<u32>,
+    ) -> Result<String, RenderingError> {
+        let max_lines = max_lines.map_or(usize::MAX, |max_lines| max_lines.max(1) as usize);
+        let mut paragraphs = split_paragraphs(spans);
+        let mut lines: Vec<Vec<Word>> = vec![];
+        let mut truncated = false;
+
+        // Trailing line breaks do not add lines.
+        while paragraphs.last().is_some_and(Vec::is_empty) {
+            paragraphs.pop();
+        }
+
+        let fits = |values: &mut PlaceholderValueMap, words: &[Word]| {
+            let bbox = self.measure_with(values, placeholder, self.words_to_tspans(spans, &[words.to_vec()]))?;
+            Ok::<bool, RenderingError>(self.fits_width(bbox))
+        };
+
+        'paragraphs: for mut words in paragraphs {
+            let mut start = 0;
+
+            loop {
+                if lines.len() == max_lines {
+                    truncated = true;
+                    break 'paragraphs;
+                }
+
+                let done = if words.is_empty() {
+                    lines.push(vec![]);
+                    true
+                } else if fits(values, &words[start..])? {
+                    lines.push(words[start..].to_vec());
+                    true
+                } else if !fits(values, &words[start..=start])? {
+                    // A word that is wider than a line on its own is broken up. A glyph that is
+                    // wider than a line on its own leaves nothing of the word, so the next word
+                    // is wrapped.
+                    let length = self.longest_fitting_prefix(values, placeholder, spans, &words[start])?;
+                    let (head, tail) = split_word(&words[start], length);
+                    lines.push(vec![head]);
+
+                    match tail.is_empty() {
+                        true => start += 1,
+                        false => words[start] = tail,
+                    }
+                    start == words.len()
+                } else {
+                    // Binary search for the most words that fit onto the line.
+                    let (mut low, mut high) = (1, words.len() - start - 1);
+                    while low < high {
+                        let mid = (low + high).div_ceil(2);
+
+                        if fits(values, &words[start..start + mid])? {
+                            low = mid;
+                        } else {
+                            high = mid - 1;
+                        }
+                    }
+
+                    lines.push(words[start..start + low].to_vec());
+                    start += low;
+                    false
+                };
+
+                // The first line is always kept, even if it is higher than the bounds.
+                if self.bounds.height.is_some() && lines.len() > 1 {
+                    let bbox = self.measure_with(values, placeholder, self.words_to_tspans(spans, &lines))?;
+                    if !self.fits_height(bbox) {
+                        lines.pop();
+                        truncated = true;
+                        break 'paragraphs;
+                    }
+                }
+
+                if done {
+                    break;
+                }
+            }
+        }
+
+        if !truncated {
+            return Ok(self.words_to_tspans(spans, &lines));
+        }
+
+        while lines.last().is_some_and(Vec::is_empty) {
+            lines.pop();
+        }
+
+        let Some(last) = lines.pop() else {
+            return Ok(String::new());
+        };
+
+        let last = words_to_spans(spans, &last);
+        let lines: Vec<Vec<TextSpan>> = lines.iter().map(|line| words_to_spans(spans, line)).collect();
+        self.truncate(values, placeholder, text_length(&last), |length| {
+            let mut lines = lines.clone();
+            lines.push(ellipsize(&last, length));
+            self.lines_to_tspans(&lines)
+        })
+    }
+
+    /// Returns the number of characters of a word that fit onto a new line, which is at least one.
+    ///
+    /// # Arguments
+    ///
+    /// - `values` - The placeholder values of the fragment.
+    /// - `placeholder` - The placeholder to wrap the value of.
+    /// - `spans` - The text spans of the placeholder value.
+    /// - `word` - The word to break up.
+    fn longest_fitting_prefix(
+        &self,
+        values: &mut PlaceholderValueMap,
+        placeholder: &str,
+        spans: &[TextSpan],
+        word: &Word,
+    ) -> Result<usize, RenderingError> {
+        let length: usize = word.iter().map(|(_, piece)| piece.chars().count()).sum();
+        let (mut low, mut high) = (1, length.saturating_sub(1).max(1));
+
+        while low < high {
+            let mid = (low + high).div_ceil(2);
+            let line = vec![split_word(word, mid).0];
+            let bbox = self.measure_with(values, placeholder, self.words_to_tspans(spans, &[line]))?;
+            if self.fits_width(bbox) {
+                low = mid;
+            } else {
+                high = mid - 1;
+            }
+        }
+
+        Ok(low)
+    }
+
+    /// Converts lines of words to XML tspan elements.
+    fn words_to_tspans(&self, spans: &[TextSpan], lines: &[Vec<Word>]) -> String {
+        let lines: Vec<Vec<TextSpan>> = lines.iter().map(|line| words_to_spans(spans, line)).collect();
+        self.lines_to_tspans(&lines)
+    }
+
+    /// Converts lines of text spans to XML tspan elements. Every line starts a new text chunk
+    /// that is aligned within the bounds, and empty lines only move the following lines down.
+    fn lines_to_tspans(&self, lines: &[Vec<TextSpan>]) -> String {
+        let (line_height, align) = match self.bounds.overflow {
+            SchemaTextOverflow::Wrap { line_height, align, .. } => (line_height, align),
+            _ => (None, SchemaTextAlign::Left),
+        };
+
+        let (x, anchor) = match align {
+            SchemaTextAlign::Left => (self.bounds.x, "start"),
+            SchemaTextAlign::Center => (self.bounds.x + self.bounds.width / 2.0, "middle"),
+            SchemaTextAlign::Right => (self.bounds.x + self.bounds.width, "end"),
+        };
+
+        let dy = |lines: usize| match line_height {
+            Some(line_height) => format!(" dy=\"{}\"", line_height * lines as f32),
+            None => format!(" dy=\"{}em\"", DEFAULT_LINE_HEIGHT * lines as f32),
+        };
+
+        let mut tspans = String::new();
+        let mut previous = 0;
+
+        for (i, line) in lines.iter().enumerate() {
+            if line.is_empty() {
+                continue;
+            }
+
+            let dy = match i - previous {
+                0 => String::new(),
+                lines => dy(lines),
+            };
+
+            tspans.push_str(&format!(
+                "<tspan x=\"{x}\" text-anchor=\"{anchor}\"{dy}>{}</tspan>",
+                spans_to_tspans(line)
+            ));
+            previous = i;
+        }
+
+        tspans
+    }
+}
+
+/// Returns the number of characters in text spans.
+fn text_length(spans: &[TextSpan]) -> usize {
+    spans.iter().map(|span| span.value.chars().count()).sum()
+}
+
+/// Converts text spans to an XML tspan element that scales their font size by a factor.
+///
+/// # Arguments
+///
+/// - `spans` - The text spans to scale.
+/// - `scale` - The factor to scale the font size by.
+fn scaled_tspans(spans: &[TextSpan], scale: f32) -> String {
+    let spans: Vec<TextSpan> = spans.iter().map(|span| span.scaled(scale)).collect();
+    format!("<tspan font-size=\"{scale}em\">{}</tspan>", spans_to_tspans(&spans))
+}
+
+/// Returns the first characters of text spans followed by an ellipsis. Trailing whitespace is
+/// removed before the ellipsis.
+///
+/// # Arguments
+///
+/// - `spans` - The text spans to truncate.
+/// - `length` - The number of characters to keep.
+fn ellipsize(spans: &[TextSpan], length: usize) -> Vec<TextSpan> {
+    let mut remaining = length;
+    let mut truncated: Vec<TextSpan> = vec![];
+
+    for span in spans {
+        if remaining == 0 {
+            break;
+        }
+
+        let value: String = span.value.chars().take(remaining).collect();
+        remaining -= value.chars().count();
+        truncated.push(span.with_value(&value));
+    }
+
+    while let Some(span) = truncated.last_mut() {
+        span.value = span.value.trim_end().to_string();
+
+        if !span.value.is_empty() {
+            break;
+        }
+
+        truncated.pop();
+    }
+
+    match truncated.last_mut() {
+        Some(span) => span.value.push(ELLIPSIS),
+        None => {
+            let span = spans.first().cloned().unwrap_or_else(|| TextSpan::plain(""));
+            truncated.push(span.with_value(&ELLIPSIS.to_string()));
+        }
+    }
+
+    truncated
+}
+
+/// Splits text spans into paragraphs at line breaks, and paragraphs into words at whitespace.
+///
+/// # Arguments
+///
+/// - `spans` - The text spans to split.
+fn split_paragraphs(spans: &[TextSpan]) -> Vec<Vec<Word>> {
+    let mut paragraphs: Vec<Vec<Word>> = vec![vec![]];
+    let mut word: Word = vec![];
+
+    for (i, span) in spans.iter().enumerate() {
+        for c in span.value.chars() {
+            if c.is_whitespace() {
+                let paragraph = paragraphs.last_mut().expect("There is always a paragraph.");
+                if !word.is_empty() {
+                    paragraph.push(std::mem::take(&mut word));
+                }
+
+                if c == '\n' {
+                    paragraphs.push(vec![]);
+                }
+                continue;
+            }
+
+            match word.last_mut() {
+                Some((span, piece)) if *span == i => piece.push(c),
+                _ => word.push((i, c.to_string())),
+            }
+        }
+    }
+
+    if !word.is_empty() {
+        paragraphs.last_mut().expect("There is always a paragraph.").push(word);
+    }
+
+    paragraphs
+}
+
+/// Splits a word after a number of characters.
+///
+/// # Arguments
+///
+/// - `word` - The word to split.
+/// - `length` - The number of characters of the first part.
+fn split_word(word: &Word, length: usize) -> (Word, Word) {
+    let mut head: Word = vec![];
+    let mut tail: Word = vec![];
+    let mut remaining = length;
+
+    for (span, piece) in word {
+        let count = piece.chars().count();
+
+        if remaining >= count {
+            head.push((*span, piece.clone()));
+            remaining -= count;
+        } else if remaining > 0 {
+            head.push((*span, piece.chars().take(remaining).collect()));
+            tail.push((*span, piece.chars().skip(remaining).collect()));
+            remaining = 0;
+        } else {
+            tail.push((*span, piece.clone()));
+        }
+    }
+
+    (head, tail)
+}
+
+/// Joins the words of a line with spaces and merges pieces of the same span.
+///
+/// # Arguments
+///
+/// - `spans` - The text spans the words came from.
+/// - `words` - The words of the line.
+fn words_to_spans(spans: &[TextSpan], words: &[Word]) -> Vec<TextSpan> {
+    let mut pieces: Vec<(usize, String)> = vec![];
+
+    for (i, word) in words.iter().enumerate() {
+        for (j, (span, piece)) in word.iter().enumerate() {
+            let piece = match (i, j) {
+                (i, 0) if i > 0 => format!(" {piece}"),
+                _ => piece.clone(),
+            };
+
+            match pieces.last_mut() {
+                Some((last, value)) if last == span => value.push_str(&piece),
+                _ => pieces.push((*span, piece)),
+            }
+        }
+    }
+
+    pieces
+        .into_iter()
+        .map(|(span, value)| spans[span].with_value(&value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// The width of every glyph and the height of every line measured by [`measure`] in tests
+    /// of glyphs that are wider than the bounds.
+    const GLYPH_SIZE: f32 = 500.0;
+
+    /// The size of glyphs that are narrower than the bounds, so ten fit onto a line.
+    const NARROW_GLYPH_SIZE: f32 = 10.0;
+
+    /// Returns the number of characters outside of the tags of XML markup.
+    fn glyphs(markup: &str) -> usize {
+        text(markup).chars().count()
+    }
+
+    /// Returns the characters outside of the tags of XML markup.
+    fn text(markup: &str) -> String {
+        let (mut text, mut in_tag) = (String::new(), false);
+        for c in markup.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' => in_tag = false,
+                _ if !in_tag => text.push(c),
+                _ => {}
+            }
+        }
+        text
+    }
+
+    /// Returns the opening tag and the text of every wrapped line.
+    fn lines(markup: &str) -> Vec<(String, String)> {
+        markup
+            .split("<tspan x=")
+            .skip(1)
+            .map(|line| {
+                let (tag, line) = line.split_once('>').unwrap_or((line, ""));
+                (format!("<tspan x={tag}>"), text(line))
+            })
+            .collect()
+    }
+
+    /// Returns a function that measures the text of a placeholder as if every glyph and line
+    /// were a square of the same size, so the tests do not depend on the installed fonts.
+    fn measure(glyph_size: f32) -> impl Fn(&PlaceholderValueMap) -> Result<Option<Rect>, RenderingError> {
+        move |values| {
+            let value = &values["text"];
+
+            // Shrunk text is wrapped in a tspan element that scales its font size.
+            let scale: f32 = value
+                .strip_prefix("<tspan font-size=\"")
+                .and_then(|value| value.split_once("em\""))
+                .and_then(|(scale, _)| scale.parse().ok())
+                .unwrap_or(1.0);
+
+            // Wrapped text starts every line with a tspan element that positions it.
+            let mut lines: Vec<usize> = lines(value).iter().map(|(_, line)| line.chars().count()).collect();
+            if lines.is_empty() {
+                lines.push(glyphs(value));
+            }
+
+            let size = glyph_size * scale;
+            let width = lines.iter().copied().max().unwrap_or(0) as f32 * size;
+            Ok(Rect::from_xywh(0.0, 0.0, width.max(1.0), lines.len() as f32 * size))
+        }
+    }
+
+    fn fit_glyphs(text: &str, glyph_size: f32, height: Option<f32>, overflow: SchemaTextOverflow) -> String {
+        let bounds = SchemaTextBounds {
+            x: 0.0,
+            y: 0.0,
+            width: 100.0,
+            height,
+            overflow,
+        };
+
+        let values = HashMap::from([("text".to_string(), text.to_string())]);
+        let values = TextFitter::new(&bounds, measure(glyph_size))
+            .fit(values, "text", &[TextSpan::plain(text)])
+            .expect("Fitting does not fail.");
+        values["text"].clone()
+    }
+
+    fn fit(text: &str, height: Option<f32>, overflow: SchemaTextOverflow) -> String {
+        fit_glyphs(text, GLYPH_SIZE, height, overflow)
+    }
+
+    fn wrap(text: &str, height: Option<f32>, max_lines: Option<u32>) -> String {
+        let overflow = SchemaTextOverflow::Wrap {
+            max_lines,
+            line_height: None,
+            align: SchemaTextAlign::Left,
+        };
+        fit(text, height, overflow)
+    }
+
+    /// Wraps text of narrow glyphs and returns the opening tag and text of every line.
+    fn wrap_narrow(
+        text: &str,
+        height: Option<f32>,
+        max_lines: Option<u32>,
+        line_height: Option<f32>,
+        align: SchemaTextAlign,
+    ) -> Vec<(String, String)> {
+        let overflow = SchemaTextOverflow::Wrap { max_lines, line_height, align };
+        lines(&fit_glyphs(text, NARROW_GLYPH_SIZE, height, overflow))
+    }
+
+    /// Returns the text of every line of wrapped text of narrow glyphs.
+    fn wrapped_lines(text: &str, height: Option<f32>, max_lines: Option<u32>) -> Vec<String> {
+        wrap_narrow(text, height, max_lines, None, SchemaTextAlign::Left)
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect()
+    }
+
+    #[test]
+    fn shrinks_text_to_fit() {
+        let value = fit("W", None, SchemaTextOverflow::Shrink);
+        assert!(value.starts_with("<tspan font-size=\"0.2em\">"));
+        assert_eq!(glyphs(&value), 1);
+    }
+
+    #[test]
+    fn truncates_text_that_does_not_fit_smallest_scale() {
+        let value = fit(&"W".repeat(30), None, SchemaTextOverflow::Shrink);
+        assert!(value.starts_with(&format!("<tspan font-size=\"{MIN_SHRINK_SCALE}em\">")));
+        assert_eq!(glyphs(&value), 2);
+        assert!(value.contains(ELLIPSIS));
+    }
+
+    #[test]
+    fn wraps_glyph_wider_than_bounds() {
+        assert_eq!(wrap("W", None, None).matches("<tspan x=").count(), 1);
+        assert_eq!(wrap("W", Some(100.0), None).matches("<tspan x=").count(), 1);
+    }
+
+    #[test]
+    fn wraps_words_of_glyphs_wider_than_bounds() {
+        assert_eq!(wrap("ab cd", None, None).matches("<tspan x=").count(), 4);
+        assert_eq!(wrap("ab cd", None, Some(2)).matches("<tspan x=").count(), 2);
+        assert_eq!(wrap("ab cd", Some(100.0), None).matches("<tspan x=").count(), 1);
+    }
+
+    #[test]
+    fn truncates_lines_that_do_not_fit_height() {
+        let value = wrap("ab cd", Some(1000.0), None);
+        assert_eq!(value.matches("<tspan x=").count(), 2);
+        assert!(value.ends_with(&format!("{ELLIPSIS}</tspan></tspan>")));
+    }
+
+    #[test]
+    fn wraps_words_onto_lines() {
+        assert_eq!(wrapped_lines("the quick brown fox jumps", None, None), ["the quick", "brown fox", "jumps"]);
+        assert_eq!(wrapped_lines("fits", None, None), ["fits"]);
+        assert_eq!(wrapped_lines("a bcdefghijklmno", None, None), ["a", "bcdefghijk", "lmno"]);
+    }
+
+    #[test]
+    fn wraps_paragraphs() {
+        let lines = wrap_narrow("one\n\ntwo three four\n", None, None, None, SchemaTextAlign::Left);
+        let texts: Vec<&str> = lines.iter().map(|(_, line)| line.as_str()).collect();
+        assert_eq!(texts, ["one", "two three", "four"]);
+
+        // The empty line is not emitted but moves the following line down.
+        assert!(!lines[0].0.contains("dy="));
+        assert!(lines[1].0.contains(" dy=\"2.4em\""), "{}", lines[1].0);
+        assert!(lines[2].0.contains(" dy=\"1.2em\""), "{}", lines[2].0);
+    }
+
+    #[test]
+    fn truncates_lines_over_max_lines() {
+        let text = "the quick brown fox jumps over";
+        assert_eq!(wrapped_lines(text, None, Some(2)), ["the quick", "brown fox…"]);
+        assert_eq!(wrapped_lines(text, None, Some(1)), ["the quick…"]);
+        assert_eq!(wrapped_lines("the quick brownish", None, Some(1)), ["the quick…"]);
+        assert_eq!(wrapped_lines(text, Some(25.0), None), ["the quick", "brown fox…"]);
+    }
+
+    #[test]
+    fn spaces_lines_by_line_height() {
+        let lines = wrap_narrow("one two three\n\nfour", None, None, Some(24.0), SchemaTextAlign::Left);
+        let tags: Vec<&str> = lines.iter().map(|(tag, _)| tag.as_str()).collect();
+
+        assert_eq!(
+            tags,
+            [
+                "<tspan x=\"0\" text-anchor=\"start\">",
+                "<tspan x=\"0\" text-anchor=\"start\" dy=\"24\">",
+                "<tspan x=\"0\" text-anchor=\"start\" dy=\"48\">",
+            ]
+        );
+    }
+
+    #[test]
+    fn aligns_lines() {
+        let center = wrap_narrow("the quick brown", None, None, None, SchemaTextAlign::Center);
+        assert_eq!(center.len(), 2);
+        for (tag, _) in &center {
+            assert!(tag.starts_with("<tspan x=\"50\" text-anchor=\"middle\""), "{tag}");
+        }
+
+        let right = wrap_narrow("the quick brown", None, None, None, SchemaTextAlign::Right);
+        assert_eq!(right.len(), 2);
+        for (tag, _) in &right {
+            assert!(tag.starts_with("<tspan x=\"100\" text-anchor=\"end\""), "{tag}");
+        }
+    }
+
+    #[test]
+    fn ellipsizes_overflowing_text() {
+        let value = fit_glyphs("hello world", NARROW_GLYPH_SIZE, None, SchemaTextOverflow::Ellipsis);
+
+        assert!(value.ends_with(&format!("{ELLIPSIS}</tspan>")), "{value}");
+        assert_eq!(text(&value), "hello wor…");
+        assert!(glyphs(&value) as f32 * NARROW_GLYPH_SIZE <= 100.0);
+    }
+
+    #[test]
+    fn leaves_fitting_text_unchanged() {
+        assert_eq!(fit_glyphs("hello", NARROW_GLYPH_SIZE, None, SchemaTextOverflow::Ellipsis), "hello");
+        assert_eq!(fit_glyphs("hello", NARROW_GLYPH_SIZE, None, SchemaTextOverflow::Shrink), "hello");
+    }
+}
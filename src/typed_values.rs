@@ -0,0 +1,384 @@
+use std::{
+    fmt::Display,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::Deserialize;
+
+use crate::schema::SchemaValueFormat;
+
+/// The suffixes of compact numbers and the values they stand for, from largest to smallest.
+const COMPACT_SUFFIXES: [(f64, &str); 4] = [(1e12, "T"), (1e9, "B"), (1e6, "M"), (1e3, "K")];
+
+/// The maximum number of decimal places of floats whose format has none. Trailing zeros are
+/// removed, so floating point artifacts such as `0.30000000000000004` are shown as `0.3`.
+const MAX_FLOAT_DECIMALS: usize = 6;
+
+/// The units of relative times and their lengths in seconds, from longest to shortest.
+const RELATIVE_UNITS: [(u64, &str); 6] = [
+    (365 * 86400, "year"),
+    (30 * 86400, "month"),
+    (7 * 86400, "week"),
+    (86400, "day"),
+    (3600, "hour"),
+    (60, "minute"),
+];
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+/// A placeholder value that is converted to text by the renderer, such as `{"integer": 12345}`.
+pub enum TypedValue {
+    /// A whole number.
+    Integer(i64),
+    /// A decimal number.
+    Float(f64),
+    /// The ratio of two numbers, such as kills to deaths. A denominator of 0 is treated as 1.
+    Ratio(f64, f64),
+    /// A time in seconds since the Unix epoch.
+    Timestamp(i64),
+}
+
+impl TypedValue {
+    /// Converts the value to text.
+    ///
+    /// # Arguments
+    ///
+    /// - `format` - The format of the value.
+    /// - `now` - The time that relative timestamps are relative to.
+    pub fn format(&self, format: &SchemaValueFormat, now: SystemTime) -> String {
+        match *self {
+            TypedValue::Integer(n) => format_integer(n, format),
+            TypedValue::Float(n) => format_number(n, format, None),
+            TypedValue::Ratio(..) => format_number(self.number(), format, Some(2)),
+            TypedValue::Timestamp(seconds) if format.relative => format_relative_time(seconds, now),
+            TypedValue::Timestamp(seconds) => format_date(seconds),
+        }
+    }
+
+    /// Returns the value as a number. Ratios are divided and timestamps are seconds. Whole numbers
+    /// beyond 2^53 are rounded to the nearest float, so they are only exact when formatted.
+    pub fn number(&self) -> f64 {
+        match *self {
+            TypedValue::Integer(n) | TypedValue::Timestamp(n) => n as f64,
+            TypedValue::Float(n) => n,
+            TypedValue::Ratio(numerator, denominator) => {
+                numerator / if denominator == 0.0 { 1.0 } else { denominator }
+            }
+        }
+    }
+}
+
+impl Display for TypedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // The default format shows timestamps as dates, so the reference time is not used.
+        write!(f, "{}", self.format(&SchemaValueFormat::default(), UNIX_EPOCH))
+    }
+}
+
+/// Converts a whole number to text. Unless the number is abbreviated, its digits are used as is,
+/// so numbers beyond the precision of floats are not rounded.
+///
+/// # Arguments
+///
+/// - `value` - The number to convert.
+/// - `format` - The format of the number.
+fn format_integer(value: i64, format: &SchemaValueFormat) -> String {
+    if format.compact {
+        return format_number(value as f64, format, Some(0));
+    }
+
+    let value = if format.percent { i128::from(value) * 100 } else { i128::from(value) };
+    let percent = if format.percent { "%" } else { "" };
+
+    let mut number = value.to_string();
+    if let Some(decimals) = format.decimals.filter(|decimals| *decimals > 0) {
+        number = format!("{number}.{}", "0".repeat(decimals as usize));
+    }
+
+    format!("{}{percent}", localize_number(&number, format))
+}
+
+/// Converts a number to text.
+///
+/// # Arguments
+///
+/// - `value` - The number to convert.
+/// - `format` - The format of the number.
+/// - `default_decimals` - Optional: The number of decimal places if the format has none.
+///   Without one, up to [`MAX_FLOAT_DECIMALS`] decimal places are shown.
+fn format_number(value: f64, format: &SchemaValueFormat, default_decimals: Option<u32>) -> String {
+    let value = if format.percent { value * 100.0 } else { value };
+    let percent = if format.percent { "%" } else { "" };
+
+    let (number, suffix) = if format.compact {
+        compact_number(value, format.decimals.unwrap_or(1) as usize)
+    } else {
+        let number = match format.decimals.or(default_decimals) {
+            Some(decimals) => format!("{value:.*}", decimals as usize),
+            None => trim_decimals(format!("{value:.MAX_FLOAT_DECIMALS$}")),
+        };
+        (number, "")
+    };
+
+    format!("{}{suffix}{percent}", localize_number(&number, format))
+}
+
+/// Abbreviates a number with the suffix of the largest value it is at least one of. Trailing
+/// zeros of the decimal places are removed.
+///
+/// # Arguments
+///
+/// - `value` - The number to abbreviate.
+/// - `decimals` - The maximum number of decimal places.
+fn compact_number(value: f64, decimals: usize) -> (String, &'static str) {
+    let rounded = |value: f64| format!("{value:.decimals$}").parse::<f64>().unwrap_or(value);
+
+    let largest = COMPACT_SUFFIXES
+        .iter()
+        .position(|(size, _)| value.abs() >= *size)
+        .unwrap_or(COMPACT_SUFFIXES.len());
+
+    // Rounding can carry a number over to the next suffix, such as 999,950 to 1000K.
+    let size = COMPACT_SUFFIXES.get(largest).map_or(1.0, |(size, _)| *size);
+    let index = if largest > 0 && rounded(value.abs() / size) >= 1000.0 { largest - 1 } else { largest };
+
+    let (value, suffix) = COMPACT_SUFFIXES
+        .get(index)
+        .map_or((value, ""), |(size, suffix)| (value / size, *suffix));
+
+    (trim_decimals(format!("{value:.decimals$}")), suffix)
+}
+
+/// Removes trailing zeros of the decimal places of a number, and the decimal point if none are
+/// left.
+///
+/// # Arguments
+///
+/// - `number` - The number, with `.` before the decimal places.
+fn trim_decimals(number: String) -> String {
+    match number.contains('.') {
+        true => number.trim_end_matches('0').trim_end_matches('.').to_string(),
+        false => number,
+    }
+}
+
+/// Replaces the separators of a formatted number with those of a format.
+///
+/// # Arguments
+///
+/// - `number` - The number, with an optional sign and `.` before the decimal places.
+/// - `format` - The format with the separators.
+fn localize_number(number: &str, format: &SchemaValueFormat) -> String {
+    let (sign, number) = match number.strip_prefix('-') {
+        // Numbers that round to zero are not negative.
+        Some(number) if number.chars().any(|c| c.is_ascii_digit() && c != '0') => ("-", number),
+        Some(number) => ("", number),
+        None => ("", number),
+    };
+
+    let (integer, fraction) = match number.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (number, None),
+    };
+
+    let digits: Vec<char> = integer.chars().collect();
+    let mut localized = sign.to_string();
+
+    for (i, digit) in digits.iter().enumerate() {
+        if let Some(separator) = &format.thousands_separator
+            && i > 0
+            && (digits.len() - i).is_multiple_of(3)
+        {
+            localized.push_str(separator);
+        }
+
+        localized.push(*digit);
+    }
+
+    if let Some(fraction) = fraction {
+        localized.push_str(format.decimal_separator.as_deref().unwrap_or("."));
+        localized.push_str(fraction);
+    }
+
+    localized
+}
+
+/// Describes a timestamp relative to a reference time, such as "3 days ago" or "in 2 hours".
+///
+/// # Arguments
+///
+/// - `seconds` - The timestamp in seconds since the Unix epoch.
+/// - `now` - The reference time.
+fn format_relative_time(seconds: i64, now: SystemTime) -> String {
+    let now = match now.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    };
+
+    let difference = now.saturating_sub(seconds);
+    let distance = difference.unsigned_abs();
+
+    let Some((length, unit)) = RELATIVE_UNITS.iter().find(|(length, _)| distance >= *length) else {
+        return "just now".to_string();
+    };
+
+    let count = distance / length;
+    let plural = if count == 1 { "" } else { "s" };
+
+    if difference > 0 {
+        format!("{count} {unit}{plural} ago")
+    } else {
+        format!("in {count} {unit}{plural}")
+    }
+}
+
+/// Formats the UTC date of a timestamp as `YYYY-MM-DD`.
+///
+/// # Arguments
+///
+/// - `seconds` - The timestamp in seconds since the Unix epoch.
+fn format_date(seconds: i64) -> String {
+    // Converts days since the epoch to a date in the proleptic Gregorian calendar, using eras of
+    // 400 years that start on March 1st.
+    let days = seconds.div_euclid(86400) + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(value: TypedValue, format: SchemaValueFormat) -> String {
+        value.format(&format, UNIX_EPOCH)
+    }
+
+    #[test]
+    fn formats_integers_beyond_float_precision() {
+        let separated = || SchemaValueFormat {
+            thousands_separator: Some(",".to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(format(TypedValue::Integer(9_007_199_254_740_993), Default::default()), "9007199254740993");
+        assert_eq!(format(TypedValue::Integer(i64::MAX), separated()), "9,223,372,036,854,775,807");
+        assert_eq!(format(TypedValue::Integer(i64::MIN), separated()), "-9,223,372,036,854,775,808");
+    }
+
+    #[test]
+    fn formats_integers_with_decimals_and_percent() {
+        let percent = SchemaValueFormat {
+            decimals: Some(1),
+            decimal_separator: Some(",".to_string()),
+            percent: true,
+            ..Default::default()
+        };
+
+        assert_eq!(format(TypedValue::Integer(-12), percent), "-1200,0%");
+        assert_eq!(format(TypedValue::Integer(0), Default::default()), "0");
+    }
+
+    #[test]
+    fn formats_compact_integers() {
+        let compact = SchemaValueFormat {
+            compact: true,
+            ..Default::default()
+        };
+
+        assert_eq!(format(TypedValue::Integer(1_260_000), compact.clone()), "1.3M");
+        assert_eq!(format(TypedValue::Integer(999_950), compact), "1M");
+    }
+
+    #[test]
+    fn formats_floats_without_artifacts() {
+        assert_eq!(format(TypedValue::Float(0.1 + 0.2), Default::default()), "0.3");
+        assert_eq!(format(TypedValue::Float(1.0 / 3.0), Default::default()), "0.333333");
+        assert_eq!(format(TypedValue::Float(2.0), Default::default()), "2");
+        assert_eq!(format(TypedValue::Float(-0.0000001), Default::default()), "0");
+        assert_eq!(TypedValue::Float(0.1 + 0.2).to_string(), "0.3");
+    }
+
+    #[test]
+    fn formats_float_percent() {
+        let percent = SchemaValueFormat {
+            percent: true,
+            ..Default::default()
+        };
+
+        assert_eq!(format(TypedValue::Float(0.534), percent.clone()), "53.4%");
+        assert_eq!(
+            format(TypedValue::Float(0.53449), SchemaValueFormat { decimals: Some(1), ..percent }),
+            "53.4%"
+        );
+    }
+
+    #[test]
+    fn formats_ratios() {
+        assert_eq!(format(TypedValue::Ratio(10.0, 4.0), Default::default()), "2.50");
+        assert_eq!(format(TypedValue::Ratio(7.0, 0.0), Default::default()), "7.00");
+        assert_eq!(
+            format(TypedValue::Ratio(1.0, 3.0), SchemaValueFormat { decimals: Some(3), ..Default::default() }),
+            "0.333"
+        );
+    }
+
+    #[test]
+    fn formats_compact_decimals() {
+        let compact = SchemaValueFormat {
+            compact: true,
+            ..Default::default()
+        };
+
+        assert_eq!(format(TypedValue::Float(1200.0), compact.clone()), "1.2K");
+        assert_eq!(format(TypedValue::Integer(1200), compact.clone()), "1.2K");
+        assert_eq!(format(TypedValue::Float(3_400_000.0), compact.clone()), "3.4M");
+        assert_eq!(format(TypedValue::Float(999.0), compact.clone()), "999");
+        assert_eq!(
+            format(TypedValue::Float(1234.0), SchemaValueFormat { decimals: Some(2), ..compact }),
+            "1.23K"
+        );
+    }
+
+    #[test]
+    fn formats_relative_times() {
+        // The reference time is passed as the renderer does with `Renderer::with_reference_time`.
+        let reference_time = UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+        let relative = SchemaValueFormat {
+            relative: true,
+            ..Default::default()
+        };
+        let relative_to = |seconds: i64| TypedValue::Timestamp(seconds).format(&relative, reference_time);
+
+        assert_eq!(relative_to(1_700_000_000 - 3 * 86400), "3 days ago");
+        assert_eq!(relative_to(1_700_000_000 + 2 * 3600), "in 2 hours");
+        assert_eq!(relative_to(1_700_000_000 - 30), "just now");
+        assert_eq!(relative_to(1_700_000_000 + 30), "just now");
+        assert_eq!(relative_to(1_700_000_000 - 86400), "1 day ago");
+
+        assert_eq!(format_relative_time(1_700_000_000 - 3 * 86400, reference_time), "3 days ago");
+    }
+
+    #[test]
+    fn formats_dates() {
+        assert_eq!(format_date(0), "1970-01-01");
+        assert_eq!(format_date(1_700_000_000), "2023-11-14");
+        // 2024-02-29 is a leap day, and the last second before and the first second after it.
+        assert_eq!(format_date(1_709_164_800), "2024-02-29");
+        assert_eq!(format_date(1_709_164_799), "2024-02-28");
+        assert_eq!(format_date(1_709_251_200), "2024-03-01");
+        // 2000 is a leap year but 1900 is not.
+        assert_eq!(format_date(951_782_400), "2000-02-29");
+        assert_eq!(format_date(-2_203_891_200), "1900-03-01");
+        assert_eq!(format_date(-1), "1969-12-31");
+        assert_eq!(TypedValue::Timestamp(1_709_164_800).to_string(), "2024-02-29");
+    }
+}